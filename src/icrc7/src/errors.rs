@@ -9,6 +9,8 @@ pub enum TransferError {
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
+    // soulbound token or ticket already redeemed
+    NonTransferable,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -21,6 +23,14 @@ pub enum BurnError {
     GenericBatchError { error_code: u128, message: String },
 }
 
+#[derive(CandidType, Clone)]
+pub enum RedeemError {
+    Unauthorized,
+    NonExistingTokenId,
+    AlreadyRedeemed { redeemed_at: u64 },
+    GenericError { error_code: u128, message: String },
+}
+
 #[derive(CandidType, Clone)]
 pub enum MintError {
     SupplyCapReached,
@@ -96,6 +106,8 @@ pub enum TransferFromError {
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
+    // soulbound token or ticket already redeemed
+    NonTransferable,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
\ No newline at end of file
@@ -187,6 +187,7 @@ impl ArchiveLedgerInfo {
                     block_type: "7update".into(),
                     url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-7/ICRC-7.md".into(),
                 },
+                redeem_block_type(),
                 BlockType {
                     block_type: "37appr".into(),
                     url: "https://github.com/dfinity/ICRC/blob/main/ICRCs/ICRC-37/ICRC-37.md"
@@ -217,6 +218,14 @@ impl ArchiveLedgerInfo {
     }
 }
 
+// redemption of a ticket at the event door, not part of the ICRC-7 standard
+pub fn redeem_block_type() -> BlockType {
+    BlockType {
+        block_type: "7redeem".into(),
+        url: "https://github.com/dfinity/ICRC-1/blob/main/standards/ICRC-3/README.md".into(),
+    }
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct ArchiveSetting {
     pub archive_controllers: Option<Option<Vec<Principal>>>,
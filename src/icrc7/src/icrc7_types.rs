@@ -9,7 +9,7 @@ use icrc_ledger_types::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    errors::{BurnError, InsertTransactionError, MintError, TransferError},
+    errors::{BurnError, InsertTransactionError, MintError, RedeemError, TransferError},
     icrc37_types::InitApprovalsArg,
     icrc3_types::{Block, InitArchiveArg},
 };
@@ -61,12 +61,17 @@ pub enum TransactionType {
         from: Account,
         to: Option<Account>,
     },
+    Redeem {
+        tid: u128,
+        from: Account,
+        spender: Account,
+    },
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Transaction {
     pub ts: u64,
-    pub op: String, // "7mint" | "7burn" | "7xfer" | "7update" | "37appr" | "37appr_coll | "37revoke" | "37revoke_coll" | "37xfer"
+    pub op: String, // "7mint" | "7burn" | "7xfer" | "7update" | "37appr" | "37appr_coll | "37revoke" | "37revoke_coll" | "37xfer" | "7redeem"
     pub tid: u128,
     pub from: Option<Account>,
     pub to: Option<Account>,
@@ -244,6 +249,25 @@ impl Transaction {
         }
     }
 
+    // `from` is the holder of the ticket, `spender` the door staff who checked it in
+    pub fn redeem(
+        now_sec: u64,
+        tid: u128,
+        from: Account,
+        spender: Account,
+        memo: Option<Vec<u8>>,
+    ) -> Self {
+        Transaction {
+            ts: now_sec,
+            op: "7redeem".to_string(),
+            tid,
+            from: Some(from),
+            spender: Some(spender),
+            memo,
+            ..Default::default()
+        }
+    }
+
     pub fn new(_txn_id: u128, txn_type: TransactionType, at: u64, memo: Option<Vec<u8>>) -> Self {
         let transaction = match &txn_type {
             TransactionType::Transfer { tid, from, to } => {
@@ -300,6 +324,9 @@ impl Transaction {
                 spender.clone(),
                 memo,
             ),
+            TransactionType::Redeem { tid, from, spender } => {
+                Self::redeem(at, *tid, *from, *spender, memo)
+            }
         };
         return transaction;
     }
@@ -330,6 +357,8 @@ pub type TransferResult = Result<u128, TransferError>;
 
 pub type Icrc7TokenMetadata = BTreeMap<String, Value>;
 
+pub type Icrc7CollectionMetadata = BTreeMap<String, Value>;
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct MintArg {
     pub from_subaccount: Option<Subaccount>,
@@ -341,7 +370,9 @@ pub struct MintArg {
     pub token_name: Option<String>,
     pub token_description: Option<String>,
     pub token_logo: Option<String>,
-    pub token_privilege_code: Option<u8>
+    pub token_privilege_code: Option<u8>,
+    // soulbound tokens can only be transferred by the minting authority
+    pub token_soulbound: Option<bool>,
 }
 
 pub type MintResult = Result<u128, MintError>;
@@ -355,6 +386,31 @@ pub struct BurnArg {
 
 pub type BurnResult = Result<u128, BurnError>;
 
+#[derive(CandidType, Deserialize, Clone)]
+pub struct RedeemArg {
+    pub token_id: u128,
+    // the account expected to hold the token at the door
+    pub holder: Account,
+    pub memo: Option<Vec<u8>>,
+}
+
+pub type RedeemResult = Result<u128, RedeemError>;
+
+// owners of the tickets still to be checked in at the time of the snapshot, sorted by token id
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct OwnersSnapshot {
+    pub taken_at: u64,
+    pub owners: Vec<(u128, Account)>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedOwnersSnapshot {
+    pub snapshot: OwnersSnapshot,
+    pub certificate: Option<Vec<u8>>,
+    // witness of the "owners_snapshot" leaf, the sha256 of the snapshot
+    pub hash_tree: Vec<u8>,
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct InitArg {
     pub minting_account: Option<Account>,
@@ -371,6 +427,7 @@ pub struct InitArg {
     pub icrc7_atomic_batch_transfers: Option<bool>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
+    pub icrc7_royalties: Option<u16>,
     pub approval_init: Option<InitApprovalsArg>,
     pub archive_init: Option<InitArchiveArg>,
 }
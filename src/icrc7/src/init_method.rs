@@ -3,7 +3,8 @@ use ic_stable_structures::{writer::Writer, Memory};
 use icrc_ledger_types::icrc1::account::Account;
 
 use crate::{
-    icrc37_types::LedgerInfo, icrc3_types::ArchiveLedgerInfo, icrc7_types::InitArg, state::STATE,
+    icrc37_types::LedgerInfo,
+    icrc3_types::{redeem_block_type, ArchiveLedgerInfo}, icrc7_types::InitArg, state::{certify_owners_snapshot, STATE},
     utils::account_transformer,
 };
 
@@ -65,6 +66,7 @@ pub fn init(arg: InitArg) {
         s.icrc7_atomic_batch_transfers = arg.icrc7_atomic_batch_transfers;
         s.tx_window = arg.tx_window;
         s.permitted_drift = arg.permitted_drift;
+        s.icrc7_royalties = arg.icrc7_royalties;
         s.approval_ledger_info = ledger_info;
         s.archive_ledger_info = archive_ledger_info;
     })
@@ -105,4 +107,22 @@ fn post_upgrade() {
     // Deserialize and set the state.
     let state = ciborium::de::from_reader(&*state_bytes).expect("failed to decode state");
     STATE.with(|s| *s.borrow_mut() = state);
+
+    // collections deployed before ticket redemption do not list its block type yet
+    STATE.with(|s| {
+        let supported_blocks = &mut s.borrow_mut().archive_ledger_info.supported_blocks;
+        if !supported_blocks
+            .iter()
+            .any(|block| block.block_type == redeem_block_type().block_type)
+        {
+            supported_blocks.push(redeem_block_type());
+        }
+    });
+
+    // the certified tree is not kept across upgrades
+    STATE.with(|s| {
+        if let Some(ref snapshot) = s.borrow().owners_snapshot {
+            certify_owners_snapshot(snapshot);
+        }
+    });
 }
@@ -2,7 +2,7 @@ use candid::Principal;
 use ic_cdk_macros::query;
 use icrc_ledger_types::icrc1::account::Account;
 
-use crate::{icrc7_types::Transaction, state::STATE, Icrc7TokenMetadata, Standard, guards::authenticated_guard};
+use crate::{icrc7_types::{CertifiedOwnersSnapshot, Transaction}, state::STATE, Icrc7CollectionMetadata, Icrc7TokenMetadata, Standard, guards::authenticated_guard};
 
 #[query(guard = "authenticated_guard")]
 pub fn icrc7_symbol() -> String {
@@ -69,6 +69,22 @@ pub fn icrc7_atomic_batch_transfers() -> Option<bool> {
     STATE.with(|s| s.borrow().icrc7_atomic_batch_transfers())
 }
 
+#[query(guard = "authenticated_guard")]
+pub fn icrc7_royalties() -> Option<u16> {
+    STATE.with(|s| s.borrow().icrc7_royalties())
+}
+
+#[query(guard = "authenticated_guard")]
+pub fn icrc7_collection_metadata() -> Icrc7CollectionMetadata {
+    STATE.with(|s| s.borrow().icrc7_collection_metadata())
+}
+
+// not guarded: the certificate is only returned to queries called directly by the scanners
+#[query]
+pub fn icrc7_owners_snapshot() -> Option<CertifiedOwnersSnapshot> {
+    STATE.with(|s| s.borrow().icrc7_owners_snapshot())
+}
+
 #[query(guard = "authenticated_guard")]
 pub fn icrc7_owner_of(ids: Vec<u128>) -> Vec<Option<Account>> {
     STATE.with(|s| s.borrow().icrc7_owner_of(&ids))
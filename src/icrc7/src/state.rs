@@ -4,7 +4,7 @@ use crate::{
     archive::create_archive_canister,
     errors::{
         ApproveCollectionError, ApproveTokenError, BurnError, InsertTransactionError, MintError,
-        RevokeCollectionApprovalError, RevokeTokenApprovalError, TransferError, TransferFromError,
+        RedeemError, RevokeCollectionApprovalError, RevokeTokenApprovalError, TransferError, TransferFromError,
     },
     icrc37_types::{
         ApproveCollectionArg, ApproveCollectionResult, ApproveTokenArg, ApproveTokenResult,
@@ -19,14 +19,15 @@ use crate::{
         Tip, TransactionRange,
     },
     icrc7_types::{
-        BurnResult, Icrc7TokenMetadata, MintArg, MintResult, Transaction, TransactionType,
+        BurnResult, CertifiedOwnersSnapshot, Icrc7CollectionMetadata, Icrc7TokenMetadata, MintArg,
+        MintResult, OwnersSnapshot, RedeemArg, RedeemResult, Transaction, TransactionType,
         TransferArg, TransferResult,
     },
     memory::{
         get_collection_approvals_memory, get_log_memory, get_token_approvals_memory,
         get_token_map_memory, Memory,
     },
-    utils::{account_transformer, burn_account, hash_icrc_value},
+    utils::{account_transformer, burn_account, hash_icrc_value, hash_owners_snapshot},
     BurnArg, SyncReceipt, TRANSACTION_TRANSFER_FROM_OP, TRANSACTION_TRANSFER_OP,
 };
 use candid::{CandidType, Decode, Encode, Principal};
@@ -52,7 +53,11 @@ pub struct Icrc7Token {
     pub token_description: Option<String>,
     pub token_logo: Option<String>,
     pub token_owner: Account,
-    pub token_privilege_code: Option<u8>
+    pub token_privilege_code: Option<u8>,
+    // set once the ticket has been checked in at the event door
+    pub redeemed_at: Option<u64>,
+    // soulbound tokens can only be moved by the minting authority, to distribute them
+    pub soulbound: Option<bool>,
 }
 
 impl Storable for Icrc7Token {
@@ -82,7 +87,8 @@ impl Icrc7Token {
         token_description: Option<String>,
         token_logo: Option<String>,
         token_owner: Account,
-        token_privilege_code: Option<u8>
+        token_privilege_code: Option<u8>,
+        soulbound: Option<bool>,
     ) -> Self {
         Self {
             token_id,
@@ -91,9 +97,16 @@ impl Icrc7Token {
             token_owner,
             token_description,
             token_privilege_code,
+            redeemed_at: None,
+            soulbound,
         }
     }
 
+    // the tickets can not be resold once redeemed
+    fn is_transferable(&self) -> bool {
+        self.redeemed_at.is_none() && !self.soulbound.unwrap_or(false)
+    }
+
     fn transfer(&mut self, to: Account) {
         self.token_owner = to;
     }
@@ -111,6 +124,13 @@ impl Icrc7Token {
         if let Some(ref privilege_code) = self.token_privilege_code {
             metadata.insert("PrivilegeCode".into(), Value::Nat(privilege_code.clone().into()));
         }
+        if let Some(redeemed_at) = self.redeemed_at {
+            metadata.insert("RedeemedAt".into(), Value::Nat(redeemed_at.into()));
+        }
+        metadata.insert(
+            "Transferable".into(),
+            Value::Nat((self.is_transferable() as u8).into()),
+        );
         metadata
     }
 
@@ -136,6 +156,9 @@ pub struct State {
     pub icrc7_atomic_batch_transfers: Option<bool>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
+    // royalties on secondary sales in basis points, paid to the organizer of the collection
+    #[serde(default)]
+    pub icrc7_royalties: Option<u16>,
     #[serde(skip, default = "get_token_map_memory")]
     pub tokens: StableBTreeMap<u128, Icrc7Token, Memory>,
     pub txn_count: u128,
@@ -153,6 +176,9 @@ pub struct State {
     pub archive_log_canister: Option<Principal>,
     pub sync_pending_txn_ids: Option<Vec<u128>>,
     pub archive_txn_count: u128,
+    // last certified snapshot of the owners, used by the door staff to check in tickets offline
+    #[serde(default)]
+    pub owners_snapshot: Option<OwnersSnapshot>,
 }
 
 impl Default for State {
@@ -173,6 +199,7 @@ impl Default for State {
             icrc7_atomic_batch_transfers: None,
             tx_window: None,
             permitted_drift: None,
+            icrc7_royalties: None,
             tokens: get_token_map_memory(),
             txn_count: 0,
             next_token_id: 0,
@@ -184,6 +211,7 @@ impl Default for State {
             token_approvals: get_token_approvals_memory(),
             collection_approvals: get_collection_approvals_memory(),
             archive_ledger_info: ArchiveLedgerInfo::default(),
+            owners_snapshot: None,
         }
     }
 }
@@ -249,6 +277,30 @@ impl State {
         self.icrc7_atomic_batch_transfers
     }
 
+    pub fn icrc7_royalties(&self) -> Option<u16> {
+        self.icrc7_royalties
+    }
+
+    pub fn icrc7_collection_metadata(&self) -> Icrc7CollectionMetadata {
+        let mut metadata = BTreeMap::<String, Value>::new();
+        metadata.insert("icrc7:symbol".into(), Value::Text(self.icrc7_symbol.clone()));
+        metadata.insert("icrc7:name".into(), Value::Text(self.icrc7_name.clone()));
+        if let Some(ref description) = self.icrc7_description {
+            metadata.insert("icrc7:description".into(), Value::Text(description.clone()));
+        }
+        if let Some(ref logo) = self.icrc7_logo {
+            metadata.insert("icrc7:logo".into(), Value::Text(logo.clone()));
+        }
+        metadata.insert("icrc7:total_supply".into(), Value::Nat(self.icrc7_total_supply.into()));
+        if let Some(supply_cap) = self.icrc7_supply_cap {
+            metadata.insert("icrc7:supply_cap".into(), Value::Nat(supply_cap.into()));
+        }
+        if let Some(royalties) = self.icrc7_royalties {
+            metadata.insert("icrc7:royalties".into(), Value::Nat((royalties as u64).into()));
+        }
+        metadata
+    }
+
     pub fn icrc7_owner_of(&self, token_id: &[u128]) -> Vec<Option<Account>> {
         let mut res = vec![None; token_id.len()];
         for (index, id) in token_id.iter().enumerate() {
@@ -298,6 +350,16 @@ impl State {
         return true;
     }
 
+    pub fn set_collection_metadata(&mut self, description: Option<String>, logo: Option<String>) -> bool {
+        if description.is_some() {
+            self.icrc7_description = description;
+        }
+        if logo.is_some() {
+            self.icrc7_logo = logo;
+        }
+        true
+    }
+
     fn txn_deduplication_check(
         &self,
         allowed_past_time: &u64,
@@ -517,9 +579,23 @@ impl State {
         if token.token_owner != *caller {
             return Err(TransferError::Unauthorized);
         }
+        if !self.can_transfer(&token) {
+            return Err(TransferError::NonTransferable);
+        }
         Ok(())
     }
 
+    // soulbound tokens are still distributed by the minting authority
+    fn can_transfer(&self, token: &Icrc7Token) -> bool {
+        if token.is_transferable() {
+            return true;
+        }
+        token.redeemed_at.is_none()
+            && self
+                .minting_authority
+                .is_some_and(|authority| authority.owner == token.token_owner.owner)
+    }
+
     pub fn icrc7_transfer(
         &mut self,
         caller: &Principal,
@@ -654,7 +730,8 @@ impl State {
             arg.token_description.clone(),
             arg.token_logo,
             arg.to.clone(),
-            arg.token_privilege_code.clone()
+            arg.token_privilege_code.clone(),
+            arg.token_soulbound,
         );
         let token_metadata = token.token_metadata();
         self.tokens.insert(arg.token_id, token);
@@ -719,7 +796,7 @@ impl State {
                 subaccount: arg.from_subaccount,
             });
             if let Err(e) = self.mock_burn(&caller, arg) {
-                txn_results.insert(index, Some(Err(e)))
+                txn_results[index] = Some(Err(e));
             }
         }
         if let Some(true) = self.icrc7_atomic_batch_transfers {
@@ -747,6 +824,8 @@ impl State {
             }
             let mut token = self.tokens.get(&arg.token_id).unwrap();
             token.burn(burn_address.clone());
+            self.token_approvals_clean(&arg.token_id);
+            self.tokens.insert(arg.token_id, token);
             let tid = self.log_transaction(
                 TransactionType::Burn {
                     tid: arg.token_id,
@@ -756,11 +835,60 @@ impl State {
                 ic_cdk::api::time(),
                 arg.memo.clone(),
             );
-            txn_results.insert(index, Some(Ok(tid)))
+            txn_results[index] = Some(Ok(tid));
         }
         txn_results
     }
 
+    pub fn redeem(&mut self, caller: &Principal, arg: RedeemArg) -> RedeemResult {
+        if *caller == Principal::anonymous() {
+            return Err(RedeemError::GenericError {
+                error_code: 100,
+                message: "Anonymous Identity".into(),
+            });
+        }
+        if let Some(ref memo) = arg.memo {
+            if memo.len() as u32
+                > self
+                    .icrc7_max_memo_size
+                    .unwrap_or(State::DEFAULT_MAX_MEMO_SIZE)
+            {
+                return Err(RedeemError::GenericError {
+                    error_code: 3,
+                    message: "Exceeds Max Memo Length".into(),
+                });
+            }
+        }
+        let mut token = match self.tokens.get(&arg.token_id) {
+            None => return Err(RedeemError::NonExistingTokenId),
+            Some(token) => token,
+        };
+        let holder = account_transformer(arg.holder);
+        if token.token_owner == burn_account() || token.token_owner != holder {
+            return Err(RedeemError::Unauthorized);
+        }
+        if let Some(redeemed_at) = token.redeemed_at {
+            return Err(RedeemError::AlreadyRedeemed { redeemed_at });
+        }
+        let now = ic_cdk::api::time();
+        token.redeemed_at = Some(now);
+        self.tokens.insert(arg.token_id, token);
+        let spender = account_transformer(Account {
+            owner: *caller,
+            subaccount: None,
+        });
+        let tid = self.log_transaction(
+            TransactionType::Redeem {
+                tid: arg.token_id,
+                from: holder,
+                spender,
+            },
+            now,
+            arg.memo,
+        );
+        Ok(tid)
+    }
+
     fn mock_approve(
         &self,
         caller: &Account,
@@ -1287,6 +1415,11 @@ impl State {
                 });
             }
         };
+        if let Some(ref token) = self.tokens.get(&arg.token_id) {
+            if !self.can_transfer(token) {
+                return Err(TransferFromError::NonTransferable);
+            }
+        }
         Ok(())
     }
 
@@ -1593,6 +1726,43 @@ impl State {
         });
     }
 
+    pub fn snapshot_owners(&mut self) -> u64 {
+        let burn_address = burn_account();
+        let owners = self
+            .tokens
+            .iter()
+            .filter(|(_, token)| token.token_owner != burn_address && token.redeemed_at.is_none())
+            .map(|(id, token)| (id, token.token_owner))
+            .collect();
+        let snapshot = OwnersSnapshot {
+            taken_at: ic_cdk::api::time(),
+            owners,
+        };
+        certify_owners_snapshot(&snapshot);
+        let taken_at = snapshot.taken_at;
+        self.owners_snapshot = Some(snapshot);
+        taken_at
+    }
+
+    pub fn icrc7_owners_snapshot(&self) -> Option<CertifiedOwnersSnapshot> {
+        let snapshot = self.owners_snapshot.clone()?;
+        let witness = TREE.with(|tree| {
+            let tree = tree.borrow();
+            let mut witness = vec![];
+            let mut witness_serializer = serde_cbor::Serializer::new(&mut witness);
+            let _ = witness_serializer.self_describe();
+            tree.witness(b"owners_snapshot")
+                .serialize(&mut witness_serializer)
+                .unwrap();
+            witness
+        });
+        Some(CertifiedOwnersSnapshot {
+            snapshot,
+            certificate: ic_cdk::api::data_certificate(),
+            hash_tree: witness,
+        })
+    }
+
     pub fn icrc3_get_blocks(&self, args: GetBlocksArgs) -> GetBlocksResult {
         let local_ledger_length = self.txn_ledger.len() as u128;
         let local_first_index = self.archive_ledger_info.first_index;
@@ -1807,6 +1977,14 @@ thread_local! {
     pub static TIMER_IDS: RefCell<Vec<TimerId>> = RefCell::new(Vec::new());
 }
 
+pub fn certify_owners_snapshot(snapshot: &OwnersSnapshot) {
+    TREE.with(|tree| {
+        let mut tree = tree.borrow_mut();
+        tree.insert("owners_snapshot", hash_owners_snapshot(snapshot));
+        ic_cdk::api::set_certified_data(&tree.root_hash());
+    });
+}
+
 pub async fn call_sync_logs(
     archive_log_canister: Principal,
     txn_logs: Vec<Transaction>,
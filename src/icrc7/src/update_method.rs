@@ -2,7 +2,8 @@ use candid::Principal;
 use ic_cdk_macros::update;
 
 use crate::{
-    guards::owner_guard, state::STATE, BurnArg, BurnResult, MintArg, MintResult, TransferArg,
+    guards::owner_guard, state::STATE, BurnArg, BurnResult, MintArg, MintResult, RedeemArg,
+    RedeemResult, TransferArg,
     TransferResult, guards::authenticated_guard
 };
 use icrc_ledger_types::icrc1::account::Account;
@@ -33,11 +34,33 @@ pub fn icrc7_transfer(args: Vec<TransferArg>, caller: Option<Principal>) -> Vec<
 }
 
 #[update(guard = "authenticated_guard")]
-pub fn icrc7_burn(args: Vec<BurnArg>) -> Vec<Option<BurnResult>> {
-    let caller = ic_cdk::caller();
+pub fn icrc7_burn(args: Vec<BurnArg>, caller: Option<Principal>) -> Vec<Option<BurnResult>> {
+    let caller = match caller {
+        Some(x) => x,
+        None => ic_cdk::caller(),
+    };
     STATE.with(|s| s.borrow_mut().burn(&caller, args))
 }
 
+#[update(guard = "authenticated_guard")]
+pub fn icrc7_redeem(arg: RedeemArg, caller: Option<Principal>) -> RedeemResult {
+    let caller = match caller {
+        Some(x) => x,
+        None => ic_cdk::caller(),
+    };
+    STATE.with(|s| s.borrow_mut().redeem(&caller, arg))
+}
+
+#[update(guard = "authenticated_guard")]
+pub fn icrc7_snapshot_owners() -> u64 {
+    STATE.with(|s| s.borrow_mut().snapshot_owners())
+}
+
+#[update(guard = "authenticated_guard")]
+pub fn icrc7_set_collection_metadata(description: Option<String>, logo: Option<String>) -> bool {
+    STATE.with(|s| s.borrow_mut().set_collection_metadata(description, logo))
+}
+
 #[update(guard = "owner_guard")]
 pub fn icrc7_set_minting_authority(minting_account: Account) -> bool {
     STATE.with(|s| s.borrow_mut().minting_authority = Some(minting_account));
@@ -1,7 +1,10 @@
 use candid::Principal;
+use sha2::{Digest, Sha256};
 use icrc_ledger_types::icrc::generic_value::{self, Value};
 use icrc_ledger_types::icrc1::account::{Account, Subaccount, DEFAULT_SUBACCOUNT};
 
+use crate::icrc7_types::OwnersSnapshot;
+
 pub fn account_transformer(account: Account) -> Account {
     if let Some(_) = account.subaccount {
         account
@@ -37,3 +40,35 @@ pub fn burn_account() -> Account {
 pub fn hash_icrc_value(value: &Value) -> generic_value::Hash {
     return value.hash();
 }
+
+// the encoding is replicated by the scanners verifying the snapshot offline
+pub fn hash_owners_snapshot(snapshot: &OwnersSnapshot) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(snapshot.taken_at.to_be_bytes());
+    for (token_id, owner) in snapshot.owners.iter() {
+        let principal = owner.owner.as_slice();
+        hasher.update(token_id.to_be_bytes());
+        hasher.update([principal.len() as u8]);
+        hasher.update(principal);
+        hasher.update(owner.subaccount.unwrap_or(*DEFAULT_SUBACCOUNT));
+    }
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_hash_matches_the_scanners() {
+        // the same snapshot is hashed by `hash_owners_snapshot` of the ticket_verifier crate
+        let snapshot = OwnersSnapshot {
+            taken_at: 5,
+            owners: vec![
+                (1, Account { owner: Principal::from_slice(&[1, 2, 3]), subaccount: None }),
+                (2, Account { owner: Principal::anonymous(), subaccount: Some([7; 32]) }),
+            ],
+        };
+        assert_eq!(hex::encode(hash_owners_snapshot(&snapshot)), "298dcd31fbdf96e8818824f2e6ce55fc7258314c586f9689e0f842cb6f84c81d");
+    }
+}
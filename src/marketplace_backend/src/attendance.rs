@@ -0,0 +1,215 @@
+use std::collections::HashSet;
+
+use candid::{Nat, Principal};
+
+use crate::collection::{mint, owner_of, redeemed_holders};
+use crate::common::structures::{AttendanceArg, AttendanceJob, AttendanceReward, CanisterArg, CollectionInfo, CollectionStatus, NftMetadata, OwnersDoubleKey, Role};
+use crate::editing::MAX_METADATA_SIZE;
+use crate::factory::{create_collection_canister, encode_init_arg, install_collection_code};
+use crate::memory::{get_attendance_job, get_attendance_rewards, get_collection, insert_attendance_job, insert_attendance_reward, insert_collection_record};
+use crate::roles::has_role;
+use crate::search::MAX_DESCRIPTION_LENGTH;
+use crate::tiers::tkn_ids;
+
+/// Time after which a call that has not saved any progress, like one stopped by a trap, no longer blocks the next ones
+pub const REWARD_LOCK_TIMEOUT: u64 = 10 * 60 * 1_000_000_000;
+
+///
+/// Gets a collection whose event is ended, checking that the caller is an owner
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+/// ## Returns
+/// * `Ok`: info of the collection
+/// * `Error`: collection not existing, not owned by the caller, cancelled or not expired yet
+///
+fn get_ended(collection_id: Principal, caller: Principal) -> Result<CollectionInfo, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Owner) {
+        return Err("only the owners can reward the attendees".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if collection.expire_date > ic_cdk::api::time() {
+        return Err("the collection is not expired yet".to_string());
+    }
+    Ok(collection)
+}
+
+///
+/// Checks the metadata of a proof-of-attendance collection
+///
+/// ## Arguments
+/// * `arg` - metadata of the collection
+///
+fn validate_arg(arg: &AttendanceArg) -> Result<(), String> {
+    if arg.name.is_empty() || arg.symbol.is_empty() {
+        return Err("the name and the symbol of the collection are required".to_string());
+    }
+    if arg.description.len() > MAX_DESCRIPTION_LENGTH {
+        return Err(format!("the description can not be longer than {} bytes", MAX_DESCRIPTION_LENGTH));
+    }
+    if arg.description.len() + arg.logo.len() > MAX_METADATA_SIZE {
+        return Err(format!("the description and the logo can not be larger than {} bytes", MAX_METADATA_SIZE));
+    }
+    Ok(())
+}
+
+///
+/// Creates the proof-of-attendance collection of an event and mints a token to the holder of every ticket checked in.
+/// Every step is saved, so a call stopped by an error is resumed by the next one and
+/// the tickets checked in after a call are rewarded by the following ones.
+/// The metadata passed the first time is kept by the next calls.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+/// * `caller` - principal calling the function
+/// * `arg` - metadata of the proof-of-attendance collection
+///
+/// ## Returns
+/// * `Ok`: canister id of the proof-of-attendance collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn reward(collection_id: Principal, caller: Principal, arg: AttendanceArg) -> Result<Principal, String> {
+    let collection = get_ended(collection_id, caller)?;
+    let now = ic_cdk::api::time();
+    let mut job = match get_attendance_job(&collection_id) {
+        Some(x) if x.in_progress && now < x.updated_at.saturating_add(REWARD_LOCK_TIMEOUT) => return Err("the attendees are being rewarded".to_string()),
+        Some(x) => x,
+        None => {
+            validate_arg(&arg)?;
+            AttendanceJob {
+                arg,
+                canister_id: None,
+                installed: false,
+                in_progress: false,
+                minted: 0,
+                minting: None,
+                error: None,
+                updated_at: now,
+            }
+        },
+    };
+    job.in_progress = true;
+    job.updated_at = now;
+    insert_attendance_job(collection_id, job.clone());
+
+    let res = run(collection_id, &collection, &mut job).await;
+
+    job.in_progress = false;
+    job.error = res.as_ref().err().cloned();
+    job.updated_at = ic_cdk::api::time();
+    insert_attendance_job(collection_id, job);
+    res
+}
+
+///
+/// Runs the steps of the minting of a proof-of-attendance collection not completed yet
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+/// * `collection` - info of the collection of the event
+/// * `job` - state of the minting, saved after every step
+///
+async fn run(collection_id: Principal, collection: &CollectionInfo, job: &mut AttendanceJob) -> Result<Principal, String> {
+    let canister_id = match job.canister_id {
+        Some(x) => x,
+        None => {
+            let canister_id = create_collection_canister(collection.owner).await?;
+            job.canister_id = Some(canister_id);
+            job.updated_at = ic_cdk::api::time();
+            insert_attendance_job(collection_id, job.clone());
+            canister_id
+        },
+    };
+
+    if !job.installed {
+        let init_arg = encode_init_arg(CanisterArg {
+            icrc7_symbol: job.arg.symbol.clone(),
+            icrc7_name: job.arg.name.clone(),
+            icrc7_description: Some(job.arg.description.clone()),
+            icrc7_logo: Some(job.arg.logo.clone()),
+            icrc7_supply_cap: collection.nfts.iter().map(|x| x.nft.quantity as u128).sum(),
+            icrc7_max_query_batch_size: None,
+            icrc7_max_update_batch_size: None,
+            icrc7_max_take_value: None,
+            icrc7_default_take_value: None,
+            icrc7_max_memo_size: None,
+            icrc7_atomic_batch_transfers: None,
+            tx_window: None,
+            permitted_drift: None,
+        }, collection.owner, 0);
+        install_collection_code(canister_id, init_arg).await?;
+        job.installed = true;
+        job.updated_at = ic_cdk::api::time();
+        insert_attendance_job(collection_id, job.clone());
+
+        // the record is read again, it may have changed during the installation
+        if let Some(mut event) = get_collection(&collection_id) {
+            event.attendance_collection = Some(canister_id);
+            insert_collection_record(collection_id, event);
+        }
+    }
+
+    let rewarded = get_attendance_rewards(collection_id)
+        .into_iter()
+        .map(|(tkn_id, _)| tkn_id)
+        .collect::<HashSet<u64>>();
+    let tkn_ids = tkn_ids(collection_id, collection)
+        .into_iter()
+        .filter(|x| !rewarded.contains(x))
+        .collect::<Vec<u64>>();
+    let mut attendees = redeemed_holders(collection_id, &tkn_ids).await?;
+    // the ticket whose minting has been interrupted is retried first, with the same token id
+    attendees.sort_by_key(|x| Some(x.0) != job.minting);
+
+    // the tokens are soulbound, their ids are sequential because the tickets can be checked in in any order
+    let metadata = NftMetadata {
+        token_name: job.arg.name.clone(),
+        token_privilege_code: 0,
+        token_description: job.arg.description.clone(),
+        quantity: attendees.len() as u64,
+        token_logo: job.arg.logo.clone(),
+        price: Nat::from(0u32),
+        sale_start: None,
+        sale_end: None,
+        soulbound: Some(true),
+    };
+    for (ticket, holder) in attendees {
+        let tkn_id = job.minted + 1;
+        job.minting = Some(ticket);
+        job.updated_at = ic_cdk::api::time();
+        insert_attendance_job(collection_id, job.clone());
+
+        if let Err(e) = mint(canister_id, tkn_id, &metadata, collection.owner, holder).await {
+            // the token may have been minted by a call whose result has been lost
+            if owner_of(canister_id, tkn_id).await != Ok(holder) {
+                return Err(e);
+            }
+        }
+        insert_attendance_reward(OwnersDoubleKey { collection_id, tkn_id: ticket }, AttendanceReward { holder, tkn_id });
+        job.minted += 1;
+        job.minting = None;
+        insert_attendance_job(collection_id, job.clone());
+    }
+    Ok(canister_id)
+}
+
+///
+/// Tickets of an event rewarded with a proof of attendance, with the holders rewarded and the tokens minted
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+///
+pub fn rewards(collection_id: Principal) -> Result<Vec<(u64, AttendanceReward)>, String> {
+    if get_collection(&collection_id).is_none() {
+        return Err("collection does not exists".to_string());
+    }
+    Ok(get_attendance_rewards(collection_id))
+}
@@ -0,0 +1,248 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::collection::transfer_token;
+use crate::common::guards::LockGuard;
+use crate::common::structures::{Auction, AuctionStatus, Bid, CollectionStatus, OwnersDoubleKey, PayoutKind, PurchaseRecord};
+use crate::ledger::{escrow_account, fee, ledger_of, transfer_from};
+use crate::memory::{get_auction, get_auctions, get_collections, insert_auction_record, insert_nft_record, insert_purchase_record, update_purchase_record};
+use crate::payouts::{net_of_fee, pay_from_escrow, settle_sale};
+
+/// Interval between two checks of the ended auctions
+pub const AUCTIONS_INTERVAL: Duration = Duration::from_secs(60);
+
+thread_local! {
+    static SETTLING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Starts the timer that assigns the NFTs of the ended auctions to the highest bidders
+///
+pub fn start_auctions_timer() {
+    ic_cdk_timers::set_timer_interval(AUCTIONS_INTERVAL, || ic_cdk::spawn(settle_ended_auctions()));
+}
+
+///
+/// Returns if an NFT is on auction
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+///
+pub fn on_auction(key: &OwnersDoubleKey) -> bool {
+    get_auction(key).is_some_and(|x| x.status == AuctionStatus::Active)
+}
+
+///
+/// Minimum amount of the next bid of an auction
+///
+/// ## Arguments
+/// * `auction` - auction of the NFT
+///
+pub fn min_bid(auction: &Auction) -> Nat {
+    match &auction.highest_bid {
+        Some(bid) => bid.amount.clone() + auction.min_increment.clone(),
+        None => auction.start_price.clone(),
+    }
+}
+
+///
+/// Puts an NFT on auction, the NFT is removed from the fixed price sales until the end of the auction
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `seller` - owner of the NFT
+/// * `start_price` - minimum amount of the first bid
+/// * `min_increment` - minimum difference between a bid and the previous one
+/// * `end_time` - time in nanoseconds when the auction ends
+/// * `expire_date` - expire date of the collection
+/// * `ledger_fee` - fee of a transaction on the ledger of the collection
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn start(key: OwnersDoubleKey, seller: Principal, start_price: Nat, min_increment: Nat, end_time: u64, expire_date: u64, ledger_fee: u128) -> Result<String, String> {
+    // the winning bid pays the fee of the transfers to the seller
+    if start_price <= ledger_fee {
+        return Err(format!("start price must be greater than the fee of the ledger: {}", ledger_fee));
+    }
+    if min_increment == 0u32 {
+        return Err("minimum increment must be greater than 0".to_string());
+    }
+    if end_time <= ic_cdk::api::time() || end_time > expire_date {
+        return Err("end time cannot be in the past or after the expire date of the collection".to_string());
+    }
+    if on_auction(&key) {
+        return Err("NFT already on auction".to_string());
+    }
+
+    insert_nft_record(key.collection_id, key.tkn_id, seller, None, false);
+    insert_auction_record(key, Auction {
+        seller,
+        start_price,
+        min_increment,
+        end_time,
+        highest_bid: None,
+        status: AuctionStatus::Active,
+        purchase_id: None,
+    });
+    Ok(format!("NFT with token id: {} on auction until {}", key.tkn_id, end_time))
+}
+
+///
+/// Places a bid on an auction, the amount is moved from the bidder to the escrow of the collection with ICRC-2
+/// and the previous highest bidder is refunded.
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `bidder` - principal placing the bid
+/// * `amount` - amount of the bid
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn bid(key: OwnersDoubleKey, bidder: Principal, amount: Nat) -> Result<String, String> {
+    let collection_id = key.collection_id;
+    let auction = match get_auction(&key) {
+        Some(x) if x.status == AuctionStatus::Active => x,
+        _ => return Err("NFT not on auction".to_string()),
+    };
+    if auction.end_time <= ic_cdk::api::time() {
+        return Err("auction ended".to_string());
+    }
+    if auction.seller == bidder {
+        return Err("the seller cannot bid on his auction".to_string());
+    }
+    if amount < min_bid(&auction) {
+        return Err(format!("the bid needs to be at least {}", min_bid(&auction)));
+    }
+    if u128::try_from(&amount.0).is_err() {
+        return Err("bid too big".to_string());
+    }
+
+    let ledger = ledger_of(&collection_id);
+    let payment_block = transfer_from(ledger, amount.clone(), Account::from(bidder), escrow_account(&collection_id))
+        .await
+        .map_err(|e| format!("Error in transferring the tokens from the caller to the escrow: {}", e))?;
+    let ledger_fee = fee(ledger).await.unwrap_or_default();
+
+    // the auction could have been changed by another bid while the payment was in progress
+    let mut auction = get_auction(&key).expect("auction does not exists");
+    let cancelled = get_collections().get(&collection_id).is_some_and(|x| x.status == CollectionStatus::Cancelled);
+    if cancelled || auction.status != AuctionStatus::Active || auction.end_time <= ic_cdk::api::time() || amount < min_bid(&auction) {
+        return match pay_from_escrow(collection_id, bidder, net_of_fee(&amount, ledger_fee), PayoutKind::Refund, None).await {
+            Ok(block) => Err(format!("the bid is no longer valid, the payment has been refunded on block {}", block)),
+            Err(payout_id) => Err(format!("the bid is no longer valid, a refund will be sent automatically with payout id: {}", payout_id)),
+        };
+    }
+
+    let previous = auction.highest_bid.replace(Bid {
+        bidder,
+        amount: amount.clone(),
+        payment_block: payment_block.clone(),
+        timestamp: ic_cdk::api::time(),
+    });
+    insert_auction_record(key, auction);
+
+    if let Some(previous) = previous {
+        let _ = pay_from_escrow(collection_id, previous.bidder, net_of_fee(&previous.amount, ledger_fee), PayoutKind::Refund, None).await;
+    }
+    Ok(format!("bid of {} placed on NFT with token id: {}, payment block: {}", amount, key.tkn_id, payment_block))
+}
+
+///
+/// Assigns the NFT of an ended auction to the highest bidder and settles the payment like a purchase,
+/// if the NFT cannot be transferred the highest bid is refunded
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `auction` - ended auction
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+async fn settle_auction(key: OwnersDoubleKey, mut auction: Auction, ledger_fee: u128) {
+    let collection_id = key.collection_id;
+    let bid = match auction.highest_bid.clone() {
+        Some(x) => x,
+        None => {
+            auction.status = AuctionStatus::Unsold;
+            insert_auction_record(key, auction);
+            return;
+        },
+    };
+
+    let mut purchase = PurchaseRecord {
+        collection_id,
+        tkn_id: key.tkn_id,
+        buyer: bid.bidder,
+        seller: auction.seller,
+        amount: bid.amount.clone(),
+        timestamp: ic_cdk::api::time(),
+        payment_block: bid.payment_block,
+        settlement_block: None,
+        refund_block: None,
+        royalty_block: None,
+        platform_fee_block: None,
+        escrowed: false,
+        refunded: false,
+    };
+    let purchase_id = insert_purchase_record(purchase.clone());
+
+    // the status is saved before transferring so that the auction cannot be settled twice
+    auction.status = AuctionStatus::Settled;
+    auction.purchase_id = Some(purchase_id);
+    insert_auction_record(key, auction.clone());
+
+    let cancelled = get_collections().get(&collection_id).is_some_and(|x| x.status == CollectionStatus::Cancelled);
+    let transfer = if cancelled {
+        Err("collection cancelled".to_string())
+    } else {
+        transfer_token(collection_id, key.tkn_id, auction.seller, bid.bidder).await.map_err(|e| format!("{:?}", e))
+    };
+
+    match transfer {
+        Ok(_) => {
+            insert_nft_record(collection_id, key.tkn_id, bid.bidder, None, false);
+            let price = u128::try_from(&bid.amount.0).unwrap_or(u128::MAX);
+            settle_sale(purchase_id, purchase, price, ledger_fee).await;
+        },
+        Err(e) => {
+            ic_cdk::println!("auction of NFT {} of collection {} failed: {}", key.tkn_id, collection_id, e);
+            auction.status = AuctionStatus::Failed;
+            insert_auction_record(key, auction);
+            if let Ok(block) = pay_from_escrow(collection_id, bid.bidder, net_of_fee(&bid.amount, ledger_fee), PayoutKind::Refund, Some(purchase_id)).await {
+                purchase.refund_block = Some(block);
+                update_purchase_record(purchase_id, purchase);
+            }
+        },
+    }
+}
+
+///
+/// Settles all the auctions ended, the auctions of cancelled collections are closed immediately
+///
+pub async fn settle_ended_auctions() {
+    let _guard = match LockGuard::acquire(&SETTLING) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let now = ic_cdk::api::time();
+    let collections = get_collections();
+    let mut due = get_auctions()
+        .into_iter()
+        .filter(|x| x.1.status == AuctionStatus::Active
+            && (x.1.end_time <= now || collections.get(&x.0.collection_id).is_some_and(|c| c.status == CollectionStatus::Cancelled)))
+        .collect::<Vec<(OwnersDoubleKey, Auction)>>();
+    due.sort_by_key(|x| x.1.end_time);
+
+    for (key, auction) in due {
+        match fee(ledger_of(&key.collection_id)).await {
+            Ok(ledger_fee) => settle_auction(key, auction, ledger_fee).await,
+            Err(e) => ic_cdk::println!("unable to settle the auction of NFT {} of collection {}: {}", key.tkn_id, key.collection_id, e),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use std::cell::Cell;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::collection::burn;
+use crate::common::guards::LockGuard;
+use crate::common::structures::{CollectionStatus, OwnersDoubleKey, PayoutKind, PurchaseRecord, Role};
+use crate::crowdfunding::refund_escrowed_purchases;
+use crate::ledger::{escrow_account, fee, ledger_of, transfer_from};
+use crate::memory::{get_collections, get_nfts, get_purchase, get_purchases, insert_collection_record, remove_nft_record, update_purchase_record};
+use crate::payouts::{net_of_fee, pay_from_escrow};
+use crate::roles::has_role;
+
+thread_local! {
+    static CANCELLING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Returns if a purchase has already been refunded, if its refund is in progress or in the payouts queue
+///
+/// ## Arguments
+/// * `purchase` - receipt of the purchase
+///
+pub fn is_refunded(purchase: &PurchaseRecord) -> bool {
+    purchase.refunded || purchase.refund_block.is_some()
+}
+
+///
+/// Primary sales of a collection already paid to the organizer that need to be refunded on cancellation,
+/// only the purchases whose NFT has not been burned yet are returned
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `organizer` - owner of the collection
+///
+fn settled_purchases(collection_id: Principal, organizer: Principal) -> Vec<(u64, PurchaseRecord)> {
+    let nfts = get_nfts();
+    let mut res = get_purchases()
+        .into_iter()
+        .filter(|x| x.1.collection_id == collection_id
+            && x.1.seller == organizer
+            && !x.1.escrowed
+            && nfts.contains_key(&OwnersDoubleKey { collection_id, tkn_id: x.1.tkn_id })
+            && !is_refunded(&x.1))
+        .collect::<Vec<(u64, PurchaseRecord)>>();
+    res.sort_by_key(|x| x.0);
+    res
+}
+
+///
+/// Cancels a collection, only the owners can do it.
+/// The sales are stopped, every buyer is refunded the price paid on his purchase and all the NFTs are burned.
+/// The payments still held in escrow are refunded from the escrow of the collection,
+/// the payments already received by the organizer are taken back from the caller with ICRC-2,
+/// so before calling this function the caller needs to approve their sum + the transaction fee to the backend canister.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal requesting the cancellation
+///
+/// ## Returns
+/// * `Ok`: description of the refunds
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn cancel(collection_id: Principal, caller: Principal) -> Result<String, String> {
+    let mut collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Owner) {
+        return Err("only the owners can cancel the collection".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection already cancelled".to_string());
+    }
+
+    // the collection is marked as cancelled before any call so that no other sale can happen
+    collection.status = CollectionStatus::Cancelled;
+    insert_collection_record(collection_id, collection.clone());
+
+    let to_refund = settled_purchases(collection_id, collection.owner)
+        .iter()
+        .fold(Nat::from(0u32), |acc, x| acc + x.1.amount.clone());
+    if to_refund > 0u32 {
+        if let Err(e) = transfer_from(collection.token.ledger, to_refund.clone(), Account::from(caller), escrow_account(&collection_id)).await {
+            collection.status = CollectionStatus::Active;
+            insert_collection_record(collection_id, collection);
+            return Err(format!("unable to collect {} tokens from the organizer to refund the buyers: {}", to_refund, e));
+        }
+    }
+
+    refund_cancelled(collection_id).await
+}
+
+///
+/// Refunds the buyers of a cancelled collection and burns all his NFTs,
+/// the NFTs that could not be burned are retried by the timer
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+/// ## Returns
+/// * `Ok`: description of the refunds
+/// * `Error`: String with the errors of the NFTs that could not be burned
+///
+pub async fn refund_cancelled(collection_id: Principal) -> Result<String, String> {
+    let ledger_fee = fee(ledger_of(&collection_id)).await?;
+    let organizer = match get_collections().get(&collection_id) {
+        Some(x) => x.owner,
+        None => return Err("collection does not exists".to_string()),
+    };
+
+    let mut errors = Vec::new();
+    let escrowed = match refund_escrowed_purchases(collection_id).await {
+        Ok(x) => x,
+        Err(e) => {
+            errors.push(e);
+            0
+        },
+    };
+
+    let mut settled = 0;
+    for (purchase_id, _) in settled_purchases(collection_id, organizer) {
+        // the purchase is read again, it may have been refunded by a concurrent run since the list was taken
+        let mut purchase = match get_purchase(purchase_id) {
+            Some(x) if !is_refunded(&x) => x,
+            _ => continue,
+        };
+        let holder = match get_nfts().get(&OwnersDoubleKey { collection_id, tkn_id: purchase.tkn_id }) {
+            Some(x) => x.owner,
+            None => continue,
+        };
+        // the refund is claimed before any call so that a concurrent run cannot refund it twice
+        purchase.refunded = true;
+        update_purchase_record(purchase_id, purchase.clone());
+        if let Err(e) = burn(collection_id, purchase.tkn_id, holder).await {
+            if let Some(mut purchase) = get_purchase(purchase_id) {
+                purchase.refunded = false;
+                update_purchase_record(purchase_id, purchase);
+            }
+            errors.push(e);
+            continue;
+        }
+        remove_nft_record(collection_id, purchase.tkn_id);
+        settled += 1;
+
+        let amount = net_of_fee(&purchase.amount, ledger_fee);
+        if amount == 0u32 {
+            continue;
+        }
+        if let Ok(block) = pay_from_escrow(collection_id, holder, amount, PayoutKind::Refund, Some(purchase_id)).await {
+            purchase.refund_block = Some(block);
+            update_purchase_record(purchase_id, purchase);
+        }
+    }
+
+    // the NFTs not sold are burned on behalf of the organizer
+    let unsold = get_nfts()
+        .into_iter()
+        .filter(|x| x.0.collection_id == collection_id && x.1.owner == organizer)
+        .map(|x| x.0.tkn_id)
+        .collect::<Vec<u64>>();
+    for tkn_id in unsold {
+        match burn(collection_id, tkn_id, organizer).await {
+            Ok(_) => remove_nft_record(collection_id, tkn_id),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join(", "));
+    }
+    Ok(format!("{} purchases refunded from escrow, {} purchases refunded by the organizer", escrowed, settled))
+}
+
+///
+/// Completes the cancellations whose NFTs could not be all burned
+///
+pub async fn complete_cancellations() {
+    let _guard = match LockGuard::acquire(&CANCELLING) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let nfts = get_nfts();
+    let pending = get_collections()
+        .into_iter()
+        .filter(|x| x.1.status == CollectionStatus::Cancelled && nfts.keys().any(|k| k.collection_id == x.0))
+        .map(|x| x.0)
+        .collect::<Vec<Principal>>();
+
+    for collection_id in pending {
+        if let Err(e) = refund_cancelled(collection_id).await {
+            ic_cdk::println!("cancellation of collection {} not completed: {}", collection_id, e);
+        }
+    }
+}
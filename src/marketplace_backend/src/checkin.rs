@@ -0,0 +1,197 @@
+use std::time::Duration;
+
+use candid::Principal;
+use hmac::{Hmac, Mac};
+use ic_cdk::api::management_canister::main::raw_rand;
+use sha2::Sha256;
+
+use crate::collection::{owner_of, redeem, redeemed_at, snapshot_owners};
+use crate::common::structures::{CheckInChallenge, CollectionInfo, CollectionStatus, Role, SignedCheckIn};
+use crate::memory::{get_challenge_key, get_collection, set_challenge_key};
+use crate::roles::has_role;
+use ticket_verifier::{challenge_message, verify_check_in, CHALLENGE_TTL, IC_ROOT_KEY};
+
+/// Maximum number of check-ins synced in a single call
+pub const MAX_SYNC_BATCH: usize = 100;
+
+///
+/// Generates the secret key of the authentication codes of the challenges, if it has not been generated yet
+///
+pub fn start_challenge_key() {
+    if get_challenge_key().is_empty() {
+        ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(async {
+            match raw_rand().await {
+                Ok((key,)) if get_challenge_key().is_empty() => set_challenge_key(key),
+                Ok(_) => {},
+                Err((_, e)) => ic_cdk::println!("failed to generate the challenge key: {}", e),
+            }
+        }));
+    }
+}
+
+///
+/// Authentication code of a challenge, computed with the secret key of the backend
+///
+/// ## Arguments
+/// * `challenge` - challenge issued by the backend
+///
+/// ## Returns
+/// * `Ok`: HMAC-SHA256 of the bytes of the challenge
+/// * `Error`: the secret key has not been generated yet
+///
+fn challenge_mac(challenge: &CheckInChallenge) -> Result<Hmac<Sha256>, String> {
+    let key = get_challenge_key();
+    if key.is_empty() {
+        return Err("check-in challenges are not available yet, retry later".to_string());
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).map_err(|e| e.to_string())?;
+    mac.update(&challenge_message(challenge));
+    Ok(mac)
+}
+
+///
+/// Gets a collection checking that the caller can check in its tickets
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+fn get_checkable(collection_id: Principal, caller: Principal) -> Result<CollectionInfo, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::DoorStaff) {
+        return Err("only the owners and the door staff can check in tickets".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    Ok(collection)
+}
+
+///
+/// Checks in a ticket at the event door, redeeming the token on the collection canister.
+/// Only the owners and the door staff of the collection can check in tickets, a ticket can be checked in only once.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - door staff checking in the ticket
+/// * `tkn_id` - id of the token
+/// * `holder` - principal presenting the ticket
+///
+/// ## Returns
+/// * `Ok`: id of the redeem transaction on the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn check_in(collection_id: Principal, caller: Principal, tkn_id: u64, holder: Principal) -> Result<u128, String> {
+    get_checkable(collection_id, caller)?;
+
+    redeem(collection_id, tkn_id, holder, caller).await
+}
+
+///
+/// Returns when a ticket has been checked in
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+///
+/// ## Returns
+/// * `Ok`: timestamp of the check-in, None if the ticket has not been checked in
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn redemption(collection_id: Principal, tkn_id: u64) -> Result<Option<u64>, String> {
+    if get_collection(&collection_id).is_none() {
+        return Err("collection does not exists".to_string());
+    }
+    redeemed_at(collection_id, tkn_id).await
+}
+
+///
+/// Issues a short-lived challenge to the holder of a ticket, the holder signs it and shows it at the door
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - holder of the ticket
+/// * `tkn_id` - id of the token
+///
+/// ## Returns
+/// * `Ok`: challenge to sign, bound to the collection, the ticket and the holder
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn challenge(collection_id: Principal, caller: Principal, tkn_id: u64) -> Result<CheckInChallenge, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if owner_of(collection_id, tkn_id).await? != caller {
+        return Err("ticket not owned by the caller".to_string());
+    }
+    if redeemed_at(collection_id, tkn_id).await?.is_some() {
+        return Err("ticket already checked in".to_string());
+    }
+
+    let now = ic_cdk::api::time();
+    let mut challenge = CheckInChallenge {
+        collection_id,
+        tkn_id,
+        holder: caller,
+        nonce: now,
+        expires_at: now + CHALLENGE_TTL,
+        mac: Vec::new(),
+    };
+    challenge.mac = challenge_mac(&challenge)?.finalize().into_bytes().to_vec();
+    Ok(challenge)
+}
+
+///
+/// Takes a certified snapshot of the owners of the tickets, downloaded by the scanners to check in tickets offline
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - door staff taking the snapshot
+///
+/// ## Returns
+/// * `Ok`: timestamp of the snapshot
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn snapshot(collection_id: Principal, caller: Principal) -> Result<u64, String> {
+    get_checkable(collection_id, caller)?;
+
+    snapshot_owners(collection_id).await
+}
+
+///
+/// Redeems the tickets checked in offline, verifying that the challenges have been issued by the backend and signed by their holders
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - door staff syncing the check-ins
+/// * `check_ins` - challenges signed by the holders and scanned at the door
+///
+/// ## Returns
+/// * `Ok`: the result of each check-in, with the id of the redeem transaction on the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn sync(collection_id: Principal, caller: Principal, check_ins: Vec<SignedCheckIn>) -> Result<Vec<Result<u128, String>>, String> {
+    get_checkable(collection_id, caller)?;
+    if check_ins.len() > MAX_SYNC_BATCH {
+        return Err(format!("at most {} check-ins can be synced at once", MAX_SYNC_BATCH));
+    }
+
+    let mut res = Vec::with_capacity(check_ins.len());
+    for check_in in check_ins.iter() {
+        let verified = verify_check_in(collection_id, check_in, &IC_ROOT_KEY, ic_cdk::api::time())
+            .and_then(|_| challenge_mac(&check_in.challenge))
+            .and_then(|mac| mac.verify_slice(&check_in.challenge.mac).map_err(|_| "challenge not issued by the backend".to_string()));
+        res.push(match verified {
+            Ok(()) => redeem(collection_id, check_in.challenge.tkn_id, check_in.challenge.holder, caller).await,
+            Err(e) => Err(e),
+        });
+    }
+    Ok(res)
+}
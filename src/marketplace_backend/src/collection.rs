@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use candid::Principal;
+use icrc_ledger_types::icrc::generic_value::Value;
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::common::structures::{BurnArg, BurnError, Errors, IcrcTransferArg, MintArg, NftMetadata, RedeemArg, RedeemError, TransferError};
+
+/// Maximum number of tokens read in a single query, the default batch size of the collection canisters
+pub const QUERY_BATCH_SIZE: usize = 32;
+
+///
+/// Gets the owner of a token directly from the collection canister using `icrc7_owner_of`
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+///
+/// ## Returns
+/// * `Ok`: principal of the owner of the token
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn owner_of(collection_id: Principal, tkn_id: u64) -> Result<Principal, String> {
+
+    let (owners,): (Vec<Option<Account>>,) = ic_cdk::call(collection_id, "icrc7_owner_of", (vec![tkn_id as u128],))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    match owners.first() {
+        Some(Some(account)) => Ok(account.owner),
+        _ => Err("token does not exists on the collection".to_string()),
+    }
+}
+
+///
+/// Transfers a token on the collection canister using `icrc7_transfer` on behalf of his owner
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+/// * `from` - owner of the token
+/// * `to` - new owner of the token
+///
+/// ## Returns
+/// * `Ok`: id of the transfer transaction on the collection
+/// * `Error`: Error of type TransferError
+///
+pub async fn transfer_token(collection_id: Principal, tkn_id: u64, from: Principal, to: Principal) -> Result<u128, TransferError> {
+
+    match ic_cdk::call::<(Vec<IcrcTransferArg>, Option<Principal> ), (Vec<Option<Result<u128, TransferError>>>,)>(
+        collection_id, 
+        "icrc7_transfer", 
+        ([IcrcTransferArg {
+            from_subaccount: None, 
+            to: Account::from(to), 
+            token_id: tkn_id as u128, 
+            memo: None, 
+            created_at_time: None
+        }].to_vec(), Some(from), ), )
+    .await {
+        Ok((res,)) => match res.first() {
+            Some(Some(trasfer_el)) => trasfer_el.clone(),
+            _ => Err(TransferError::GenericError { error_code: 400, message: "error in transfering NFT".to_string() })
+        },
+        Err(e) => Err(TransferError::GenericError { error_code: 400, message: format!("failed to call collection: {:?}", e) }),
+    }
+}
+
+///
+/// Burns a token on the collection canister using `icrc7_burn` on behalf of his owner
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+/// * `owner` - owner of the token
+///
+/// ## Returns
+/// * `Ok`: id of the burn transaction on the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn burn(collection_id: Principal, tkn_id: u64, owner: Principal) -> Result<u128, String> {
+
+    let arg = BurnArg {
+        from_subaccount: None,
+        token_id: tkn_id as u128,
+        memo: None,
+    };
+    let (res,): (Vec<Option<Result<u128, BurnError>>>,) = ic_cdk::call(collection_id, "icrc7_burn", (vec![arg], Some(owner),))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    match res.first() {
+        Some(Some(Ok(tid))) => Ok(*tid),
+        Some(Some(Err(e))) => Err(format!("error in burning token {}: {:?}", tkn_id, e)),
+        _ => Err(format!("error in burning token {}", tkn_id)),
+    }
+}
+
+///
+/// Redeems a ticket on the collection canister using `icrc7_redeem` on behalf of the door staff
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+/// * `holder` - principal presenting the ticket, must be its owner
+/// * `staff` - door staff checking in the ticket
+///
+/// ## Returns
+/// * `Ok`: id of the redeem transaction on the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn redeem(collection_id: Principal, tkn_id: u64, holder: Principal, staff: Principal) -> Result<u128, String> {
+
+    let arg = RedeemArg {
+        token_id: tkn_id as u128,
+        holder: Account::from(holder),
+        memo: None,
+    };
+    let (res,): (Result<u128, RedeemError>,) = ic_cdk::call(collection_id, "icrc7_redeem", (arg, Some(staff),))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    match res {
+        Ok(tid) => Ok(tid),
+        Err(RedeemError::AlreadyRedeemed { redeemed_at }) => Err(format!("ticket {} already redeemed at {}", tkn_id, redeemed_at)),
+        Err(RedeemError::Unauthorized) => Err(format!("ticket {} is not owned by {}", tkn_id, holder)),
+        Err(e) => Err(format!("error in redeeming token {}: {:?}", tkn_id, e)),
+    }
+}
+
+///
+/// Gets when a ticket has been redeemed from the metadata of the token on the collection canister
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+///
+/// ## Returns
+/// * `Ok`: timestamp of the redemption, None if the ticket has not been redeemed
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn redeemed_at(collection_id: Principal, tkn_id: u64) -> Result<Option<u64>, String> {
+
+    let (metadata,): (Vec<Option<BTreeMap<String, Value>>>,) = ic_cdk::call(collection_id, "icrc7_token_metadata", (vec![tkn_id as u128],))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    match metadata.first() {
+        Some(Some(metadata)) => match metadata.get("RedeemedAt") {
+            Some(Value::Nat(at)) => Ok(u64::try_from(at.0.clone()).ok()),
+            _ => Ok(None),
+        },
+        _ => Err("token does not exists on the collection".to_string()),
+    }
+}
+
+///
+/// Gets the tickets redeemed on the collection canister with their holders, 
+/// reading the metadata and the owners of the tokens in batches of `QUERY_BATCH_SIZE`
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_ids` - ids of the tokens
+///
+/// ## Returns
+/// * `Ok`: ids of the tickets redeemed with their holder
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn redeemed_holders(collection_id: Principal, tkn_ids: &[u64]) -> Result<Vec<(u64, Principal)>, String> {
+
+    let mut res = Vec::new();
+    for batch in tkn_ids.chunks(QUERY_BATCH_SIZE) {
+        let ids = batch.iter().map(|x| *x as u128).collect::<Vec<u128>>();
+        let (metadata,): (Vec<Option<BTreeMap<String, Value>>>,) = ic_cdk::call(collection_id, "icrc7_token_metadata", (ids.clone(),))
+            .await
+            .map_err(|e| format!("failed to call collection: {:?}", e))?;
+        let (owners,): (Vec<Option<Account>>,) = ic_cdk::call(collection_id, "icrc7_owner_of", (ids,))
+            .await
+            .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+        for ((tkn_id, metadata), owner) in batch.iter().zip(metadata).zip(owners) {
+            if let (Some(metadata), Some(owner)) = (metadata, owner) {
+                if metadata.contains_key("RedeemedAt") {
+                    res.push((*tkn_id, owner.owner));
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+///
+/// Takes a certified snapshot of the owners of the tickets on the collection canister using `icrc7_snapshot_owners`
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+/// ## Returns
+/// * `Ok`: timestamp of the snapshot
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn snapshot_owners(collection_id: Principal) -> Result<u64, String> {
+
+    let (taken_at,): (u64,) = ic_cdk::call(collection_id, "icrc7_snapshot_owners", ())
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    Ok(taken_at)
+}
+
+///
+/// Mints a token on the collection canister using `icrc7_mint` on behalf of the organizer, the minting authority of the collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token
+/// * `metadata` - metadata of the tier of the token
+/// * `organizer` - owner of the collection
+/// * `to` - owner of the new token
+///
+/// ## Returns
+/// * `Ok`: id of the mint transaction on the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn mint(collection_id: Principal, tkn_id: u64, metadata: &NftMetadata, organizer: Principal, to: Principal) -> Result<u128, String> {
+
+    let arg = MintArg {
+        to: Account::from(to),
+        memo: None,
+        token_id: tkn_id as u128,
+        from_subaccount: None,
+        token_description: Some(metadata.token_description.clone()),
+        token_logo: Some(metadata.token_logo.clone()),
+        token_name: Some(metadata.token_name.clone()),
+        token_privilege_code: Some(metadata.token_privilege_code),
+        token_soulbound: metadata.soulbound,
+    };
+    let (res,): (Result<u128, Errors>,) = ic_cdk::call(collection_id, "icrc7_mint", (arg, Some(organizer),))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    res.map_err(|e| format!("error in minting token {}: {:?}", tkn_id, e))
+}
+
+///
+/// Updates the description and the logo of the collection canister using `icrc7_set_collection_metadata`,
+/// the fields passed as none are not changed
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `description` - new description of the collection
+/// * `logo` - new logo of the collection
+///
+/// ## Returns
+/// * `Ok`: empty
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn set_metadata(collection_id: Principal, description: Option<String>, logo: Option<String>) -> Result<(), String> {
+
+    let (res,): (bool,) = ic_cdk::call(collection_id, "icrc7_set_collection_metadata", (description, logo,))
+        .await
+        .map_err(|e| format!("failed to call collection: {:?}", e))?;
+
+    if res {
+        Ok(())
+    } else {
+        Err("error in updating the metadata of the collection".to_string())
+    }
+}
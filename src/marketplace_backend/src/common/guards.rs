@@ -1,3 +1,6 @@
+use std::cell::Cell;
+use std::thread::LocalKey;
+
 use candid::Principal;
 
 ///
@@ -14,3 +17,49 @@ pub fn caller_is_auth() -> Result<(), String> {
     }
     Ok(())
 }
+
+///
+/// Checks if the caller is a controller of the backend canister
+/// 
+/// ## Returns
+/// * `Ok` - the caller is a controller
+/// * `Err` - the caller is not a controller
+/// 
+pub fn caller_is_controller() -> Result<(), String> {
+    let caller = ic_cdk::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Caller is not a controller".into());
+    }
+    Ok(())
+}
+
+///
+/// Lock of a periodic task held until dropped,
+/// it is also dropped when a callback traps so the task is not left locked
+///
+pub struct LockGuard(&'static LocalKey<Cell<bool>>);
+
+impl LockGuard {
+    ///
+    /// Acquires a lock
+    ///
+    /// ## Arguments
+    /// * `lock` - flag set while the lock is held
+    ///
+    /// ## Returns
+    /// * `Some`: guard releasing the lock when dropped
+    /// * `None`: the lock is already held
+    ///
+    pub fn acquire(lock: &'static LocalKey<Cell<bool>>) -> Option<Self> {
+        if lock.with(|x| x.replace(true)) {
+            return None;
+        }
+        Some(LockGuard(lock))
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.0.with(|x| x.set(false));
+    }
+}
@@ -1,10 +1,14 @@
 use std::borrow::Cow;
 
-use candid::{CandidType, Principal};
+use candid::{CandidType, Nat, Principal};
+use ic_ledger_types::MAINNET_LEDGER_CANISTER_ID;
 use ic_stable_structures::{storable::Bound, Storable};
 use serde::{Deserialize, Serialize};
 use icrc_ledger_types::icrc1::account::Account;
 
+// shared with the scanners verifying the tickets offline
+pub use ticket_verifier::{CheckInChallenge, SignedCheckIn};
+
 ///
 /// Init args of the collection of NFTs
 /// 
@@ -24,6 +28,7 @@ pub struct InitArg {
     pub icrc7_atomic_batch_transfers: Option<bool>,
     pub tx_window: Option<u64>,
     pub permitted_drift: Option<u64>,
+    pub icrc7_royalties: Option<u16>,
 }
 
 ///
@@ -63,6 +68,7 @@ impl From<(Account, CanisterArg)> for InitArg {
             icrc7_atomic_batch_transfers: arg.icrc7_atomic_batch_transfers,
             tx_window: arg.tx_window,
             permitted_drift: arg.permitted_drift,
+            icrc7_royalties: None,
         }
     }
 }
@@ -70,6 +76,9 @@ impl From<(Account, CanisterArg)> for InitArg {
 ///
 /// Metadata of a NFT including the quantity of that single NFT 
 /// Quantity is the number of nfts of that type that are going to be created
+/// The soulbound NFTs (speaker passes, staff badges) can only be sold by the organizer and never resold
+/// The price is expressed in the smallest unit of the payment token of the collection (ex. e8s for ICP)
+/// The primary sales of the tier can be limited to a window, from `sale_start` to `sale_end` in nanoseconds
 /// 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct NftMetadata {
@@ -78,27 +87,59 @@ pub struct NftMetadata {
     pub token_description: String,
     pub quantity: u64,
     pub token_logo: String,
-    pub price: u32
+    pub price: Nat,
+    #[serde(default)]
+    pub sale_start: Option<u64>,
+    #[serde(default)]
+    pub sale_end: Option<u64>,
+    #[serde(default)]
+    pub soulbound: Option<bool>
 }
 
+///
+/// Tier of a collection, template of the NFTs minted at purchase time.
+/// `tkn_ids` are the NFTs of the tier saved on the collection record by the older versions, the NFTs minted at purchase time
+/// are saved on their own map with their tier. `reserved` is the number of NFTs minted or being minted at purchase time
+/// and `sold` the number of NFTs sold by the organizer.
+/// 
 #[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct CollectionNfts {
     pub nft: NftMetadata,
-    pub tkn_ids: Vec<u64>
+    pub tkn_ids: Vec<u64>,
+    #[serde(default)]
+    pub sold: u64,
+    #[serde(default)]
+    pub reserved: u64
+}
+
+///
+/// Availability of a tier of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct TierInfo {
+    pub token_name: String,
+    pub token_privilege_code: u8,
+    pub price: Nat,
+    pub sale_start: Option<u64>,
+    pub sale_end: Option<u64>,
+    pub supply: u64,
+    pub remaining: u64,
+    pub sold_out: bool,
+    pub soulbound: bool
 }
 
 /// 
-/// Marketplace data of the NFT
+/// Marketplace data of the NFT, the price is expressed in the smallest unit of the payment token of the collection
 /// 
-#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy)]
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
 pub struct NftMarketData {
     pub owner: Principal,
-    pub price: Option<u32>,
+    pub price: Option<Nat>,
     pub on_sale: bool
 }
 
 impl Storable for NftMarketData {
-    fn to_bytes(&self) -> Cow<[u8]> { 
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
 
         Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
     }
@@ -126,7 +167,7 @@ pub struct OwnersDoubleKey {
 
 impl Storable for OwnersDoubleKey {
 
-    fn to_bytes(&self) -> Cow<[u8]> { 
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
 
         Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
     }
@@ -152,6 +193,19 @@ pub struct DiscountWindowArg {
     pub discount_percentage: u8 
 }
 
+///
+/// Descending price of the primary sales, alternative to the discount windows.
+/// The price decreases from the price of the NFT at `start_time` to `floor_percentage` of it at `end_time`,
+/// continuously if `step_interval` is 0 or every `step_interval` nanoseconds otherwise.
+/// 
+#[derive(Debug, Serialize, Deserialize, CandidType, Clone)]
+pub struct DutchPricing {
+    pub start_time: u64,
+    pub end_time: u64,
+    pub floor_percentage: u8,
+    pub step_interval: u64
+}
+
 ///
 /// General arg passed to create a collection of NFTs
 /// 
@@ -160,7 +214,219 @@ pub struct Arg {
     pub canister_arg: CanisterArg,
     pub nfts: Vec<NftMetadata>,
     pub expire_date: u64,
-    pub discount_windows: Vec<DiscountWindowArg>
+    pub discount_windows: Vec<DiscountWindowArg>,
+    pub royalty_bps: u16,
+    pub funding_goal: Option<Nat>,
+    pub settlement: SettlementMode,
+    pub milestones: Vec<MilestoneArg>,
+    pub dutch_pricing: Option<DutchPricing>,
+    pub ledger: Option<Principal>,
+    pub presale_end: Option<u64>,
+    pub max_per_wallet: Option<u32>,
+    pub tags: Vec<String>
+}
+
+///
+/// Token used to pay the NFTs of a collection, the decimals are read from the ledger at the creation of the collection.
+/// The collections created before the payment token was configurable are paid in ICP.
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub struct PaymentToken {
+    pub ledger: Principal,
+    pub decimals: u8
+}
+
+impl Default for PaymentToken {
+    fn default() -> Self {
+        PaymentToken { ledger: MAINNET_LEDGER_CANISTER_ID, decimals: 8 }
+    }
+}
+
+///
+/// How the payments of the primary sales of a collection are settled
+/// * `Immediate` - the organizer is paid at every sale
+/// * `AllOrNothing` - the payments stay in escrow until the expire date, 
+///   then the organizer can withdraw them if the funding goal is reached, otherwise the buyers are refunded
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum SettlementMode {
+    #[default]
+    Immediate,
+    AllOrNothing
+}
+
+///
+/// Status of the funds raised by a collection with all-or-nothing settlement
+/// * `Open` - the funds are held in escrow
+/// * `Withdrawn` - the funding goal has been reached and the funds have been paid to the organizer
+/// * `Refunded` - the funding goal has not been reached, the buyers have been refunded and their NFTs burned
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum FundingStatus {
+    #[default]
+    Open,
+    Withdrawn,
+    Refunded
+}
+
+///
+/// Status of a collection
+/// * `Active` - the NFTs of the collection can be sold
+/// * `Cancelled` - the event has been cancelled by the organizer, the buyers are refunded and the NFTs burned
+/// * `Draft` - the collection is prepared by the organizer and not published yet, it has no canister
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Default)]
+pub enum CollectionStatus {
+    #[default]
+    Active,
+    Cancelled,
+    Draft
+}
+
+///
+/// Amount released by a milestone, either fixed or as percentage of the funds raised at the moment of the release
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub enum MilestoneAmount {
+    Amount(Nat),
+    Percentage(u8)
+}
+
+///
+/// Milestone passed at the creation of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct MilestoneArg {
+    pub amount: MilestoneAmount,
+    pub deadline: u64,
+    pub description: String
+}
+
+///
+/// Status of a milestone
+/// * `Pending` - the organizer has not requested the release yet
+/// * `Requested` - the release is waiting for the approval of an admin or of the backers
+/// * `Released` - the funds have been paid to the organizer
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum MilestoneStatus {
+    Pending,
+    Requested,
+    Released
+}
+
+///
+/// Tranche of the escrowed funds of a collection paid to the organizer once approved
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Milestone {
+    pub amount: MilestoneAmount,
+    pub deadline: u64,
+    pub description: String,
+    pub status: MilestoneStatus,
+    pub requested_at: Option<u64>,
+    pub released_amount: Option<Nat>,
+    pub release_block: Option<Nat>
+}
+
+impl From<MilestoneArg> for Milestone {
+    fn from(arg: MilestoneArg) -> Self {
+        Self {
+            amount: arg.amount,
+            deadline: arg.deadline,
+            description: arg.description,
+            status: MilestoneStatus::Pending,
+            requested_at: None,
+            released_amount: None,
+            release_block: None,
+        }
+    }
+}
+
+///
+/// Key of the approvals given by the backers to the release of a milestone
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Copy)]
+pub struct MilestoneApprovalKey {
+    pub collection_id: Principal,
+    pub milestone_id: u32,
+    pub backer: Principal
+}
+
+impl Storable for MilestoneApprovalKey {
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Principal allowed to buy from the organizer during the presale of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AllowlistKey {
+    pub collection_id: Principal,
+    pub principal: Principal
+}
+
+impl Storable for AllowlistKey {
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Purchase of a principal on a collection, used as key of the index of the purchases by buyer
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BuyerPurchaseKey {
+    pub collection_id: Principal,
+    pub buyer: Principal,
+    pub purchase_id: u64
+}
+
+impl Storable for BuyerPurchaseKey {
+
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
 }
 
 ///
@@ -175,7 +441,8 @@ pub struct MintArg {
     pub token_description : Option<String>,
     pub token_logo : Option<String>,
     pub token_name : Option<String>,
-    pub token_privilege_code: Option<u8>
+    pub token_privilege_code: Option<u8>,
+    pub token_soulbound: Option<bool>
   }
 
   ///
@@ -191,6 +458,48 @@ pub enum Errors {
     TokenIdAlreadyExist,
 }
 
+///
+/// Args passed to the method icrc7_burn
+/// 
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct BurnArg {
+    pub from_subaccount: Option<[u8; 32]>,
+    pub token_id: u128,
+    pub memo: Option<Vec<u8>>,
+}
+
+///
+/// Possible Errors of the icrc7_burn method
+/// 
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum BurnError {
+    Unauthorized,
+    NonExistingTokenId,
+    GenericError { error_code: u128, message: String },
+    GenericBatchError { error_code: u128, message: String },
+}
+
+///
+/// Arg of the icrc7_redeem method, used to check in a ticket at the event door
+/// 
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct RedeemArg {
+    pub token_id: u128,
+    pub holder: Account,
+    pub memo: Option<Vec<u8>>,
+}
+
+///
+/// Possible Errors of the icrc7_redeem method
+/// 
+#[derive(CandidType, Deserialize, Debug, Clone)]
+pub enum RedeemError {
+    Unauthorized,
+    NonExistingTokenId,
+    AlreadyRedeemed { redeemed_at: u64 },
+    GenericError { error_code: u128, message: String },
+}
+
 ///
 /// Full information about a collection
 /// 
@@ -201,7 +510,24 @@ pub struct CollectionFullInfo {
     pub expire_date: u64,
     pub discount_windows: Vec<DiscountWindowArg>,
     pub available: bool,
-    pub nfts: Vec<CollectionNfts>
+    pub nfts: Vec<CollectionNfts>,
+    pub royalty_bps: u16,
+    pub funding_goal: Option<Nat>,
+    pub settlement: SettlementMode,
+    pub raised: Nat,
+    pub funding_status: FundingStatus,
+    pub milestones: Vec<Milestone>,
+    pub released: Nat,
+    pub status: CollectionStatus,
+    pub dutch_pricing: Option<DutchPricing>,
+    pub token: PaymentToken,
+    pub presale_end: Option<u64>,
+    pub max_per_wallet: Option<u32>,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub created_at: u64,
+    pub attendance_collection: Option<Principal>
 }
 
 ///
@@ -212,11 +538,85 @@ pub struct CollectionInfo {
     pub owner: Principal,
     pub expire_date: u64,
     pub discount_windows: Vec<DiscountWindowArg>,
-    pub nfts: Vec<CollectionNfts>
+    pub nfts: Vec<CollectionNfts>,
+    #[serde(default)]
+    pub royalty_bps: u16,
+    #[serde(default)]
+    pub funding_goal: Option<Nat>,
+    #[serde(default)]
+    pub settlement: SettlementMode,
+    #[serde(default)]
+    pub raised: Nat,
+    #[serde(default)]
+    pub funding_status: FundingStatus,
+    #[serde(default)]
+    pub milestones: Vec<Milestone>,
+    #[serde(default)]
+    pub released: Nat,
+    #[serde(default)]
+    pub status: CollectionStatus,
+    #[serde(default)]
+    pub dutch_pricing: Option<DutchPricing>,
+    #[serde(default)]
+    pub token: PaymentToken,
+    #[serde(default)]
+    pub next_tkn_id: u64,
+    #[serde(default)]
+    pub presale_end: Option<u64>,
+    #[serde(default)]
+    pub max_per_wallet: Option<u32>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub attendance_collection: Option<Principal>
+}
+
+impl CollectionInfo {
+    ///
+    /// Lowest price of the tiers of the collection, before discounts
+    ///
+    pub fn floor_price(&self) -> Nat {
+        self.nfts.iter().map(|x| x.nft.price.clone()).min().unwrap_or_default()
+    }
+}
+
+impl Default for CollectionInfo {
+    fn default() -> Self {
+        CollectionInfo {
+            owner: Principal::anonymous(),
+            expire_date: 0,
+            discount_windows: Vec::new(),
+            nfts: Vec::new(),
+            royalty_bps: 0,
+            funding_goal: None,
+            settlement: SettlementMode::default(),
+            raised: Nat::from(0u32),
+            funding_status: FundingStatus::default(),
+            milestones: Vec::new(),
+            released: Nat::from(0u32),
+            status: CollectionStatus::default(),
+            dutch_pricing: None,
+            token: PaymentToken::default(),
+            next_tkn_id: 0,
+            presale_end: None,
+            max_per_wallet: None,
+            name: String::new(),
+            description: None,
+            tags: Vec::new(),
+            created_at: 0,
+            attendance_collection: None,
+        }
+    }
 }
 
 impl Storable for CollectionInfo {
-    fn to_bytes(&self) -> Cow<[u8]> { 
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
 
         Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
     }
@@ -227,10 +627,7 @@ impl Storable for CollectionInfo {
             .expect("failed to serialize from bytes")
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: 2048,
-        is_fixed_size: false,
-    };
+    const BOUND: Bound = Bound::Unbounded;
 }
 
 ///
@@ -254,6 +651,7 @@ pub enum TransferError {
     TooOld,
     CreatedInFuture { ledger_time: u64 },
     Duplicate { duplicate_of: u128 },
+    NonTransferable,
     GenericError { error_code: u128, message: String },
     GenericBatchError { error_code: u128, message: String },
 }
@@ -268,4 +666,688 @@ pub struct IcrcTransferArg {
     pub token_id: u128,
     pub memo: Option<Vec<u8>>,
     pub created_at_time: Option<u64>,
-}
\ No newline at end of file
+}
+
+///
+/// Receipt of a purchase of an NFT, including the block indexes of the ledger transactions
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct PurchaseRecord {
+    pub collection_id: Principal,
+    pub tkn_id: u64,
+    pub buyer: Principal,
+    pub seller: Principal,
+    pub amount: Nat,
+    pub timestamp: u64,
+    pub payment_block: Nat,
+    pub settlement_block: Option<Nat>,
+    pub refund_block: Option<Nat>,
+    #[serde(default)]
+    pub royalty_block: Option<Nat>,
+    #[serde(default)]
+    pub platform_fee_block: Option<Nat>,
+    #[serde(default)]
+    pub escrowed: bool,
+    #[serde(default)]
+    pub refunded: bool
+}
+
+impl Storable for PurchaseRecord {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Kind of payment owed by the backend canister
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum PayoutKind {
+    Settlement,
+    Royalty,
+    PlatformFee,
+    Refund
+}
+
+///
+/// Status of a payment owed by the backend canister
+/// * `Pending` - it will be retried automatically
+/// * `Completed` - the payment has been sent
+/// * `Failed` - the maximum number of attempts has been reached
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum PayoutStatus {
+    Pending,
+    Completed,
+    Failed
+}
+
+///
+/// Payment from the escrow of a collection that could not be completed and needs to be retried
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct PendingPayout {
+    pub collection_id: Principal,
+    pub to: Principal,
+    pub amount: Nat,
+    pub kind: PayoutKind,
+    pub purchase_id: Option<u64>,
+    pub status: PayoutStatus,
+    pub attempts: u32,
+    pub next_attempt: u64,
+    pub last_error: String,
+    pub block: Option<Nat>,
+    // sent unchanged on every attempt so that the ledger deduplicates the transfer
+    #[serde(default)]
+    pub created_at_time: Option<u64>,
+    #[serde(default)]
+    pub memo: Option<Vec<u8>>
+}
+
+impl Storable for PendingPayout {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 2048,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Fee taken by the platform on every sale, paid to the fee account if set
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Default)]
+pub struct PlatformFee {
+    pub fee_account: Option<Principal>,
+    pub fee_bps: u16
+}
+
+impl Storable for PlatformFee {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 256,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Highest bid of an auction, the amount is held in the escrow of the collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Bid {
+    pub bidder: Principal,
+    pub amount: Nat,
+    pub payment_block: Nat,
+    pub timestamp: u64
+}
+
+///
+/// Status of an auction
+/// * `Active` - the auction accepts bids until the end time
+/// * `Settled` - the NFT has been transferred to the highest bidder
+/// * `Unsold` - the auction ended without bids
+/// * `Failed` - the NFT could not be transferred and the highest bid has been refunded
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum AuctionStatus {
+    Active,
+    Settled,
+    Unsold,
+    Failed
+}
+
+///
+/// English auction of an NFT, the NFT is assigned to the highest bidder at the end time
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Auction {
+    pub seller: Principal,
+    pub start_price: Nat,
+    pub min_increment: Nat,
+    pub end_time: u64,
+    pub highest_bid: Option<Bid>,
+    pub status: AuctionStatus,
+    pub purchase_id: Option<u64>
+}
+
+impl Storable for Auction {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Offer made by a collector on an NFT, the amount is held in the escrow of the collection until the offer is accepted, cancelled or expired
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Offer {
+    pub bidder: Principal,
+    pub amount: Nat,
+    pub payment_block: Nat,
+    pub created_at: u64,
+    pub expires_at: u64
+}
+
+///
+/// Offers made on an NFT
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Default)]
+pub struct NftOffers {
+    pub offers: Vec<Offer>
+}
+
+impl Storable for NftOffers {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 8192,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Steps of the creation of a collection
+/// * `Draft` - the collection is a draft that can be edited, nothing is created until it is published
+/// * `Pending` - the creation has been requested, no canister exists yet
+/// * `CanisterCreated` - the collection canister has been created
+/// * `CodeInstalled` - the ICRC-7 code has been installed on the collection canister
+/// * `Registered` - the collection has been saved on the marketplace, the creation is completed
+/// * `RolledBack` - the creation has been abandoned and the collection canister deleted
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum CreationStep {
+    Draft,
+    Pending,
+    CanisterCreated,
+    CodeInstalled,
+    Registered,
+    RolledBack
+}
+
+///
+/// Creation of a collection saved step by step so that it can be resumed or rolled back after an error.
+/// `init_arg` is the candid encoded init arg of the collection canister and `collection` the record saved when it is registered.
+/// 
+#[derive(Deserialize, Debug, Serialize, Clone)]
+pub struct CreationJob {
+    pub owner: Principal,
+    pub init_arg: Vec<u8>,
+    pub collection: CollectionInfo,
+    pub canister_id: Option<Principal>,
+    pub step: CreationStep,
+    pub in_progress: bool,
+    pub error: Option<String>,
+    pub updated_at: u64
+}
+
+impl Storable for CreationJob {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+///
+/// Status of the creation of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CreationStatus {
+    pub job_id: u64,
+    pub owner: Principal,
+    pub canister_id: Option<Principal>,
+    pub step: CreationStep,
+    pub in_progress: bool,
+    pub error: Option<String>,
+    pub updated_at: u64
+}
+
+impl From<(u64, CreationJob)> for CreationStatus {
+    fn from((job_id, job): (u64, CreationJob)) -> Self {
+        Self {
+            job_id,
+            owner: job.owner,
+            canister_id: job.canister_id,
+            step: job.step,
+            in_progress: job.in_progress,
+            error: job.error,
+            updated_at: job.updated_at,
+        }
+    }
+}
+
+///
+/// Change made by the organizer on a collection after its creation
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub enum CollectionChangeKind {
+    ExpireDate { from: u64, to: u64 },
+    DiscountWindowAdded(DiscountWindowArg),
+    DiscountWindowRemoved(DiscountWindowArg),
+    Metadata { description: Option<String>, logo: Option<String> },
+    RoleGranted { principal: Principal, role: Role },
+    RoleRevoked { principal: Principal, role: Role },
+    PrivilegeSet { code: u8, name: String },
+    PrivilegeRemoved { code: u8 }
+}
+
+///
+/// Audit record of a change made on a collection, `caller` is the principal that made the change
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct CollectionChange {
+    pub collection_id: Principal,
+    pub caller: Principal,
+    pub change: CollectionChangeKind,
+    pub timestamp: u64
+}
+
+impl Storable for CollectionChange {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 32768,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Role of a team member of a collection:
+/// * `Owner` - co-owner, can do everything the organizer can do and manage the roles of the other members
+/// * `Manager` - edits the collection and its allowlist
+/// * `DoorStaff` - checks in the tickets at the event
+/// * `Finance` - withdraws the funds and requests the release of the milestones, the funds are always paid to the organizer
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum Role {
+    Owner,
+    Manager,
+    DoorStaff,
+    Finance
+}
+
+impl Storable for Role {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 64,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Key of the roles given to the team members of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RoleKey {
+    pub collection_id: Principal,
+    pub principal: Principal
+}
+
+impl Storable for RoleKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Filters of the search of the collections, the filters not passed are not applied:
+/// * `available` - if true only the collections on sale, if false only the collections expired or cancelled
+/// * `owner` - organizer of the collections
+/// * `min_price`, `max_price` - range including the lowest price of the tiers of the collections
+/// * `tags` - the collections need to have at least one of the tags
+/// * `text` - text contained in the name or in the description of the collections, case insensitive
+/// 
+#[derive(CandidType, Deserialize, Debug, Clone, Default)]
+pub struct CollectionFilter {
+    pub available: Option<bool>,
+    pub owner: Option<Principal>,
+    pub min_price: Option<Nat>,
+    pub max_price: Option<Nat>,
+    pub tags: Vec<String>,
+    pub text: Option<String>
+}
+
+///
+/// Orders of the search of the collections
+/// 
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum CollectionSort {
+    ExpireDate,
+    Newest,
+    PriceLowToHigh,
+    PriceHighToLow
+}
+
+///
+/// Key of the indexes of the collections sorted by a number: expire date, creation time or lowest price
+/// 
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollectionIndexKey {
+    pub value: Nat,
+    pub collection_id: Principal
+}
+
+impl Storable for CollectionIndexKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Key of the indexes of the collections by a text: organizer or tag
+/// 
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollectionTextKey {
+    pub value: String,
+    pub collection_id: Principal
+}
+
+impl Storable for CollectionTextKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Kind of a perk given by a privilege code
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub enum PerkKind {
+    PriorityAccess,
+    Backstage,
+    MerchPickup,
+    ExclusiveContent { url: String }
+}
+
+///
+/// Perk given to the holders of the tickets with a privilege code.
+/// `max_uses` is the number of times the perk can be claimed with a ticket, unlimited if not passed,
+/// `expires_at` the time in nanoseconds after which the perk can not be claimed
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Perk {
+    pub name: String,
+    pub kind: PerkKind,
+    pub max_uses: Option<u32>,
+    pub expires_at: Option<u64>
+}
+
+///
+/// Named set of perks given by a privilege code of a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct Privilege {
+    pub name: String,
+    pub perks: Vec<Perk>
+}
+
+impl Storable for Privilege {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 16384,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Key of the privileges registered on a collection
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrivilegeKey {
+    pub collection_id: Principal,
+    pub code: u8
+}
+
+impl Storable for PrivilegeKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Key of the claims of a perk made with a ticket
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PerkClaimKey {
+    pub collection_id: Principal,
+    pub tkn_id: u64,
+    pub perk: String
+}
+
+impl Storable for PerkClaimKey {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Perk of a ticket with the number of times it has been claimed and if it can still be claimed
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct TicketPerk {
+    pub perk: Perk,
+    pub claimed: u32,
+    pub claimable: bool
+}
+
+///
+/// Proof of attendance minted for a ticket checked in: the principal rewarded and the id of the token minted
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct AttendanceReward {
+    pub holder: Principal,
+    pub tkn_id: u64
+}
+
+impl Storable for AttendanceReward {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 1024,
+        is_fixed_size: false,
+    };
+}
+
+///
+/// Metadata of the proof-of-attendance collection minted after an event for the attendees who checked in
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct AttendanceArg {
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    pub logo: String
+}
+
+///
+/// Minting of the proof-of-attendance collection of an event, resumed on every call until every attendee is rewarded.
+/// `installed` tells if the code of the collection canister has been installed and `minted` counts the tokens minted,
+/// the ids of the tokens are sequential from 1. `minting` is the ticket whose token is being minted, retried first if interrupted.
+/// 
+#[derive(CandidType, Deserialize, Debug, Serialize, Clone)]
+pub struct AttendanceJob {
+    pub arg: AttendanceArg,
+    pub canister_id: Option<Principal>,
+    pub installed: bool,
+    pub in_progress: bool,
+    pub minted: u64,
+    #[serde(default)]
+    pub minting: Option<u64>,
+    pub error: Option<String>,
+    pub updated_at: u64
+}
+
+impl Storable for AttendanceJob {
+    fn to_bytes(&self) -> Cow<'_, [u8]> { 
+
+        Cow::Owned(serde_json::to_string(self).expect("failed to serialize to bytes").as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+
+        serde_json::from_str(String::from_utf8(bytes.to_vec()).expect("failed to serialize from bytes").as_str())
+            .expect("failed to serialize from bytes")
+    }
+
+    const BOUND: Bound = Bound::Bounded {
+        max_size: 32768,
+        is_fixed_size: false,
+    };
+}
@@ -0,0 +1,324 @@
+use candid::Principal;
+
+use crate::common::structures::{CollectionInfo, CollectionStatus, CreationJob, CreationStatus, CreationStep};
+use crate::factory::{create_collection_canister, delete_collection_canister, install_collection_code};
+use crate::memory::{get_creation_job, insert_collection_record, insert_creation_job, update_creation_job};
+
+///
+/// Saves a new creation of a collection and runs all his steps
+///
+/// ## Arguments
+/// * `owner` - owner of the collection
+/// * `init_arg` - candid encoded init arg of the collection canister
+/// * `collection` - record of the collection saved when it is registered
+///
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: String with the error of the step that failed and the id of the creation to resume or roll back
+///
+pub async fn create(owner: Principal, init_arg: Vec<u8>, collection: CollectionInfo) -> Result<Principal, String> {
+    let job_id = insert_creation_job(CreationJob {
+        owner,
+        init_arg,
+        collection,
+        canister_id: None,
+        step: CreationStep::Pending,
+        in_progress: false,
+        error: None,
+        updated_at: ic_cdk::api::time(),
+    });
+    run(job_id).await
+}
+
+///
+/// Saves the draft of a collection, nothing is created until it is published
+///
+/// ## Arguments
+/// * `owner` - owner of the collection
+/// * `init_arg` - candid encoded init arg of the collection canister
+/// * `collection` - record of the collection saved when it is registered
+///
+/// ## Returns
+/// * id of the draft
+///
+pub fn create_draft(owner: Principal, init_arg: Vec<u8>, collection: CollectionInfo) -> u64 {
+    insert_creation_job(CreationJob {
+        owner,
+        init_arg,
+        collection,
+        canister_id: None,
+        step: CreationStep::Draft,
+        in_progress: false,
+        error: None,
+        updated_at: ic_cdk::api::time(),
+    })
+}
+
+///
+/// Gets a draft checking that the caller is his owner
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// * `caller` - principal calling the function
+///
+fn get_owned_draft(draft_id: u64, caller: Principal) -> Result<CreationJob, String> {
+    let job = get_owned_job(draft_id, caller)?;
+    if job.step != CreationStep::Draft {
+        return Err("the collection is not a draft".to_string());
+    }
+    Ok(job)
+}
+
+///
+/// Replaces the content of a draft
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// * `caller` - principal calling the function
+/// * `init_arg` - candid encoded init arg of the collection canister
+/// * `collection` - record of the collection saved when it is registered
+///
+pub fn update_draft(draft_id: u64, caller: Principal, init_arg: Vec<u8>, collection: CollectionInfo) -> Result<(), String> {
+    let mut job = get_owned_draft(draft_id, caller)?;
+    job.init_arg = init_arg;
+    job.collection = collection;
+    job.updated_at = ic_cdk::api::time();
+    update_creation_job(draft_id, job);
+    Ok(())
+}
+
+///
+/// Gets the collection prepared in a draft, only his owner can see it
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// * `caller` - principal calling the function
+///
+pub fn get_draft(draft_id: u64, caller: Principal) -> Result<CollectionInfo, String> {
+    get_owned_draft(draft_id, caller).map(|x| x.collection)
+}
+
+///
+/// Publishes a draft running all the steps of the creation of the collection
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// * `caller` - principal calling the function
+///
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn publish(draft_id: u64, caller: Principal) -> Result<Principal, String> {
+    let mut job = get_owned_draft(draft_id, caller)?;
+    if job.collection.expire_date <= ic_cdk::api::time() {
+        return Err("the expire date of the collection is passed, the draft needs to be updated".to_string());
+    }
+
+    job.step = CreationStep::Pending;
+    job.collection.status = CollectionStatus::Active;
+    job.updated_at = ic_cdk::api::time();
+    update_creation_job(draft_id, job);
+    run(draft_id).await
+}
+
+///
+/// Gets a creation checking that the caller is the owner of the collection and that no step is running
+///
+/// ## Arguments
+/// * `job_id` - id of the creation
+/// * `caller` - principal calling the function
+///
+fn get_owned_job(job_id: u64, caller: Principal) -> Result<CreationJob, String> {
+    let job = match get_creation_job(job_id) {
+        Some(x) => x,
+        None => return Err("creation does not exists".to_string()),
+    };
+    if job.owner != caller {
+        return Err("only the owner of the collection can manage his creation".to_string());
+    }
+    if job.in_progress {
+        return Err("a step of the creation is already in progress".to_string());
+    }
+    Ok(job)
+}
+
+///
+/// Runs the steps of a creation starting from the last one completed:
+/// the collection canister is created, the ICRC-7 code installed on it and the collection registered on the marketplace.
+/// Every step is saved as soon as it is completed, on error the creation stops and can be resumed or rolled back.
+///
+/// ## Arguments
+/// * `job_id` - id of the creation
+///
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: String with the error of the step that failed and the id of the creation to resume or roll back
+///
+async fn run(job_id: u64) -> Result<Principal, String> {
+    let mut job = match get_creation_job(job_id) {
+        Some(x) => x,
+        None => return Err("creation does not exists".to_string()),
+    };
+    job.in_progress = true;
+    update_creation_job(job_id, job.clone());
+
+    let res = loop {
+        let step = match job.step {
+            CreationStep::Pending => create_collection_canister(job.owner).await.map(|canister_id| {
+                job.canister_id = Some(canister_id);
+                CreationStep::CanisterCreated
+            }),
+            CreationStep::CanisterCreated => {
+                let canister_id = job.canister_id.expect("canister id not saved");
+                install_collection_code(canister_id, job.init_arg.clone()).await.map(|_| CreationStep::CodeInstalled)
+            },
+            CreationStep::CodeInstalled => {
+                if job.collection.expire_date <= ic_cdk::api::time() {
+                    Err("the expire date of the collection is passed, the creation can only be rolled back".to_string())
+                } else {
+                    job.collection.created_at = ic_cdk::api::time();
+                    insert_collection_record(job.canister_id.expect("canister id not saved"), job.collection.clone());
+                    Ok(CreationStep::Registered)
+                }
+            },
+            CreationStep::Registered => break Ok(job.canister_id.expect("canister id not saved")),
+            CreationStep::Draft => break Err("the collection is a draft, it needs to be published".to_string()),
+            CreationStep::RolledBack => break Err("creation rolled back".to_string()),
+        };
+
+        job.updated_at = ic_cdk::api::time();
+        match step {
+            Ok(step) => {
+                job.step = step;
+                job.error = None;
+                update_creation_job(job_id, job.clone());
+            },
+            Err(e) => {
+                job.error = Some(e.clone());
+                break Err(e);
+            },
+        }
+    };
+
+    job.in_progress = false;
+    update_creation_job(job_id, job);
+    res.map_err(|e| format!("{}, the creation {} can be resumed or rolled back", e, job_id))
+}
+
+///
+/// Resumes a creation stopped by an error, only the owner of the collection can do it
+///
+/// ## Arguments
+/// * `job_id` - id of the creation
+/// * `caller` - principal calling the function
+///
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn resume(job_id: u64, caller: Principal) -> Result<Principal, String> {
+    let job = get_owned_job(job_id, caller)?;
+    match job.step {
+        CreationStep::Registered => Err("creation already completed".to_string()),
+        CreationStep::RolledBack => Err("creation rolled back".to_string()),
+        CreationStep::Draft => Err("the collection is a draft, it needs to be published".to_string()),
+        _ => run(job_id).await,
+    }
+}
+
+///
+/// Rolls back a creation not completed deleting the collection canister, only the owner of the collection can do it
+///
+/// ## Arguments
+/// * `job_id` - id of the creation
+/// * `caller` - principal calling the function
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn rollback(job_id: u64, caller: Principal) -> Result<String, String> {
+    let mut job = get_owned_job(job_id, caller)?;
+    match job.step {
+        CreationStep::Registered => return Err("creation already completed".to_string()),
+        CreationStep::RolledBack => return Err("creation already rolled back".to_string()),
+        _ => (),
+    }
+
+    if let Some(canister_id) = job.canister_id {
+        job.in_progress = true;
+        update_creation_job(job_id, job.clone());
+        let deleted = delete_collection_canister(canister_id).await;
+        job.in_progress = false;
+        job.updated_at = ic_cdk::api::time();
+        if let Err(e) = deleted {
+            job.error = Some(e.clone());
+            update_creation_job(job_id, job);
+            return Err(format!("unable to delete the canister {}: {}", canister_id, e));
+        }
+    }
+
+    job.step = CreationStep::RolledBack;
+    job.error = None;
+    job.updated_at = ic_cdk::api::time();
+    update_creation_job(job_id, job);
+    Ok(format!("creation {} rolled back", job_id))
+}
+
+///
+/// Status of a creation
+///
+/// ## Arguments
+/// * `job_id` - id of the creation
+///
+pub fn status(job_id: u64) -> Option<CreationStatus> {
+    get_creation_job(job_id).map(|x| CreationStatus::from((job_id, x)))
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Encode;
+    use ic_stable_structures::{StableBTreeMap, Storable, VectorMemory};
+
+    use super::*;
+    use crate::common::structures::InitArg;
+    use crate::editing::MAX_LOGO_SIZE;
+
+    #[test]
+    fn jobs_with_the_largest_logo_are_stored() {
+        let init_arg = InitArg {
+            minting_account: None,
+            icrc7_symbol: "TKT".to_string(),
+            icrc7_name: "tickets".to_string(),
+            icrc7_description: None,
+            icrc7_logo: Some("x".repeat(MAX_LOGO_SIZE)),
+            icrc7_supply_cap: Some(u128::MAX),
+            icrc7_max_query_batch_size: None,
+            icrc7_max_update_batch_size: None,
+            icrc7_max_take_value: None,
+            icrc7_default_take_value: None,
+            icrc7_max_memo_size: None,
+            icrc7_atomic_batch_transfers: None,
+            tx_window: None,
+            permitted_drift: None,
+            icrc7_royalties: None,
+        };
+        let job = CreationJob {
+            owner: Principal::anonymous(),
+            init_arg: Encode!(&init_arg).unwrap(),
+            collection: CollectionInfo::default(),
+            canister_id: None,
+            step: CreationStep::Draft,
+            in_progress: false,
+            error: None,
+            updated_at: 0,
+        };
+
+        let mut jobs = StableBTreeMap::<u64, CreationJob, VectorMemory>::new(VectorMemory::default());
+        jobs.insert(0, job.clone());
+        let stored = jobs.get(&0).unwrap();
+        assert_eq!(stored.init_arg, job.init_arg);
+        assert_eq!(stored.to_bytes(), job.to_bytes());
+    }
+}
@@ -0,0 +1,229 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use candid::{Nat, Principal};
+
+use crate::cancellation::complete_cancellations;
+use crate::collection::burn;
+use crate::common::guards::LockGuard;
+use crate::common::structures::{CollectionInfo, CollectionStatus, FundingStatus, OwnersDoubleKey, PayoutKind, Role, SettlementMode};
+use crate::ledger::{fee, ledger_of};
+use crate::memory::{get_collections, get_nfts, get_platform_fee, get_purchase, get_purchases, insert_collection_record, remove_nft_record, update_purchase_record};
+use crate::milestones::unreleased;
+use crate::payouts::{net_of_fee, pay_from_escrow, split_proceeds};
+use crate::roles::has_role;
+
+/// Interval between two checks of the expired all-or-nothing collections
+pub const FUNDING_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+thread_local! {
+    static REFUNDING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Starts the timer that refunds the buyers of the all-or-nothing collections that did not reach their funding goal
+/// and completes the cancellations interrupted by an error
+///
+pub fn start_funding_timer() {
+    ic_cdk_timers::set_timer_interval(FUNDING_CHECK_INTERVAL, || {
+        ic_cdk::spawn(refund_failed_collections());
+        ic_cdk::spawn(complete_cancellations());
+    });
+}
+
+///
+/// Returns if the funds raised by a collection reached his funding goal, a collection without goal always reaches it
+///
+/// ## Arguments
+/// * `collection` - info of the collection
+///
+pub fn goal_reached(collection: &CollectionInfo) -> bool {
+    match &collection.funding_goal {
+        Some(goal) => collection.raised >= *goal,
+        None => true,
+    }
+}
+
+///
+/// Adds the price of a primary sale to the funds raised by a collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `amount` - price paid by the buyer
+///
+pub fn add_raised(collection_id: Principal, amount: u128) {
+    if let Some(mut collection) = get_collections().get(&collection_id).cloned() {
+        collection.raised += Nat::from(amount);
+        insert_collection_record(collection_id, collection);
+    }
+}
+
+///
+/// Pays to the organizer the funds held in escrow by an all-or-nothing collection,
+/// possible only after the expire date and if the funding goal has been reached.
+/// The platform fee is taken on the total amount raised.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal requesting the withdrawal, it must be an owner or a member of the finance team
+///
+/// ## Returns
+/// * `Ok`: description of the payments made or scheduled
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn withdraw_raised(collection_id: Principal, caller: Principal) -> Result<String, String> {
+    let ledger_fee = fee(ledger_of(&collection_id)).await?;
+
+    let mut collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Finance) {
+        return Err("only the owners and the finance team can withdraw the funds".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if collection.settlement != SettlementMode::AllOrNothing {
+        return Err("the funds of the collection are not held in escrow".to_string());
+    }
+    if !collection.milestones.is_empty() {
+        return Err("the funds of the collection are released by milestones".to_string());
+    }
+    if collection.funding_status != FundingStatus::Open {
+        return Err(format!("funds already {:?}", collection.funding_status));
+    }
+    if collection.expire_date > ic_cdk::api::time() {
+        return Err("funds can be withdrawn only after the expire date".to_string());
+    }
+    if !goal_reached(&collection) {
+        return Err("funding goal not reached, the buyers will be refunded".to_string());
+    }
+    let raised = u128::try_from(&collection.raised.0).map_err(|_| "amount raised too big".to_string())?;
+
+    // the status is saved before paying so that the funds cannot be withdrawn twice
+    collection.funding_status = FundingStatus::Withdrawn;
+    insert_collection_record(collection_id, collection.clone());
+    for (purchase_id, mut purchase) in get_purchases().into_iter().filter(|x| x.1.collection_id == collection_id && x.1.escrowed) {
+        purchase.escrowed = false;
+        update_purchase_record(purchase_id, purchase);
+    }
+
+    let mut res = Vec::new();
+    for (kind, to, amount) in split_proceeds(raised, collection.owner, &collection, &get_platform_fee(), ledger_fee) {
+        match pay_from_escrow(collection_id, to, Nat::from(amount.saturating_sub(ledger_fee)), kind, None).await {
+            Ok(block) => res.push(format!("{:?} to {} on block {}", kind, to, block)),
+            Err(payout_id) => res.push(format!("{:?} to {} will be retried with payout id {}", kind, to, payout_id)),
+        }
+    }
+    Ok(res.join(", "))
+}
+
+///
+/// Refunds every purchase of a collection whose payment is still held in escrow, burning the NFT bought.
+/// The funds not released to the organizer are refunded pro-rata to the holders of the NFTs.
+/// The refund is sent only after the NFT has been burned, the purchases whose NFT could not be burned are left in escrow.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+/// ## Returns
+/// * `Ok`: number of purchases refunded
+/// * `Error`: String with the errors of the NFTs that could not be burned
+///
+pub async fn refund_escrowed_purchases(collection_id: Principal) -> Result<usize, String> {
+    let ledger_fee = fee(ledger_of(&collection_id)).await?;
+    let collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    let available = unreleased(&collection);
+
+    let mut purchases = get_purchases()
+        .into_iter()
+        .filter(|x| x.1.collection_id == collection_id && x.1.escrowed)
+        .collect::<Vec<_>>();
+    purchases.sort_by_key(|x| x.0);
+
+    let mut refunded = 0;
+    let mut errors = Vec::new();
+    for (purchase_id, _) in purchases {
+        // the purchase is read again and taken out of the escrow before any call,
+        // so that a concurrent run cannot refund it twice
+        let mut purchase = match get_purchase(purchase_id) {
+            Some(x) if x.escrowed => x,
+            _ => continue,
+        };
+        purchase.escrowed = false;
+        update_purchase_record(purchase_id, purchase.clone());
+
+        let holder = get_nfts()
+            .get(&OwnersDoubleKey { collection_id, tkn_id: purchase.tkn_id })
+            .map(|x| x.owner);
+        if let Some(holder) = holder {
+            if let Err(e) = burn(collection_id, purchase.tkn_id, holder).await {
+                if let Some(mut purchase) = get_purchase(purchase_id) {
+                    purchase.escrowed = true;
+                    update_purchase_record(purchase_id, purchase);
+                }
+                errors.push(e);
+                continue;
+            }
+            remove_nft_record(collection_id, purchase.tkn_id);
+        }
+        refunded += 1;
+
+        let share = if collection.raised > 0u32 {
+            purchase.amount.clone() * available.clone() / collection.raised.clone()
+        } else {
+            purchase.amount.clone()
+        };
+        let amount = net_of_fee(&share, ledger_fee);
+        if amount == 0u32 {
+            continue;
+        }
+        if let Ok(block) = pay_from_escrow(collection_id, holder.unwrap_or(purchase.buyer), amount, PayoutKind::Refund, Some(purchase_id)).await {
+            purchase.refund_block = Some(block);
+            update_purchase_record(purchase_id, purchase);
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors.join(", "));
+    }
+    Ok(refunded)
+}
+
+///
+/// Refunds the buyers of the all-or-nothing collections expired without reaching their funding goal,
+/// the collections whose refunds are completed are marked as refunded.
+///
+pub async fn refund_failed_collections() {
+    let _guard = match LockGuard::acquire(&REFUNDING) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let now = ic_cdk::api::time();
+    let failed = get_collections()
+        .into_iter()
+        .filter(|x| x.1.settlement == SettlementMode::AllOrNothing
+            && x.1.status == CollectionStatus::Active
+            && x.1.funding_status == FundingStatus::Open
+            && x.1.expire_date <= now
+            && !goal_reached(&x.1))
+        .map(|x| x.0)
+        .collect::<Vec<Principal>>();
+
+    for collection_id in failed {
+        match refund_escrowed_purchases(collection_id).await {
+            Ok(_) => {
+                if let Some(mut collection) = get_collections().get(&collection_id).cloned() {
+                    collection.funding_status = FundingStatus::Refunded;
+                    insert_collection_record(collection_id, collection);
+                }
+            },
+            Err(e) => ic_cdk::println!("refunds of collection {} not completed: {}", collection_id, e),
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use candid::Principal;
+
+use crate::collection::set_metadata;
+use crate::common::structures::{CollectionChange, CollectionChangeKind, CollectionInfo, CollectionStatus, DiscountWindowArg, Role};
+use crate::memory::{get_collection, get_collection_changes, insert_collection_change, insert_collection_record};
+use crate::roles::has_role;
+use crate::search::MAX_DESCRIPTION_LENGTH;
+
+/// Maximum size in bytes of the description and of the logo of a collection together
+pub const MAX_METADATA_SIZE: usize = 16 * 1024;
+
+/// Maximum size in bytes of the logo of a collection, the space left by the longest description
+pub const MAX_LOGO_SIZE: usize = MAX_METADATA_SIZE - MAX_DESCRIPTION_LENGTH;
+
+/// Maximum number of discount windows of a collection
+pub const MAX_DISCOUNT_WINDOWS: usize = 20;
+
+///
+/// Gets a collection that can still be edited, checking that the caller is an owner or a manager
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+/// ## Returns
+/// * `Ok`: info of the collection
+/// * `Error`: collection not existing, not owned by the caller, cancelled or expired
+///
+fn get_editable(collection_id: Principal, caller: Principal) -> Result<CollectionInfo, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Manager) {
+        return Err("only the owners and the managers can edit the collection".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if collection.expire_date <= ic_cdk::api::time() {
+        return Err("collection Expired".to_string());
+    }
+    Ok(collection)
+}
+
+///
+/// Saves a change made on a collection in its audit trail
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal that made the change
+/// * `change` - change made on the collection
+///
+pub fn record(collection_id: Principal, caller: Principal, change: CollectionChangeKind) {
+    insert_collection_change(CollectionChange {
+        collection_id,
+        caller,
+        change,
+        timestamp: ic_cdk::api::time(),
+    });
+}
+
+///
+/// Extends the expire date of a collection, the expire date can only be postponed
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `expire_date` - new expire date of the collection
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn extend_expire_date(collection_id: Principal, caller: Principal, expire_date: u64) -> Result<String, String> {
+    let mut collection = get_editable(collection_id, caller)?;
+    if expire_date <= collection.expire_date {
+        return Err("the expire date can only be extended".to_string());
+    }
+
+    let from = collection.expire_date;
+    collection.expire_date = expire_date;
+    insert_collection_record(collection_id, collection);
+    record(collection_id, caller, CollectionChangeKind::ExpireDate { from, to: expire_date });
+    Ok(format!("expire date of the collection extended to {}", expire_date))
+}
+
+///
+/// Adds a discount window to a collection, checked like the discount windows passed at creation
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `window` - discount window to add
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn add_discount_window(collection_id: Principal, caller: Principal, window: DiscountWindowArg) -> Result<String, String> {
+    let mut collection = get_editable(collection_id, caller)?;
+    if collection.dutch_pricing.is_some() {
+        return Err("descending price cannot be used together with discount windows".to_string());
+    }
+    if window.expire_date >= collection.expire_date || window.expire_date <= ic_cdk::api::time() {
+        return Err("Error: discount windows date cannot be in the past or it cannot be after the expire date".to_string());
+    }
+    if window.discount_percentage > 100 {
+        return Err("Error: discount percentage cannot be higher than 100".to_string());
+    }
+    if collection.discount_windows.iter().any(|x| x.expire_date == window.expire_date) {
+        return Err("a discount window with the same expire date already exists".to_string());
+    }
+    if collection.discount_windows.len() >= MAX_DISCOUNT_WINDOWS {
+        return Err(format!("a collection cannot have more than {} discount windows", MAX_DISCOUNT_WINDOWS));
+    }
+
+    collection.discount_windows.push(window.clone());
+    insert_collection_record(collection_id, collection);
+    record(collection_id, caller, CollectionChangeKind::DiscountWindowAdded(window));
+    Ok("discount window added".to_string())
+}
+
+///
+/// Removes a discount window not expired yet from a collection, the expired ones are kept
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `expire_date` - expire date of the discount window to remove
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn remove_discount_window(collection_id: Principal, caller: Principal, expire_date: u64) -> Result<String, String> {
+    let mut collection = get_editable(collection_id, caller)?;
+    let window = match collection.discount_windows.iter().position(|x| x.expire_date == expire_date) {
+        Some(_) if expire_date <= ic_cdk::api::time() => return Err("discount window already expired".to_string()),
+        Some(index) => collection.discount_windows.remove(index),
+        None => return Err("discount window does not exists".to_string()),
+    };
+
+    insert_collection_record(collection_id, collection);
+    record(collection_id, caller, CollectionChangeKind::DiscountWindowRemoved(window));
+    Ok("discount window removed".to_string())
+}
+
+///
+/// Updates the description and the logo of a collection on its canister, the fields passed as none are not changed
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `description` - new description of the collection
+/// * `logo` - new logo of the collection
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn update_metadata(collection_id: Principal, caller: Principal, description: Option<String>, logo: Option<String>) -> Result<String, String> {
+    let mut collection = get_editable(collection_id, caller)?;
+    if description.is_none() && logo.is_none() {
+        return Err("nothing to update".to_string());
+    }
+    // each field is bounded on its own, so the metadata stays within MAX_METADATA_SIZE
+    // whatever the field already stored on the canister
+    if description.as_ref().is_some_and(|x| x.len() > MAX_DESCRIPTION_LENGTH) {
+        return Err(format!("description cannot be longer than {} bytes", MAX_DESCRIPTION_LENGTH));
+    }
+    if logo.as_ref().is_some_and(|x| x.len() > MAX_LOGO_SIZE) {
+        return Err(format!("logo cannot be bigger than {} bytes", MAX_LOGO_SIZE));
+    }
+
+    set_metadata(collection_id, description.clone(), logo.clone()).await?;
+    // the description is also saved on the marketplace for the search
+    if description.is_some() {
+        collection = get_collection(&collection_id).unwrap_or(collection);
+        collection.description = description.clone();
+        insert_collection_record(collection_id, collection);
+    }
+    record(collection_id, caller, CollectionChangeKind::Metadata { description, logo });
+    Ok("metadata of the collection updated".to_string())
+}
+
+///
+/// Changes made on a collection after its creation
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+pub fn changes(collection_id: Principal) -> Vec<CollectionChange> {
+    get_collection_changes(collection_id).into_iter().map(|x| x.1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Nat;
+    use ic_stable_structures::{StableBTreeMap, Storable, VectorMemory};
+
+    use super::*;
+    use crate::common::structures::{CollectionNfts, Milestone, MilestoneAmount, MilestoneStatus, NftMetadata};
+    use crate::milestones::{MAX_MILESTONES, MAX_MILESTONE_DESCRIPTION};
+    use crate::search::{MAX_TAGS, MAX_TAG_LENGTH};
+
+    /// Collection with every bounded field at its maximum, the quotes double the size of the texts once serialized
+    fn largest_collection() -> CollectionInfo {
+        let tier = CollectionNfts {
+            nft: NftMetadata {
+                token_name: "\"".repeat(64),
+                token_privilege_code: u8::MAX,
+                token_description: "\"".repeat(MAX_DESCRIPTION_LENGTH),
+                quantity: u64::MAX,
+                token_logo: "x".repeat(MAX_LOGO_SIZE),
+                price: Nat::from(u128::MAX),
+                sale_start: Some(u64::MAX),
+                sale_end: Some(u64::MAX),
+                soulbound: Some(true),
+            },
+            tkn_ids: (1..=1_000).collect(),
+            sold: u64::MAX,
+            reserved: u64::MAX,
+        };
+        let milestone = Milestone {
+            amount: MilestoneAmount::Amount(Nat::from(u128::MAX)),
+            deadline: u64::MAX,
+            description: "\"".repeat(MAX_MILESTONE_DESCRIPTION),
+            status: MilestoneStatus::Released,
+            requested_at: Some(u64::MAX),
+            released_amount: Some(Nat::from(u128::MAX)),
+            release_block: Some(Nat::from(u128::MAX)),
+        };
+        CollectionInfo {
+            expire_date: u64::MAX,
+            discount_windows: (0..MAX_DISCOUNT_WINDOWS as u64).map(|x| DiscountWindowArg { expire_date: u64::MAX - x, discount_percentage: 100 }).collect(),
+            nfts: vec![tier; 4],
+            royalty_bps: u16::MAX,
+            funding_goal: Some(Nat::from(u128::MAX)),
+            raised: Nat::from(u128::MAX),
+            milestones: vec![milestone; MAX_MILESTONES],
+            released: Nat::from(u128::MAX),
+            next_tkn_id: u64::MAX,
+            presale_end: Some(u64::MAX),
+            max_per_wallet: Some(u32::MAX),
+            name: "\"".repeat(MAX_DESCRIPTION_LENGTH),
+            description: Some("\"".repeat(MAX_DESCRIPTION_LENGTH)),
+            tags: (0..MAX_TAGS).map(|x| format!("{:0>1$}", x, MAX_TAG_LENGTH)).collect(),
+            created_at: u64::MAX,
+            attendance_collection: Some(Principal::anonymous()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn collections_with_every_field_at_its_maximum_are_stored() {
+        let collection = largest_collection();
+
+        let mut collections = StableBTreeMap::<u64, CollectionInfo, VectorMemory>::new(VectorMemory::default());
+        collections.insert(0, collection.clone());
+        assert_eq!(collections.get(&0).unwrap().to_bytes(), collection.to_bytes());
+    }
+}
@@ -1,8 +1,8 @@
 use crate::common::structures::CanisterArg;
 use crate::common::structures::InitArg;
-use candid::Encode;
+use candid::{Encode, Principal};
 use ic_cdk::api::management_canister::{
-    main::{create_canister, install_code, CreateCanisterArgument, InstallCodeArgument},
+    main::{create_canister, delete_canister, install_code, stop_canister, CanisterIdRecord, CreateCanisterArgument, InstallCodeArgument},
     provisional::CanisterSettings,
 };
 use icrc_ledger_types::icrc1::account::Account;
@@ -11,36 +11,53 @@ pub const ICRC7_WASM: &[u8] = std::include_bytes!("../../../wasm_files/icrc7.was
 
 
 ///
-/// Creates a collection of nft using the ICRC-7 standard and saves in database the principal of the owner of the colletion and the id of the canister collection.
+/// Encodes the init arg of a collection canister, the owner of the collection is his minting account.
 ///
 /// ## Arguments
-/// *   icrc7_supply_cap : opt nat;
-/// *   icrc7_description : opt text;
-/// *   tx_window : opt nat64;
-/// *   icrc7_max_query_batch_size : opt nat;
-/// *   permitted_drift : opt nat64;
-/// *   icrc7_max_take_value : opt nat;
-/// *   icrc7_max_memo_size : opt nat;
-/// *   icrc7_symbol : text;
-/// *   icrc7_max_update_batch_size : opt nat;
-/// *   icrc7_atomic_batch_transfers : opt bool;
-/// *   icrc7_default_take_value : opt nat;
-/// *   icrc7_logo : opt text;
-/// *   icrc7_name : text;
+/// * `arg`:
+///     *   icrc7_supply_cap : opt nat;
+///     *   icrc7_description : opt text;
+///     *   tx_window : opt nat64;
+///     *   icrc7_max_query_batch_size : opt nat;
+///     *   permitted_drift : opt nat64;
+///     *   icrc7_max_take_value : opt nat;
+///     *   icrc7_max_memo_size : opt nat;
+///     *   icrc7_symbol : text;
+///     *   icrc7_max_update_batch_size : opt nat;
+///     *   icrc7_atomic_batch_transfers : opt bool;
+///     *   icrc7_default_take_value : opt nat;
+///     *   icrc7_logo : opt text;
+///     *   icrc7_name : text;
+/// * `owner` - owner of the collection
+/// * `royalty_bps` - royalties on secondary sales in basis points, exposed as `icrc7:royalties` metadata
 ///
 /// ## Returns
-/// * canister id of the collection
-/// 
-pub async fn mint_collection_canister(arg: CanisterArg) -> Result<String, String> {
-    let caller = ic_cdk::caller();
+/// * candid encoded init arg
+///
+pub fn encode_init_arg(arg: CanisterArg, owner: Principal, royalty_bps: u16) -> Vec<u8> {
     let account = Account {
-        owner: caller,
+        owner,
         subaccount: None,
     };
-    let principal = match create_canister(
+    let mut init_arg = InitArg::from((account, arg));
+    init_arg.icrc7_royalties = Some(royalty_bps);
+    Encode!(&init_arg).unwrap()
+}
+
+///
+/// Creates an empty canister for a collection, controlled by the backend and by the owner of the collection
+///
+/// ## Arguments
+/// * `owner` - owner of the collection
+///
+/// ## Returns
+/// * canister id of the collection
+///
+pub async fn create_collection_canister(owner: Principal) -> Result<Principal, String> {
+    match create_canister(
         CreateCanisterArgument {
             settings: Some(CanisterSettings {
-                controllers: Some(vec![ic_cdk::id(), caller]),
+                controllers: Some(vec![ic_cdk::id(), owner]),
                 compute_allocation: None,
                 memory_allocation: None,
                 freezing_threshold: None,
@@ -51,20 +68,43 @@ pub async fn mint_collection_canister(arg: CanisterArg) -> Result<String, String
     )
     .await
     {
-        Err((code, msg)) => return Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
-        Ok((principal,)) => principal.canister_id,
-    };
-    let init_arg = InitArg::from((account, arg));
-    let init_arg = Encode!(&init_arg).unwrap();
+        Err((code, msg)) => Err(format!("Rejection Code: {:?}, Message: {:?}", code, msg)),
+        Ok((principal,)) => Ok(principal.canister_id),
+    }
+}
+
+///
+/// Installs the ICRC-7 code on a collection canister
+///
+/// ## Arguments
+/// * `canister_id` - canister id of the collection
+/// * `init_arg` - candid encoded init arg of the collection
+///
+pub async fn install_collection_code(canister_id: Principal, init_arg: Vec<u8>) -> Result<(), String> {
     match install_code(InstallCodeArgument {
         mode: ic_cdk::api::management_canister::main::CanisterInstallMode::Install,
-        canister_id: principal,
+        canister_id,
         wasm_module: ICRC7_WASM.to_vec(),
         arg: init_arg,
     })
     .await
     {
-        Ok(()) => Ok(principal.to_string()),
+        Ok(()) => Ok(()),
         Err((code, msg)) => Err(format!("Code: {:?}, Message: {:?}", code, msg)),
     }
 }
+
+///
+/// Stops and deletes the canister of a collection whose creation has been rolled back
+///
+/// ## Arguments
+/// * `canister_id` - canister id of the collection
+///
+pub async fn delete_collection_canister(canister_id: Principal) -> Result<(), String> {
+    stop_canister(CanisterIdRecord { canister_id })
+        .await
+        .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))?;
+    delete_canister(CanisterIdRecord { canister_id })
+        .await
+        .map_err(|(code, msg)| format!("Rejection Code: {:?}, Message: {:?}", code, msg))
+}
@@ -0,0 +1,27 @@
+use crate::auctions::start_auctions_timer;
+use crate::checkin::start_challenge_key;
+use crate::crowdfunding::start_funding_timer;
+use crate::memory::migrate_storage;
+use crate::offers::start_offers_timer;
+use crate::payouts::start_payouts_timer;
+
+#[ic_cdk::init]
+pub fn init() {
+    migrate_storage();
+    start_payouts_timer();
+    start_funding_timer();
+    start_auctions_timer();
+    start_offers_timer();
+    start_challenge_key();
+}
+
+// Timers are not persisted across upgrades, so they need to be started again
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    migrate_storage();
+    start_payouts_timer();
+    start_funding_timer();
+    start_auctions_timer();
+    start_offers_timer();
+    start_challenge_key();
+}
@@ -0,0 +1,179 @@
+use candid::{Nat, Principal};
+use ic_ledger_types::MAINNET_LEDGER_CANISTER_ID;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::{BlockIndex, Memo, TransferArg, TransferError as TransferErrorICRC};
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
+
+use crate::memory::get_collections;
+
+///
+/// Ledger of the token used to pay the NFTs of a collection, the ICP ledger if the collection is not found
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+pub fn ledger_of(collection_id: &Principal) -> Principal {
+    get_collections()
+        .get(collection_id)
+        .map_or(MAINNET_LEDGER_CANISTER_ID, |x| x.token.ledger)
+}
+
+///
+/// Subaccount of the backend canister used as escrow for the payments of a collection,
+/// every collection has his own escrow so that the funds can be audited separately on the ledger
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+/// ## Returns
+/// * subaccount composed by the length of the principal followed by the principal bytes
+///
+pub fn escrow_subaccount(collection_id: &Principal) -> Subaccount {
+    let mut subaccount = [0; 32];
+    let bytes = collection_id.as_slice();
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..1 + bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+///
+/// Account of the backend canister holding the escrowed payments of a collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+pub fn escrow_account(collection_id: &Principal) -> Account {
+    Account {
+        owner: ic_cdk::id(),
+        subaccount: Some(escrow_subaccount(collection_id)),
+    }
+}
+
+///
+/// Transfer amount of tokens from an account of the backend canister to another account
+///
+/// ## Arguments
+/// * `ledger` - ledger canister of the token
+/// * `amount` - amount of tokens to transfer, the fee is paid by the sender on top of it
+/// * `from_subaccount` - subaccount of the backend canister from which the tokens are taken
+/// * `to` - account to which the tokens will be transferred
+/// * `created_at_time` - creation time of the transfer, used by the ledger to deduplicate it
+/// * `memo` - memo of the transfer, used by the ledger to deduplicate it
+///
+/// ## Returns
+/// * `Ok`: Block index of the transaction on the ledger, the block of the first transfer if it is a duplicate
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn transfer(ledger: Principal, amount: Nat, from_subaccount: Option<Subaccount>, to: Account, created_at_time: Option<u64>, memo: Option<Memo>) -> Result<BlockIndex, String> {
+
+    ic_cdk::println!(
+        "Transferring {} tokens to account {}",
+        amount,
+        to,
+    );
+
+    let transfer_args: TransferArg = TransferArg {
+        memo,
+        amount,
+        from_subaccount,
+        fee: None,
+        to,
+        created_at_time,
+    };
+
+    match ic_cdk::call::<(TransferArg,), (Result<BlockIndex, TransferErrorICRC>,)>
+        ( ledger, "icrc1_transfer", (transfer_args,),)
+            .await
+            .map_err(|e| format!("failed to call ledger: {:?}", e))?
+            .0
+    {
+        Ok(block) => Ok(block),
+        // an earlier attempt has been executed even if its call failed
+        Err(TransferErrorICRC::Duplicate { duplicate_of }) => Ok(duplicate_of),
+        Err(e) => Err(format!("ledger transfer error {:?}", e)),
+    }
+}
+
+///
+/// Transfer amount of tokens on behalf of an account using the ICRC-2 standard,
+/// before calling this function the owner of `from` needs to approve the amount + the transaction fee to this backend canister
+///
+/// ## Arguments
+/// * `ledger` - ledger canister of the token
+/// * `amount` - amount of tokens to transfer
+/// * `from` - account from which the tokens are taken
+/// * `to` - account to which the tokens will be transferred
+///
+/// ## Returns
+/// * `Ok`: Block index of the transaction on the ledger
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn transfer_from(ledger: Principal, amount: Nat, from: Account, to: Account) -> Result<BlockIndex, String> {
+
+    ic_cdk::println!(
+        "Transferring {} tokens from account {} to account {}",
+        amount,
+        from,
+        to,
+    );
+
+    let transfer_from_args = TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount,
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    ic_cdk::call::<(TransferFromArgs,), (Result<BlockIndex, TransferFromError>,)>
+        ( ledger, "icrc2_transfer_from", (transfer_from_args,),)
+            .await
+            .map_err(|e| format!("failed to call ledger: {:?}", e))?
+            .0
+            .map_err(|e| format!("ledger transfer from error {:?}", e))
+}
+
+///
+/// Gets the balance of an account
+///
+/// ## Arguments
+/// * `ledger` - ledger canister of the token
+/// * `account` - account to check
+///
+pub async fn balance_of(ledger: Principal, account: Account) -> Result<u128, String> {
+
+    Ok(ic_cdk::call::<(Account,), (u128,)>
+        ( ledger, "icrc1_balance_of", (account,) )
+        .await
+        .map_err(|e| format!("failed to call ledger: {:?}", e))?.0)
+}
+
+///
+/// Gets the fee of a transaction on the ledger
+///
+/// ## Arguments
+/// * `ledger` - ledger canister of the token
+///
+pub async fn fee(ledger: Principal) -> Result<u128, String> {
+
+    Ok(ic_cdk::call::<(), (u128,)>
+        ( ledger, "icrc1_fee", () )
+        .await
+        .map_err(|e| format!("failed to call ledger: {:?}", e))?.0)
+}
+
+///
+/// Gets the number of decimals of the token of the ledger
+///
+/// ## Arguments
+/// * `ledger` - ledger canister of the token
+///
+pub async fn decimals(ledger: Principal) -> Result<u8, String> {
+
+    Ok(ic_cdk::call::<(), (u8,)>
+        ( ledger, "icrc1_decimals", () )
+        .await
+        .map_err(|e| format!("failed to call ledger: {:?}", e))?.0)
+}
@@ -1,5 +1,24 @@
 pub mod common;
 pub mod memory;
 pub mod factory;
+pub mod creation;
+pub mod editing;
+pub mod checkin;
+pub mod collection;
+pub mod ledger;
+pub mod payouts;
+pub mod crowdfunding;
+pub mod milestones;
+pub mod cancellation;
+pub mod auctions;
+pub mod attendance;
+pub mod offers;
+pub mod minting;
+pub mod presale;
+pub mod privileges;
+pub mod roles;
+pub mod search;
+pub mod tiers;
+pub mod init_method;
 pub mod query_methods;
 pub mod update_methods;
\ No newline at end of file
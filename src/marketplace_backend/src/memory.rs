@@ -1,13 +1,24 @@
-use candid::Principal;
+use candid::{Nat, Principal};
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
-use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap};
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell};
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::thread::LocalKey;
+use std::collections::{HashMap, HashSet};
 
-use crate::common::structures::{CollectionInfo, NftMarketData, OwnersDoubleKey};
+use crate::common::structures::{AllowlistKey, AttendanceJob, AttendanceReward, Auction, BuyerPurchaseKey, CollectionChange, CollectionIndexKey, CollectionInfo, CollectionSort, CollectionTextKey, CreationJob, MilestoneApprovalKey, NftMarketData, NftOffers, OwnersDoubleKey, PayoutKind, PendingPayout, PerkClaimKey, PlatformFee, Privilege, PrivilegeKey, PurchaseRecord, Role, RoleKey};
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 
+/// Version of the layout of the records saved on the stable memory
+/// * `1` - prices of the NFTs stored as `Nat` instead of `u32`
+/// * `2` - number of NFTs sold by the organizer saved on every tier of the collections
+/// * `3` - index of the purchases by buyer and refunded flag of the purchases
+/// * `4` - indexes of the collections used by the search
+pub const STORAGE_VERSION: u32 = 4;
+
+type CollectionIndex = RefCell<StableBTreeMap<CollectionIndexKey, (), Memory>>;
+type CollectionTextIndex = RefCell<StableBTreeMap<CollectionTextKey, (), Memory>>;
+
 // Creation of the datasets stored on the stable memory of ICP
 thread_local! {
 
@@ -25,6 +36,167 @@ thread_local! {
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
         )
     });
+
+    static PURCHASES: RefCell<StableBTreeMap<u64, PurchaseRecord, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+        )
+    });
+
+    static PAYOUTS: RefCell<StableBTreeMap<u64, PendingPayout, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
+        )
+    });
+
+    // incremented for every payment from an escrow, it makes the memo of the transfer unique
+    static PAYMENT_NONCE: RefCell<StableCell<u64, Memory>> = RefCell::new({
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+            0
+        ).expect("failed to init the payment nonce")
+    });
+
+    static PLATFORM_FEE: RefCell<StableCell<PlatformFee, Memory>> = RefCell::new({
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5))),
+            PlatformFee::default()
+        ).expect("failed to init the platform fee")
+    });
+
+    static MILESTONE_APPROVALS: RefCell<StableBTreeMap<MilestoneApprovalKey, u64, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+        )
+    });
+
+    static AUCTIONS: RefCell<StableBTreeMap<OwnersDoubleKey, Auction, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+        )
+    });
+
+    static OFFERS: RefCell<StableBTreeMap<OwnersDoubleKey, NftOffers, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8)))
+        )
+    });
+
+    // tier of every NFT minted at purchase time
+    static TIER_TOKENS: RefCell<StableBTreeMap<OwnersDoubleKey, u32, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+        )
+    });
+
+    static CREATION_JOBS: RefCell<StableBTreeMap<u64, CreationJob, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+        )
+    });
+
+    static COLLECTION_CHANGES: RefCell<StableBTreeMap<u64, CollectionChange, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+        )
+    });
+
+    static ALLOWLISTS: RefCell<StableBTreeMap<AllowlistKey, u64, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(13)))
+        )
+    });
+
+    static PURCHASES_BY_BUYER: RefCell<StableBTreeMap<BuyerPurchaseKey, (), Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(14)))
+        )
+    });
+
+    static ROLES: RefCell<StableBTreeMap<RoleKey, Role, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(15)))
+        )
+    });
+
+    // indexes of the collections, the stable btreemap cannot be iterated backwards
+    // so the descending orders are saved as the complement of the value
+    static COLLECTIONS_BY_EXPIRY: CollectionIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(16)))
+        )
+    });
+
+    static COLLECTIONS_BY_NEWEST: CollectionIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(17)))
+        )
+    });
+
+    static COLLECTIONS_BY_PRICE: CollectionIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(18)))
+        )
+    });
+
+    static COLLECTIONS_BY_PRICE_DESC: CollectionIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(19)))
+        )
+    });
+
+    static COLLECTIONS_BY_OWNER: CollectionTextIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(20)))
+        )
+    });
+
+    static COLLECTIONS_BY_TAG: CollectionTextIndex = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(21)))
+        )
+    });
+
+    // secret key of the authentication codes of the check-in challenges, empty until it is generated
+    static CHALLENGE_KEY: RefCell<StableCell<Vec<u8>, Memory>> = RefCell::new({
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(22))),
+            Vec::new()
+        ).expect("failed to init the challenge key")
+    });
+
+    static PRIVILEGES: RefCell<StableBTreeMap<PrivilegeKey, Privilege, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(23)))
+        )
+    });
+
+    static PERK_CLAIMS: RefCell<StableBTreeMap<PerkClaimKey, u32, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(24)))
+        )
+    });
+
+    static ATTENDANCE_JOBS: RefCell<StableBTreeMap<Principal, AttendanceJob, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(25)))
+        )
+    });
+
+    // tickets checked in rewarded with a proof of attendance, with the holder rewarded
+    static ATTENDANCE_REWARDS: RefCell<StableBTreeMap<OwnersDoubleKey, AttendanceReward, Memory>> = RefCell::new({
+        StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(26)))
+        )
+    });
+
+    // the canisters installed before the versioning of the stable memory start from version 0
+    static VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new({
+        StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9))),
+            0
+        ).expect("failed to init the storage version")
+    });
 }
 
 ///
@@ -68,7 +240,129 @@ pub fn get_collections() -> HashMap<Principal, CollectionInfo> {
 /// 
 pub fn insert_collection_record(canister: Principal, collection_info: CollectionInfo) {
     
-    COLLECTIONS.with(|x| x.borrow_mut().insert(canister, collection_info));
+    let previous = COLLECTIONS.with(|x| x.borrow_mut().insert(canister, collection_info.clone()));
+    if let Some(previous) = previous {
+        index_collection(canister, &previous, false);
+    }
+    index_collection(canister, &collection_info, true);
+}
+
+///
+/// Gets the info of a collection
+/// 
+/// ## Arguments
+/// * `canister` - collection canister id
+/// 
+pub fn get_collection(canister: &Principal) -> Option<CollectionInfo> {
+
+    COLLECTIONS.with(|x| x.borrow().get(canister))
+}
+
+///
+/// Adds or removes a collection from the indexes used by the search
+/// 
+/// ## Arguments
+/// * `canister` - collection canister id
+/// * `collection_info` - infos of the collection
+/// * `add` - true to add the collection, false to remove it
+/// 
+fn index_collection(canister: Principal, collection_info: &CollectionInfo, add: bool) {
+
+    let floor_price = u128::try_from(&collection_info.floor_price().0).unwrap_or(u128::MAX);
+    let numbers: [(&'static LocalKey<CollectionIndex>, Nat); 4] = [
+        (&COLLECTIONS_BY_EXPIRY, Nat::from(collection_info.expire_date)),
+        (&COLLECTIONS_BY_NEWEST, Nat::from(u64::MAX - collection_info.created_at)),
+        (&COLLECTIONS_BY_PRICE, Nat::from(floor_price)),
+        (&COLLECTIONS_BY_PRICE_DESC, Nat::from(u128::MAX - floor_price)),
+    ];
+    for (index, value) in numbers {
+        let key = CollectionIndexKey { value, collection_id: canister };
+        index.with(|x| if add { x.borrow_mut().insert(key, ()); } else { x.borrow_mut().remove(&key); });
+    }
+
+    let texts = std::iter::once((&COLLECTIONS_BY_OWNER, collection_info.owner.to_text()))
+        .chain(collection_info.tags.iter().map(|x| (&COLLECTIONS_BY_TAG, x.clone())));
+    for (index, value) in texts {
+        let key = CollectionTextKey { value, collection_id: canister };
+        index.with(|x| if add { x.borrow_mut().insert(key, ()); } else { x.borrow_mut().remove(&key); });
+    }
+}
+
+///
+/// Gets the collections of an organizer using the index of the owners
+/// 
+/// ## Arguments
+/// * `owner` - organizer of the collections
+/// 
+pub fn get_collections_by_owner(owner: Principal) -> Vec<Principal> {
+
+    text_index_range(&COLLECTIONS_BY_OWNER, owner.to_text())
+}
+
+///
+/// Gets the collections having a tag using the index of the tags
+/// 
+/// ## Arguments
+/// * `tag` - category tag
+/// 
+pub fn get_collections_by_tag(tag: String) -> Vec<Principal> {
+
+    text_index_range(&COLLECTIONS_BY_TAG, tag)
+}
+
+///
+/// Gets the collections saved on a text index with a value
+/// 
+/// ## Arguments
+/// * `index` - index of the collections
+/// * `value` - value searched
+/// 
+fn text_index_range(index: &'static LocalKey<CollectionTextIndex>, value: String) -> Vec<Principal> {
+
+    // the management canister is the lowest principal
+    let start = CollectionTextKey { value: value.clone(), collection_id: Principal::management_canister() };
+    index.with(|x| 
+        x
+        .borrow()
+        .range(start..)
+        .take_while(|(k, _)| k.value == value)
+        .map(|(k, _)| k.collection_id)
+        .collect::<Vec<Principal>>()
+    )
+}
+
+///
+/// Gets a page of the collections in the order of an index, reading only the range of the index between the bounds
+/// and stopping as soon as the page is complete
+/// 
+/// ## Arguments
+/// * `sort` - order of the collections
+/// * `start` - lowest value of the index read
+/// * `end` - if some, highest value of the index read
+/// * `filter_map` - returns the item of a collection if it needs to be included
+/// * `offset` - Offset of the first collection included to retrieve
+/// * `limit` - Number of collections to retrieve
+/// 
+pub fn find_collections<T>(sort: CollectionSort, start: Nat, end: Option<Nat>, filter_map: impl Fn(&Principal) -> Option<T>, offset: usize, limit: usize) -> Vec<T> {
+
+    let index = match sort {
+        CollectionSort::ExpireDate => &COLLECTIONS_BY_EXPIRY,
+        CollectionSort::Newest => &COLLECTIONS_BY_NEWEST,
+        CollectionSort::PriceLowToHigh => &COLLECTIONS_BY_PRICE,
+        CollectionSort::PriceHighToLow => &COLLECTIONS_BY_PRICE_DESC,
+    };
+    // the management canister is the lowest principal
+    let start = CollectionIndexKey { value: start, collection_id: Principal::management_canister() };
+    index.with(|x| 
+        x
+        .borrow()
+        .range(start..)
+        .take_while(|(k, _)| end.as_ref().is_none_or(|end| k.value <= *end))
+        .filter_map(|(k, _)| filter_map(&k.collection_id))
+        .skip(offset)
+        .take(limit)
+        .collect::<Vec<T>>()
+    )
 }
 
 ///
@@ -86,7 +380,7 @@ pub fn insert_collection_record(canister: Principal, collection_info: Collection
 /// 
 /// pub struct NftMarketData {
 ///     pub owner: Principal,
-///     pub price: Option<u32>,
+///     pub price: Option<Nat>,
 ///     pub on_sale: bool
 /// }
 /// ```
@@ -106,7 +400,7 @@ pub fn get_nfts() -> HashMap<OwnersDoubleKey, NftMarketData> {
 /// * `price` - Optional of price of the NFT 
 /// * `on_sale` - boolean 
 /// 
-pub fn insert_nft_record(canister: Principal, tkn_id: u64, owner: Principal, price: Option<u32>, on_sale: bool) {
+pub fn insert_nft_record(canister: Principal, tkn_id: u64, owner: Principal, price: Option<Nat>, on_sale: bool) {
     
     NFTS.with(|x| 
         x
@@ -117,3 +411,761 @@ pub fn insert_nft_record(canister: Principal, tkn_id: u64, owner: Principal, pri
         ));
 }
 
+///
+/// Removes an NFT from the database of the marketplace, used when the token is burned
+/// 
+/// ## Arguments
+/// * `canister` - collection canister id as principal
+/// * `tkn_id` - id of the token
+/// 
+pub fn remove_nft_record(canister: Principal, tkn_id: u64) {
+    
+    NFTS.with(|x| x.borrow_mut().remove(&OwnersDoubleKey {collection_id: canister, tkn_id}));
+}
+
+///
+/// Gets hashmap of purchase ids as keys and purchase records as values
+/// 
+/// ## Returns
+/// * HashMap<u64, PurchaseRecord>
+/// 
+pub fn get_purchases() -> HashMap<u64, PurchaseRecord> {
+
+    PURCHASES.with(|x| x.borrow().iter().collect::<HashMap<u64, PurchaseRecord>>())
+}
+
+///
+/// Inserts a new purchase record on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `purchase` - receipt of the purchase
+/// 
+/// ## Returns
+/// * id assigned to the purchase
+/// 
+pub fn insert_purchase_record(purchase: PurchaseRecord) -> u64 {
+
+    let (collection_id, buyer) = (purchase.collection_id, purchase.buyer);
+    let id = PURCHASES.with(|x| {
+        let mut purchases = x.borrow_mut();
+        let id = purchases.last_key_value().map_or(0, |(id, _)| id + 1);
+        purchases.insert(id, purchase);
+        id
+    });
+    PURCHASES_BY_BUYER.with(|x| x.borrow_mut().insert(BuyerPurchaseKey { collection_id, buyer, purchase_id: id }, ()));
+    id
+}
+
+///
+/// Gets the purchases made by a principal on a collection
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `buyer` - principal that made the purchases
+/// 
+/// ## Returns
+/// * purchase ids with the purchases, sorted by id
+/// 
+pub fn get_buyer_purchases(collection_id: Principal, buyer: Principal) -> Vec<(u64, PurchaseRecord)> {
+
+    let start = BuyerPurchaseKey { collection_id, buyer, purchase_id: u64::MIN };
+    let end = BuyerPurchaseKey { collection_id, buyer, purchase_id: u64::MAX };
+    let ids = PURCHASES_BY_BUYER.with(|x| x.borrow().range(start..=end).map(|(k, _)| k.purchase_id).collect::<Vec<u64>>());
+    PURCHASES.with(|x| {
+        let purchases = x.borrow();
+        ids.into_iter().filter_map(|id| purchases.get(&id).map(|p| (id, p))).collect()
+    })
+}
+
+///
+/// Updates an existing purchase record on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `id` - id of the purchase
+/// * `purchase` - updated receipt of the purchase
+/// 
+pub fn update_purchase_record(id: u64, purchase: PurchaseRecord) {
+
+    PURCHASES.with(|x| x.borrow_mut().insert(id, purchase));
+}
+
+///
+/// Gets hashmap of payout ids as keys and payments still owed by the backend as values
+/// 
+/// ## Returns
+/// * HashMap<u64, PendingPayout>
+/// 
+pub fn get_payouts() -> HashMap<u64, PendingPayout> {
+
+    PAYOUTS.with(|x| x.borrow().iter().collect::<HashMap<u64, PendingPayout>>())
+}
+
+///
+/// Inserts a payment to retry on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `payout` - payment owed by the backend
+/// 
+/// ## Returns
+/// * id assigned to the payout
+/// 
+pub fn insert_payout_record(payout: PendingPayout) -> u64 {
+
+    PAYOUTS.with(|x| {
+        let mut payouts = x.borrow_mut();
+        let id = payouts.last_key_value().map_or(0, |(id, _)| id + 1);
+        payouts.insert(id, payout);
+        id
+    })
+}
+
+///
+/// Gets a payment owed by the backend by his id
+/// 
+/// ## Arguments
+/// * `id` - id of the payout
+/// 
+pub fn get_payout(id: u64) -> Option<PendingPayout> {
+
+    PAYOUTS.with(|x| x.borrow().get(&id))
+}
+
+///
+/// Gets a new nonce for a payment from an escrow
+/// 
+pub fn next_payment_nonce() -> u64 {
+
+    PAYMENT_NONCE.with(|x| {
+        let mut nonce = x.borrow_mut();
+        let next = *nonce.get() + 1;
+        nonce.set(next).expect("failed to save the payment nonce");
+        next
+    })
+}
+
+///
+/// Gets the secret key of the authentication codes of the check-in challenges, empty if not generated yet
+/// 
+pub fn get_challenge_key() -> Vec<u8> {
+
+    CHALLENGE_KEY.with(|x| x.borrow().get().clone())
+}
+
+///
+/// Saves the secret key of the authentication codes of the check-in challenges
+/// 
+/// ## Arguments
+/// * `key` - random secret key
+/// 
+pub fn set_challenge_key(key: Vec<u8>) {
+
+    CHALLENGE_KEY.with(|x| x.borrow_mut().set(key).expect("failed to save the challenge key"));
+}
+
+///
+/// Updates a payment to retry on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `id` - id of the payout
+/// * `payout` - updated payment
+/// 
+pub fn update_payout_record(id: u64, payout: PendingPayout) {
+
+    PAYOUTS.with(|x| x.borrow_mut().insert(id, payout));
+}
+
+///
+/// Gets a purchase record by his id
+/// 
+/// ## Arguments
+/// * `id` - id of the purchase
+/// 
+pub fn get_purchase(id: u64) -> Option<PurchaseRecord> {
+
+    PURCHASES.with(|x| x.borrow().get(&id))
+}
+
+///
+/// Gets the fee taken by the platform on every sale
+/// 
+pub fn get_platform_fee() -> PlatformFee {
+
+    PLATFORM_FEE.with(|x| x.borrow().get().clone())
+}
+
+///
+/// Sets the fee taken by the platform on every sale
+/// 
+/// ## Arguments
+/// * `platform_fee` - account and basis points of the fee
+/// 
+pub fn set_platform_fee(platform_fee: PlatformFee) {
+
+    PLATFORM_FEE.with(|x| x.borrow_mut().set(platform_fee).expect("failed to set the platform fee"));
+}
+
+///
+/// Saves the approval of a backer to the release of a milestone
+/// 
+/// ## Arguments
+/// * `key` - collection, milestone and backer approving
+/// * `timestamp` - time of the approval
+/// 
+pub fn insert_milestone_approval(key: MilestoneApprovalKey, timestamp: u64) {
+
+    MILESTONE_APPROVALS.with(|x| x.borrow_mut().insert(key, timestamp));
+}
+
+///
+/// Gets the backers that approved the release of a milestone
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `milestone_id` - index of the milestone in the collection
+/// 
+/// ## Returns
+/// * list of the backers with the time of their approval
+/// 
+pub fn get_milestone_approvals(collection_id: Principal, milestone_id: u32) -> Vec<(Principal, u64)> {
+
+    MILESTONE_APPROVALS.with(|x| 
+        x
+        .borrow()
+        .iter()
+        .filter(|(k, _)| k.collection_id == collection_id && k.milestone_id == milestone_id)
+        .map(|(k, v)| (k.backer, v))
+        .collect::<Vec<(Principal, u64)>>()
+    )
+}
+
+///
+/// Gets hashmap of a pair of collection_id and nft_id as keys and the last auction of the NFT as values
+/// 
+/// ## Returns
+/// * HashMap<OwnersDoubleKey, Auction>
+/// 
+pub fn get_auctions() -> HashMap<OwnersDoubleKey, Auction> {
+
+    AUCTIONS.with(|x| x.borrow().iter().collect::<HashMap<OwnersDoubleKey, Auction>>())
+}
+
+///
+/// Gets the last auction of an NFT
+/// 
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// 
+pub fn get_auction(key: &OwnersDoubleKey) -> Option<Auction> {
+
+    AUCTIONS.with(|x| x.borrow().get(key))
+}
+
+///
+/// Inserts or updates the auction of an NFT on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `auction` - auction of the NFT
+/// 
+pub fn insert_auction_record(key: OwnersDoubleKey, auction: Auction) {
+
+    AUCTIONS.with(|x| x.borrow_mut().insert(key, auction));
+}
+
+///
+/// Gets hashmap of a pair of collection_id and nft_id as keys and the offers made on the NFT as values
+/// 
+/// ## Returns
+/// * HashMap<OwnersDoubleKey, NftOffers>
+/// 
+pub fn get_offers() -> HashMap<OwnersDoubleKey, NftOffers> {
+
+    OFFERS.with(|x| x.borrow().iter().collect::<HashMap<OwnersDoubleKey, NftOffers>>())
+}
+
+///
+/// Gets the offers made on an NFT
+/// 
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// 
+pub fn get_nft_offers(key: &OwnersDoubleKey) -> NftOffers {
+
+    OFFERS.with(|x| x.borrow().get(key).unwrap_or_default())
+}
+
+///
+/// Inserts or updates the offers made on an NFT, the entry is removed when there are no offers left
+/// 
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `offers` - offers made on the NFT
+/// 
+pub fn insert_nft_offers(key: OwnersDoubleKey, offers: NftOffers) {
+
+    OFFERS.with(|x| {
+        if offers.offers.is_empty() {
+            x.borrow_mut().remove(&key);
+        } else {
+            x.borrow_mut().insert(key, offers);
+        }
+    });
+}
+
+///
+/// Saves the tier of an NFT minted at purchase time
+/// 
+/// ## Arguments
+/// * `key` - collection and id of the NFT
+/// * `tier` - index of the tier in the collection
+/// 
+pub fn insert_tier_token(key: OwnersDoubleKey, tier: u32) {
+
+    TIER_TOKENS.with(|x| x.borrow_mut().insert(key, tier));
+}
+
+///
+/// Removes the tier of an NFT that could not be minted
+/// 
+/// ## Arguments
+/// * `key` - collection and id of the NFT
+/// 
+pub fn remove_tier_token(key: &OwnersDoubleKey) {
+
+    TIER_TOKENS.with(|x| x.borrow_mut().remove(key));
+}
+
+///
+/// Gets the tier of an NFT minted at purchase time
+/// 
+/// ## Arguments
+/// * `key` - collection and id of the NFT
+/// 
+pub fn get_tier_token(key: &OwnersDoubleKey) -> Option<u32> {
+
+    TIER_TOKENS.with(|x| x.borrow().get(key))
+}
+
+///
+/// Gets the NFTs of a collection minted at purchase time with their tier
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// 
+pub fn get_tier_tokens(collection_id: Principal) -> Vec<(u64, u32)> {
+
+    let start = OwnersDoubleKey { collection_id, tkn_id: u64::MIN };
+    let end = OwnersDoubleKey { collection_id, tkn_id: u64::MAX };
+    TIER_TOKENS.with(|x| 
+        x
+        .borrow()
+        .range(start..=end)
+        .map(|(k, v)| (k.tkn_id, v))
+        .collect::<Vec<(u64, u32)>>()
+    )
+}
+
+///
+/// Saves a new creation of a collection on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `job` - creation of the collection
+/// 
+/// ## Returns
+/// * id of the creation
+/// 
+pub fn insert_creation_job(job: CreationJob) -> u64 {
+
+    CREATION_JOBS.with(|x| {
+        let mut jobs = x.borrow_mut();
+        let id = jobs.last_key_value().map_or(0, |(id, _)| id + 1);
+        jobs.insert(id, job);
+        id
+    })
+}
+
+///
+/// Updates an existing creation of a collection on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `id` - id of the creation
+/// * `job` - updated creation of the collection
+/// 
+pub fn update_creation_job(id: u64, job: CreationJob) {
+
+    CREATION_JOBS.with(|x| x.borrow_mut().insert(id, job));
+}
+
+///
+/// Gets a creation of a collection
+/// 
+/// ## Arguments
+/// * `id` - id of the creation
+/// 
+pub fn get_creation_job(id: u64) -> Option<CreationJob> {
+
+    CREATION_JOBS.with(|x| x.borrow().get(&id))
+}
+
+///
+/// Saves a change made on a collection on stable memory of ICP
+/// 
+/// ## Arguments
+/// * `change` - change made on the collection
+/// 
+/// ## Returns
+/// * id of the change
+/// 
+pub fn insert_collection_change(change: CollectionChange) -> u64 {
+
+    COLLECTION_CHANGES.with(|x| {
+        let mut changes = x.borrow_mut();
+        let id = changes.last_key_value().map_or(0, |(id, _)| id + 1);
+        changes.insert(id, change);
+        id
+    })
+}
+
+///
+/// Gets the changes made on a collection after its creation
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// 
+/// ## Returns
+/// * list of the changes with their id, from the oldest one
+/// 
+pub fn get_collection_changes(collection_id: Principal) -> Vec<(u64, CollectionChange)> {
+
+    COLLECTION_CHANGES.with(|x| 
+        x
+        .borrow()
+        .iter()
+        .filter(|(_, v)| v.collection_id == collection_id)
+        .collect::<Vec<(u64, CollectionChange)>>()
+    )
+}
+
+///
+/// Adds a principal to the allowlist of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and principal allowed
+/// * `timestamp` - time of the insertion
+/// 
+pub fn insert_allowlist_entry(key: AllowlistKey, timestamp: u64) {
+
+    ALLOWLISTS.with(|x| x.borrow_mut().insert(key, timestamp));
+}
+
+///
+/// Removes a principal from the allowlist of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and principal allowed
+/// 
+pub fn remove_allowlist_entry(key: &AllowlistKey) {
+
+    ALLOWLISTS.with(|x| x.borrow_mut().remove(key));
+}
+
+///
+/// Returns if a principal is in the allowlist of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and principal
+/// 
+pub fn is_allowlisted(key: &AllowlistKey) -> bool {
+
+    ALLOWLISTS.with(|x| x.borrow().contains_key(key))
+}
+
+///
+/// Gets the principals in the allowlist of a collection
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// 
+/// ## Returns
+/// * list of the principals with the time they have been added
+/// 
+pub fn get_allowlist(collection_id: Principal) -> Vec<(Principal, u64)> {
+
+    ALLOWLISTS.with(|x| 
+        x
+        .borrow()
+        .iter()
+        .filter(|(k, _)| k.collection_id == collection_id)
+        .map(|(k, v)| (k.principal, v))
+        .collect::<Vec<(Principal, u64)>>()
+    )
+}
+
+///
+/// Inserts or updates the role of a team member of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and team member
+/// * `role` - role of the team member
+/// 
+pub fn insert_role(key: RoleKey, role: Role) {
+
+    ROLES.with(|x| x.borrow_mut().insert(key, role));
+}
+
+///
+/// Removes the role of a team member of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and team member
+/// 
+/// ## Returns
+/// * role removed, if any
+/// 
+pub fn remove_role(key: &RoleKey) -> Option<Role> {
+
+    ROLES.with(|x| x.borrow_mut().remove(key))
+}
+
+///
+/// Gets the role of a team member of a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and team member
+/// 
+pub fn get_role(key: &RoleKey) -> Option<Role> {
+
+    ROLES.with(|x| x.borrow().get(key))
+}
+
+///
+/// Gets the team members of a collection with their role
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// 
+pub fn get_roles(collection_id: Principal) -> Vec<(Principal, Role)> {
+
+    ROLES.with(|x| 
+        x
+        .borrow()
+        .iter()
+        .filter(|(k, _)| k.collection_id == collection_id)
+        .map(|(k, v)| (k.principal, v))
+        .collect::<Vec<(Principal, Role)>>()
+    )
+}
+
+///
+/// Inserts or updates the privilege registered for a code on a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and privilege code
+/// * `privilege` - perks given by the code
+/// 
+pub fn insert_privilege(key: PrivilegeKey, privilege: Privilege) {
+
+    PRIVILEGES.with(|x| x.borrow_mut().insert(key, privilege));
+}
+
+///
+/// Removes the privilege registered for a code on a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and privilege code
+/// 
+/// ## Returns
+/// * privilege removed, if any
+/// 
+pub fn remove_privilege(key: &PrivilegeKey) -> Option<Privilege> {
+
+    PRIVILEGES.with(|x| x.borrow_mut().remove(key))
+}
+
+///
+/// Gets the privilege registered for a code on a collection
+/// 
+/// ## Arguments
+/// * `key` - collection and privilege code
+/// 
+pub fn get_privilege(key: &PrivilegeKey) -> Option<Privilege> {
+
+    PRIVILEGES.with(|x| x.borrow().get(key))
+}
+
+///
+/// Gets the privileges registered on a collection sorted by code
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// 
+pub fn get_privileges(collection_id: Principal) -> Vec<(u8, Privilege)> {
+
+    let start = PrivilegeKey { collection_id, code: u8::MIN };
+    let end = PrivilegeKey { collection_id, code: u8::MAX };
+    PRIVILEGES.with(|x| 
+        x
+        .borrow()
+        .range(start..=end)
+        .map(|(k, v)| (k.code, v))
+        .collect::<Vec<(u8, Privilege)>>()
+    )
+}
+
+///
+/// Sets the number of times a perk has been claimed with a ticket
+/// 
+/// ## Arguments
+/// * `key` - collection, ticket and perk
+/// * `claims` - number of claims
+/// 
+pub fn insert_perk_claims(key: PerkClaimKey, claims: u32) {
+
+    PERK_CLAIMS.with(|x| x.borrow_mut().insert(key, claims));
+}
+
+///
+/// Gets the number of times a perk has been claimed with a ticket
+/// 
+/// ## Arguments
+/// * `key` - collection, ticket and perk
+/// 
+pub fn get_perk_claims(key: &PerkClaimKey) -> u32 {
+
+    PERK_CLAIMS.with(|x| x.borrow().get(key)).unwrap_or(0)
+}
+
+///
+/// Inserts or updates the minting of the proof-of-attendance collection of an event
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+/// * `job` - state of the minting
+/// 
+pub fn insert_attendance_job(collection_id: Principal, job: AttendanceJob) {
+
+    ATTENDANCE_JOBS.with(|x| x.borrow_mut().insert(collection_id, job));
+}
+
+///
+/// Gets the minting of the proof-of-attendance collection of an event
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+/// 
+pub fn get_attendance_job(collection_id: &Principal) -> Option<AttendanceJob> {
+
+    ATTENDANCE_JOBS.with(|x| x.borrow().get(collection_id))
+}
+
+///
+/// Saves a ticket rewarded with a proof of attendance
+/// 
+/// ## Arguments
+/// * `key` - collection and ticket checked in
+/// * `reward` - principal rewarded and id of the token minted
+/// 
+pub fn insert_attendance_reward(key: OwnersDoubleKey, reward: AttendanceReward) {
+
+    ATTENDANCE_REWARDS.with(|x| x.borrow_mut().insert(key, reward));
+}
+
+///
+/// Gets the tickets of an event rewarded with a proof of attendance, with the holders rewarded
+/// 
+/// ## Arguments
+/// * `collection_id` - collection canister id of the event
+/// 
+pub fn get_attendance_rewards(collection_id: Principal) -> Vec<(u64, AttendanceReward)> {
+
+    let start = OwnersDoubleKey { collection_id, tkn_id: u64::MIN };
+    let end = OwnersDoubleKey { collection_id, tkn_id: u64::MAX };
+    ATTENDANCE_REWARDS.with(|x| 
+        x
+        .borrow()
+        .range(start..=end)
+        .map(|(k, v)| (k.tkn_id, v))
+        .collect::<Vec<(u64, AttendanceReward)>>()
+    )
+}
+
+///
+/// Migrates the records saved on the stable memory to the layout of `STORAGE_VERSION`
+/// 
+/// Version 1: the prices of the NFTs and of the collections saved as `u32` are rewritten as `Nat`,
+/// the old records are still readable because a `Nat` is deserialized from a plain number.
+/// 
+/// Version 2: the NFTs sold of every tier are counted from the NFTs no longer held by the organizer, burned ones included.
+/// 
+/// Version 3: the purchases are added to the index by buyer and the purchases with a refund in the payouts queue are marked as refunded.
+/// 
+/// Version 4: the collections are added to the indexes of the search.
+/// 
+pub fn migrate_storage() {
+
+    let version = VERSION.with(|x| *x.borrow().get());
+    if version >= STORAGE_VERSION {
+        return;
+    }
+
+    if version < 1 {
+        NFTS.with(|x| {
+            let records = x.borrow().iter().collect::<Vec<(OwnersDoubleKey, NftMarketData)>>();
+            for (key, nft) in records {
+                x.borrow_mut().insert(key, nft);
+            }
+        });
+        COLLECTIONS.with(|x| {
+            let records = x.borrow().iter().collect::<Vec<(Principal, CollectionInfo)>>();
+            for (key, collection) in records {
+                x.borrow_mut().insert(key, collection);
+            }
+        });
+    }
+
+    if version < 2 {
+        COLLECTIONS.with(|x| {
+            let records = x.borrow().iter().collect::<Vec<(Principal, CollectionInfo)>>();
+            for (collection_id, mut collection) in records {
+                for tier in collection.nfts.iter_mut() {
+                    tier.sold = tier.tkn_ids
+                        .iter()
+                        .filter(|tkn_id| NFTS.with(|n| n
+                            .borrow()
+                            .get(&OwnersDoubleKey { collection_id, tkn_id: **tkn_id })
+                            .is_none_or(|nft| nft.owner != collection.owner)))
+                        .count() as u64;
+                }
+                x.borrow_mut().insert(collection_id, collection);
+            }
+        });
+    }
+
+    if version < 3 {
+        let refunds = PAYOUTS.with(|x| x
+            .borrow()
+            .iter()
+            .filter(|(_, p)| p.kind == PayoutKind::Refund)
+            .filter_map(|(_, p)| p.purchase_id)
+            .collect::<HashSet<u64>>());
+        PURCHASES.with(|x| {
+            let records = x.borrow().iter().collect::<Vec<(u64, PurchaseRecord)>>();
+            for (purchase_id, mut purchase) in records {
+                PURCHASES_BY_BUYER.with(|i| i.borrow_mut().insert(BuyerPurchaseKey { collection_id: purchase.collection_id, buyer: purchase.buyer, purchase_id }, ()));
+                if refunds.contains(&purchase_id) {
+                    purchase.refunded = true;
+                    x.borrow_mut().insert(purchase_id, purchase);
+                }
+            }
+        });
+    }
+
+    if version < 4 {
+        COLLECTIONS.with(|x| {
+            for (collection_id, collection) in x.borrow().iter() {
+                index_collection(collection_id, &collection, true);
+            }
+        });
+    }
+    VERSION.with(|x| x.borrow_mut().set(STORAGE_VERSION).expect("failed to set the storage version"));
+}
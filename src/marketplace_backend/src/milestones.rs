@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+
+use candid::{Nat, Principal};
+
+use crate::common::structures::{CollectionInfo, CollectionStatus, MilestoneAmount, MilestoneApprovalKey, MilestoneArg, MilestoneStatus, PayoutKind, Role, SettlementMode};
+use crate::crowdfunding::goal_reached;
+use crate::ledger::{fee, ledger_of};
+use crate::memory::{get_collections, get_milestone_approvals, get_nfts, get_platform_fee, get_purchases, insert_collection_record, insert_milestone_approval, update_purchase_record};
+use crate::payouts::{pay_from_escrow, split_proceeds};
+use crate::roles::has_role;
+
+/// Maximum number of milestones of a collection
+pub const MAX_MILESTONES: usize = 10;
+/// Maximum length of the description of a milestone
+pub const MAX_MILESTONE_DESCRIPTION: usize = 256;
+
+///
+/// Checks the milestones passed at the creation of a collection
+///
+/// ## Arguments
+/// * `milestones` - milestones of the collection
+/// * `now` - current time
+///
+/// ## Returns
+/// * `Ok`: the milestones are valid
+/// * `Error`: String with some details about what is wrong
+///
+pub fn validate_milestones(milestones: &[MilestoneArg], now: u64) -> Result<(), String> {
+    if milestones.len() > MAX_MILESTONES {
+        return Err(format!("a collection cannot have more than {} milestones", MAX_MILESTONES));
+    }
+
+    let mut percentage = 0u32;
+    for x in milestones.iter() {
+        if x.deadline <= now {
+            return Err("milestone deadline cannot be in the past".to_string());
+        }
+        if x.description.len() > MAX_MILESTONE_DESCRIPTION {
+            return Err(format!("milestone description cannot be longer than {} characters", MAX_MILESTONE_DESCRIPTION));
+        }
+        match &x.amount {
+            MilestoneAmount::Amount(amount) if *amount == 0u32 => return Err("milestone amount must be greater than 0".to_string()),
+            MilestoneAmount::Percentage(p) if *p == 0 || *p > 100 => return Err("milestone percentage must be between 1 and 100".to_string()),
+            MilestoneAmount::Percentage(p) => percentage += *p as u32,
+            MilestoneAmount::Amount(_) => (),
+        }
+    }
+    if percentage > 100 {
+        return Err("the sum of the milestone percentages cannot be higher than 100".to_string());
+    }
+    Ok(())
+}
+
+///
+/// Returns if the payments of the primary sales of a collection are held in escrow instead of being paid to the organizer,
+/// it happens for all-or-nothing collections and for collections released by milestones
+///
+/// ## Arguments
+/// * `collection` - info of the collection
+///
+pub fn holds_in_escrow(collection: &CollectionInfo) -> bool {
+    collection.settlement == SettlementMode::AllOrNothing || !collection.milestones.is_empty()
+}
+
+///
+/// Funds raised by a collection that have not been released to the organizer yet
+///
+/// ## Arguments
+/// * `collection` - info of the collection
+///
+pub fn unreleased(collection: &CollectionInfo) -> Nat {
+    if collection.raised > collection.released {
+        collection.raised.clone() - collection.released.clone()
+    } else {
+        Nat::from(0u32)
+    }
+}
+
+///
+/// Number of NFTs of a collection held by each backer, the organizer is not a backer
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `organizer` - owner of the collection
+///
+fn backers(collection_id: Principal, organizer: Principal) -> HashMap<Principal, u64> {
+    let mut res = HashMap::new();
+    for (key, nft) in get_nfts() {
+        if key.collection_id == collection_id && nft.owner != organizer {
+            *res.entry(nft.owner).or_insert(0) += 1;
+        }
+    }
+    res
+}
+
+///
+/// Requests the release of a milestone, only the owners and the finance team can do it before the deadline of the milestone.
+/// The milestones are released in order.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `milestone_id` - index of the milestone in the collection
+/// * `caller` - principal requesting the release
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn request_release(collection_id: Principal, milestone_id: u32, caller: Principal) -> Result<String, String> {
+    let mut collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Finance) {
+        return Err("only the owners and the finance team can request the release of a milestone".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if collection.settlement == SettlementMode::AllOrNothing && !goal_reached(&collection) {
+        return Err("funding goal not reached".to_string());
+    }
+    let index = milestone_id as usize;
+    if index >= collection.milestones.len() {
+        return Err("milestone does not exists".to_string());
+    }
+    if collection.milestones[..index].iter().any(|x| x.status != MilestoneStatus::Released) {
+        return Err("the previous milestones need to be released first".to_string());
+    }
+
+    let now = ic_cdk::api::time();
+    let milestone = &mut collection.milestones[index];
+    if milestone.status != MilestoneStatus::Pending {
+        return Err(format!("milestone already {:?}", milestone.status));
+    }
+    if milestone.deadline < now {
+        return Err("milestone deadline passed".to_string());
+    }
+    milestone.status = MilestoneStatus::Requested;
+    milestone.requested_at = Some(now);
+    insert_collection_record(collection_id, collection);
+    Ok(format!("release of milestone {} requested", milestone_id))
+}
+
+///
+/// Approves the release of a requested milestone.
+/// An admin (controller of the backend) releases the milestone directly,
+/// a backer adds his approval and the milestone is released when the backers approving hold more than half of the NFTs sold.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `milestone_id` - index of the milestone in the collection
+/// * `caller` - principal approving the release
+///
+/// ## Returns
+/// * `Ok`: Successful message or description of the payments of the release
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn approve_release(collection_id: Principal, milestone_id: u32, caller: Principal) -> Result<String, String> {
+    let ledger_fee = fee(ledger_of(&collection_id)).await?;
+
+    let collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    match collection.milestones.get(milestone_id as usize) {
+        Some(x) if x.status == MilestoneStatus::Requested => (),
+        Some(x) => return Err(format!("milestone is {:?}", x.status)),
+        None => return Err("milestone does not exists".to_string()),
+    }
+
+    if !ic_cdk::api::is_controller(&caller) {
+        let backers = backers(collection_id, collection.owner);
+        if !backers.contains_key(&caller) {
+            return Err("only an admin or a backer of the collection can approve a milestone".to_string());
+        }
+        insert_milestone_approval(MilestoneApprovalKey { collection_id, milestone_id, backer: caller }, ic_cdk::api::time());
+
+        let approving = get_milestone_approvals(collection_id, milestone_id)
+            .iter()
+            .map(|x| backers.get(&x.0).copied().unwrap_or_default())
+            .sum::<u64>();
+        let total = backers.values().sum::<u64>();
+        if approving * 2 <= total {
+            return Ok(format!("approval saved, backers approving hold {} of {} NFTs", approving, total));
+        }
+    }
+
+    release_milestone(collection_id, collection, milestone_id as usize, ledger_fee).await
+}
+
+///
+/// Pays to the organizer the amount of a milestone from the escrow of the collection,
+/// the platform fee is taken on the amount released
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `index` - index of the milestone in the collection
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+async fn release_milestone(collection_id: Principal, mut collection: CollectionInfo, index: usize, ledger_fee: u128) -> Result<String, String> {
+    let available = unreleased(&collection);
+    let amount = match &collection.milestones[index].amount {
+        MilestoneAmount::Amount(x) => x.clone(),
+        MilestoneAmount::Percentage(p) => collection.raised.clone() * Nat::from(*p) / Nat::from(100u32),
+    };
+    let amount = if amount > available { available } else { amount };
+    if amount == 0u32 {
+        return Err("no funds to release".to_string());
+    }
+    let gross = u128::try_from(&amount.0).map_err(|_| "milestone amount too big".to_string())?;
+
+    // the release is saved before paying so that the milestone cannot be paid twice
+    collection.milestones[index].status = MilestoneStatus::Released;
+    collection.milestones[index].released_amount = Some(amount.clone());
+    collection.released += amount;
+    insert_collection_record(collection_id, collection.clone());
+
+    let mut res = Vec::new();
+    for (kind, to, share) in split_proceeds(gross, collection.owner, &collection, &get_platform_fee(), ledger_fee) {
+        match pay_from_escrow(collection_id, to, Nat::from(share.saturating_sub(ledger_fee)), kind, None).await {
+            Ok(block) => {
+                if kind == PayoutKind::Settlement {
+                    if let Some(mut collection) = get_collections().get(&collection_id).cloned() {
+                        collection.milestones[index].release_block = Some(block.clone());
+                        insert_collection_record(collection_id, collection);
+                    }
+                }
+                res.push(format!("{:?} to {} on block {}", kind, to, block));
+            },
+            Err(payout_id) => res.push(format!("{:?} to {} will be retried with payout id {}", kind, to, payout_id)),
+        }
+    }
+    Ok(format!("milestone {} released: {}", index, res.join(", ")))
+}
+
+///
+/// Pays to the organizer the funds left in escrow once every milestone has been released and the collection is expired,
+/// it covers the milestones summing to less than the funds raised and the sales made after the last release.
+/// Only the owners and the finance team can request it, the platform fee is taken on the amount released.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal requesting the release
+///
+/// ## Returns
+/// * `Ok`: description of the payments made or scheduled
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn release_remaining(collection_id: Principal, caller: Principal) -> Result<String, String> {
+    let ledger_fee = fee(ledger_of(&collection_id)).await?;
+
+    let mut collection = match get_collections().get(&collection_id) {
+        Some(x) => x.clone(),
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Finance) {
+        return Err("only the owners and the finance team can release the remaining funds".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    if collection.milestones.is_empty() {
+        return Err("the funds of the collection are not released by milestones".to_string());
+    }
+    if collection.milestones.iter().any(|x| x.status != MilestoneStatus::Released) {
+        return Err("every milestone needs to be released first".to_string());
+    }
+    if collection.expire_date > ic_cdk::api::time() {
+        return Err("the remaining funds can be released only after the expire date".to_string());
+    }
+    let amount = unreleased(&collection);
+    if amount == 0u32 {
+        return Err("no funds to release".to_string());
+    }
+    let gross = u128::try_from(&amount.0).map_err(|_| "amount to release too big".to_string())?;
+
+    // the release is saved before paying so that the funds cannot be paid twice
+    collection.released += amount;
+    insert_collection_record(collection_id, collection.clone());
+    for (purchase_id, mut purchase) in get_purchases().into_iter().filter(|x| x.1.collection_id == collection_id && x.1.escrowed) {
+        purchase.escrowed = false;
+        update_purchase_record(purchase_id, purchase);
+    }
+
+    let mut res = Vec::new();
+    for (kind, to, share) in split_proceeds(gross, collection.owner, &collection, &get_platform_fee(), ledger_fee) {
+        match pay_from_escrow(collection_id, to, Nat::from(share.saturating_sub(ledger_fee)), kind, None).await {
+            Ok(block) => res.push(format!("{:?} to {} on block {}", kind, to, block)),
+            Err(payout_id) => res.push(format!("{:?} to {} will be retried with payout id {}", kind, to, payout_id)),
+        }
+    }
+    Ok(format!("remaining funds released: {}", res.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_000;
+
+    fn milestone(amount: MilestoneAmount) -> MilestoneArg {
+        MilestoneArg { amount, deadline: NOW + 1, description: "stage".to_string() }
+    }
+
+    #[test]
+    fn percentages_up_to_100_are_valid() {
+        let milestones = vec![milestone(MilestoneAmount::Percentage(40)), milestone(MilestoneAmount::Percentage(60))];
+        assert!(validate_milestones(&milestones, NOW).is_ok());
+
+        let milestones = vec![milestone(MilestoneAmount::Percentage(40)), milestone(MilestoneAmount::Amount(Nat::from(10u32)))];
+        assert!(validate_milestones(&milestones, NOW).is_ok());
+    }
+
+    #[test]
+    fn invalid_milestones_are_rejected() {
+        let over = vec![milestone(MilestoneAmount::Percentage(60)), milestone(MilestoneAmount::Percentage(41))];
+        assert!(validate_milestones(&over, NOW).is_err());
+        assert!(validate_milestones(&[milestone(MilestoneAmount::Percentage(0))], NOW).is_err());
+        assert!(validate_milestones(&[milestone(MilestoneAmount::Percentage(101))], NOW).is_err());
+        assert!(validate_milestones(&[milestone(MilestoneAmount::Amount(Nat::from(0u32)))], NOW).is_err());
+
+        let past = MilestoneArg { deadline: NOW, ..milestone(MilestoneAmount::Percentage(10)) };
+        assert!(validate_milestones(&[past], NOW).is_err());
+
+        let long = MilestoneArg { description: "x".repeat(MAX_MILESTONE_DESCRIPTION + 1), ..milestone(MilestoneAmount::Percentage(10)) };
+        assert!(validate_milestones(&[long], NOW).is_err());
+
+        let many = vec![milestone(MilestoneAmount::Percentage(1)); MAX_MILESTONES + 1];
+        assert!(validate_milestones(&many, NOW).is_err());
+    }
+}
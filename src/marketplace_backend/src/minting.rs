@@ -0,0 +1,196 @@
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::collection::{burn, mint};
+use crate::common::structures::{CollectionStatus, OwnersDoubleKey, PayoutKind, PurchaseRecord};
+use crate::ledger::{escrow_account, fee, ledger_of, transfer_from};
+use crate::memory::{get_collection, get_collections, insert_collection_record, insert_nft_record, insert_purchase_record, insert_tier_token, remove_tier_token, update_purchase_record};
+use crate::payouts::{net_of_fee, pay_from_escrow, settle_sale};
+use crate::presale::check_buyer;
+use crate::query_methods::get_tier_price;
+use crate::tiers::mintable;
+
+///
+/// Reserves a place in a tier for an NFT being bought, so that the tier cannot be oversold by concurrent purchases
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tier` - index of the tier in the collection
+///
+/// ## Returns
+/// * `Ok`: the place has been reserved
+/// * `Error`: String with some details about what went wrong
+///
+fn reserve(collection_id: Principal, tier: usize) -> Result<(), String> {
+    let mut collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    match collection.nfts.get(tier) {
+        Some(x) if mintable(x) => (),
+        Some(x) => return Err(format!("tier {} sold out", x.nft.token_name)),
+        None => return Err("tier does not exists".to_string()),
+    }
+
+    collection.nfts[tier].reserved += 1;
+    insert_collection_record(collection_id, collection);
+    Ok(())
+}
+
+///
+/// Assigns the id of the next NFT of a collection to a reserved place of a tier.
+/// `icrc7_mint` rejects the ids lower than the last one minted, so the id is assigned only when the NFT is about to be minted,
+/// the calls to the collection canister are delivered in the order they are made.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tier` - index of the tier in the collection
+///
+/// ## Returns
+/// * id of the token
+///
+fn assign(collection_id: Principal, tier: usize) -> u64 {
+    let mut collection = get_collection(&collection_id).expect("collection does not exists");
+
+    let tkn_id = collection.next_tkn_id.max(1);
+    collection.next_tkn_id = tkn_id + 1;
+    insert_collection_record(collection_id, collection);
+    insert_tier_token(OwnersDoubleKey { collection_id, tkn_id }, tier as u32);
+    tkn_id
+}
+
+///
+/// Releases the place of an NFT that could not be minted, it can be taken by another purchase.
+/// The id of the token is not reused since a higher id may have been minted in the meantime.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tier` - index of the tier in the collection
+/// * `tkn_id` - id of the token assigned, if any
+///
+fn release(collection_id: Principal, tier: usize, tkn_id: Option<u64>) {
+    if let Some(mut collection) = get_collection(&collection_id) {
+        collection.nfts[tier].reserved = collection.nfts[tier].reserved.saturating_sub(1);
+        insert_collection_record(collection_id, collection);
+    }
+    if let Some(tkn_id) = tkn_id {
+        remove_tier_token(&OwnersDoubleKey { collection_id, tkn_id });
+    }
+}
+
+///
+/// Purchase of an NFT of a tier, the NFT is minted directly to the buyer after the payment.
+/// The price is moved from the buyer to the escrow of the collection with ICRC-2 and settled like a primary sale,
+/// if the NFT cannot be minted the payment is refunded.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tier` - index of the tier in the collection
+/// * `buyer` - principal buying the NFT
+/// * `amount` - maximum amount the buyer is willing to pay
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn buy(collection_id: Principal, tier: u32, buyer: Principal, amount: Nat) -> Result<String, String> {
+    let price = get_tier_price(collection_id.to_text(), tier)?;
+    if price > amount {
+        return Err(format!("the price of the NFT is {}, higher than the amount passed", price));
+    }
+
+    let (organizer, metadata) = match get_collections().get(&collection_id) {
+        Some(x) => {
+            check_buyer(collection_id, x, buyer, None)?;
+            (x.owner, x.nfts[tier as usize].nft.clone())
+        },
+        None => return Err("collection does not exists".to_string()),
+    };
+    if organizer == buyer {
+        return Err("the organizer cannot buy the NFTs of his collection".to_string());
+    }
+
+    let tier = tier as usize;
+    reserve(collection_id, tier)?;
+
+    let ledger = ledger_of(&collection_id);
+    let payment_block = match transfer_from(ledger, Nat::from(price), Account::from(buyer), escrow_account(&collection_id)).await {
+        Ok(block) => block,
+        Err(e) => {
+            release(collection_id, tier, None);
+            return Err(format!("Error in transferring the tokens from the caller to the escrow: {}", e));
+        }
+    };
+    // no call is made between the assignment of the id and the mint
+    let tkn_id = assign(collection_id, tier);
+
+    let mut purchase = PurchaseRecord {
+        collection_id,
+        tkn_id,
+        buyer,
+        seller: organizer,
+        amount: Nat::from(price),
+        timestamp: ic_cdk::api::time(),
+        payment_block: payment_block.clone(),
+        settlement_block: None,
+        refund_block: None,
+        royalty_block: None,
+        platform_fee_block: None,
+        escrowed: false,
+        refunded: false,
+    };
+    let purchase_id = insert_purchase_record(purchase.clone());
+
+    // the collection could have been cancelled or the limit of the buyer reached by concurrent purchases while the payment was in progress
+    let allowed = match get_collections().get(&collection_id) {
+        Some(x) if x.status == CollectionStatus::Cancelled => Err("collection cancelled".to_string()),
+        Some(x) => check_buyer(collection_id, x, buyer, Some(purchase_id)),
+        None => Err("collection does not exists".to_string()),
+    };
+    let minted = match allowed {
+        Err(e) => Err(e),
+        Ok(()) => mint(collection_id, tkn_id, &metadata, organizer, buyer).await,
+    };
+
+    // the collection could have been cancelled during the mint, the NFT is then burned and the payment refunded
+    let cancelled = get_collection(&collection_id).is_none_or(|x| x.status == CollectionStatus::Cancelled);
+    let minted = match minted {
+        Ok(_) if cancelled => match burn(collection_id, tkn_id, buyer).await {
+            Ok(_) => Err("collection cancelled".to_string()),
+            Err(e) => {
+                // the NFT and the payment are left in escrow to the cancellation, completed by the timer
+                insert_nft_record(collection_id, tkn_id, buyer, None, false);
+                purchase.escrowed = true;
+                update_purchase_record(purchase_id, purchase);
+                return Err(format!("collection cancelled, the NFT will be burned and the payment refunded automatically: {}", e));
+            },
+        },
+        x => x,
+    };
+
+    let ledger_fee = fee(ledger).await.unwrap_or_default();
+
+    match minted {
+        Ok(_) => {
+            insert_nft_record(collection_id, tkn_id, buyer, None, false);
+            let settlement = settle_sale(purchase_id, purchase, price, ledger_fee).await;
+            Ok(format!(
+                "NFT with token id: {} minted to {} correctly, payment block: {}, settlement: {}",
+                tkn_id, buyer, payment_block, settlement
+            ))
+        },
+        Err(e) => {
+            release(collection_id, tier, Some(tkn_id));
+            match pay_from_escrow(collection_id, buyer, net_of_fee(&Nat::from(price), ledger_fee), PayoutKind::Refund, Some(purchase_id)).await {
+                Ok(refund_block) => {
+                    purchase.refund_block = Some(refund_block.clone());
+                    update_purchase_record(purchase_id, purchase);
+                    Err(format!("Error in minting NFT {}, the payment has been refunded on block {}", e, refund_block))
+                },
+                Err(payout_id) => {
+                    Err(format!("Error in minting NFT {}, a refund will be sent automatically with payout id: {}", e, payout_id))
+                },
+            }
+        },
+    }
+}
@@ -0,0 +1,226 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+
+use crate::collection::transfer_token;
+use crate::common::guards::LockGuard;
+use crate::common::structures::{CollectionStatus, NftMarketData, Offer, OwnersDoubleKey, PayoutKind, PurchaseRecord};
+use crate::ledger::{escrow_account, fee, ledger_of, transfer_from};
+use crate::memory::{get_collections, get_nft_offers, get_nfts, get_offers, insert_nft_offers, insert_nft_record, insert_purchase_record, update_purchase_record};
+use crate::payouts::{net_of_fee, pay_from_escrow, settle_sale};
+
+/// Maximum number of offers on a single NFT
+pub const MAX_OFFERS_PER_NFT: usize = 20;
+/// Interval between two checks of the expired offers
+pub const OFFERS_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+thread_local! {
+    static EXPIRING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Starts the timer that refunds the expired offers
+///
+pub fn start_offers_timer() {
+    ic_cdk_timers::set_timer_interval(OFFERS_INTERVAL, || ic_cdk::spawn(refund_expired_offers()));
+}
+
+///
+/// Refunds an offer from the escrow of the collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `offer` - offer to refund
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+/// ## Returns
+/// * description of the refund
+///
+async fn refund_offer(collection_id: Principal, offer: &Offer, ledger_fee: u128) -> String {
+    match pay_from_escrow(collection_id, offer.bidder, net_of_fee(&offer.amount, ledger_fee), PayoutKind::Refund, None).await {
+        Ok(block) => format!("the offer has been refunded on block {}", block),
+        Err(payout_id) => format!("a refund will be sent automatically with payout id: {}", payout_id),
+    }
+}
+
+///
+/// Makes an offer on an NFT, the amount is moved from the bidder to the escrow of the collection with ICRC-2.
+/// Every bidder can have a single offer on an NFT.
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `bidder` - principal making the offer
+/// * `amount` - amount offered
+/// * `expires_at` - time in nanoseconds after which the offer is refunded
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn make(key: OwnersDoubleKey, bidder: Principal, amount: Nat, expires_at: u64) -> Result<String, String> {
+    if amount == 0u32 || u128::try_from(&amount.0).is_err() {
+        return Err("invalid amount".to_string());
+    }
+    if expires_at <= ic_cdk::api::time() {
+        return Err("expiry cannot be in the past".to_string());
+    }
+    let offers = get_nft_offers(&key);
+    if offers.offers.iter().any(|x| x.bidder == bidder) {
+        return Err("the caller already made an offer on the NFT".to_string());
+    }
+    if offers.offers.len() >= MAX_OFFERS_PER_NFT {
+        return Err("too many offers on the NFT".to_string());
+    }
+
+    let payment_block = transfer_from(ledger_of(&key.collection_id), amount.clone(), Account::from(bidder), escrow_account(&key.collection_id))
+        .await
+        .map_err(|e| format!("Error in transferring the tokens from the caller to the escrow: {}", e))?;
+
+    // the offers could have been changed while the payment was in progress
+    let mut offers = get_nft_offers(&key);
+    let offer = Offer {
+        bidder,
+        amount,
+        payment_block: payment_block.clone(),
+        created_at: ic_cdk::api::time(),
+        expires_at,
+    };
+    if offers.offers.iter().any(|x| x.bidder == bidder) || offers.offers.len() >= MAX_OFFERS_PER_NFT {
+        let ledger_fee = fee(ledger_of(&key.collection_id)).await.unwrap_or_default();
+        return Err(format!("the offer is no longer valid, {}", refund_offer(key.collection_id, &offer, ledger_fee).await));
+    }
+    offers.offers.push(offer);
+    insert_nft_offers(key, offers);
+    Ok(format!("offer on NFT with token id: {} made, payment block: {}", key.tkn_id, payment_block))
+}
+
+///
+/// Cancels the offer of a bidder on an NFT and refunds it
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `bidder` - principal that made the offer
+///
+/// ## Returns
+/// * `Ok`: description of the refund
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn cancel(key: OwnersDoubleKey, bidder: Principal) -> Result<String, String> {
+    let mut offers = get_nft_offers(&key);
+    let offer = match offers.offers.iter().position(|x| x.bidder == bidder) {
+        Some(index) => offers.offers.remove(index),
+        None => return Err("offer does not exists".to_string()),
+    };
+    insert_nft_offers(key, offers);
+
+    let ledger_fee = fee(ledger_of(&key.collection_id)).await.unwrap_or_default();
+    Ok(refund_offer(key.collection_id, &offer, ledger_fee).await)
+}
+
+///
+/// Accepts the offer of a bidder on an NFT, the NFT is transferred to the bidder and the amount is settled like a purchase.
+/// If the NFT cannot be transferred the offer is refunded.
+///
+/// ## Arguments
+/// * `key` - pair of collection_id and nft_id
+/// * `nft` - marketplace data of the NFT, whose owner accepts the offer
+/// * `bidder` - principal that made the offer
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn accept(key: OwnersDoubleKey, nft: NftMarketData, bidder: Principal) -> Result<String, String> {
+    let collection_id = key.collection_id;
+    let seller = nft.owner;
+
+    let mut offers = get_nft_offers(&key);
+    let offer = match offers.offers.iter().position(|x| x.bidder == bidder) {
+        Some(index) if offers.offers[index].expires_at > ic_cdk::api::time() => offers.offers.remove(index),
+        Some(_) => return Err("offer expired".to_string()),
+        None => return Err("offer does not exists".to_string()),
+    };
+
+    // the offer is removed and the NFT locked before transferring it
+    insert_nft_offers(key, offers);
+    insert_nft_record(collection_id, key.tkn_id, seller, nft.price.clone(), false);
+
+    let mut purchase = PurchaseRecord {
+        collection_id,
+        tkn_id: key.tkn_id,
+        buyer: bidder,
+        seller,
+        amount: offer.amount.clone(),
+        timestamp: ic_cdk::api::time(),
+        payment_block: offer.payment_block.clone(),
+        settlement_block: None,
+        refund_block: None,
+        royalty_block: None,
+        platform_fee_block: None,
+        escrowed: false,
+        refunded: false,
+    };
+    let purchase_id = insert_purchase_record(purchase.clone());
+
+    let transfer = transfer_token(collection_id, key.tkn_id, seller, bidder).await;
+    let ledger_fee = fee(ledger_of(&collection_id)).await.unwrap_or_default();
+
+    match transfer {
+        Ok(_) => {
+            insert_nft_record(collection_id, key.tkn_id, bidder, None, false);
+            let price = u128::try_from(&offer.amount.0).unwrap_or(u128::MAX);
+            let settlement = settle_sale(purchase_id, purchase, price, ledger_fee).await;
+            Ok(format!(
+                "NFT with token id: {}, transferred from {} to {} correctly, payment block: {}, settlement: {}",
+                key.tkn_id, seller, bidder, offer.payment_block, settlement
+            ))
+        },
+        Err(e) => {
+            if get_nfts().contains_key(&key) {
+                insert_nft_record(collection_id, key.tkn_id, seller, nft.price, nft.on_sale);
+            }
+            match pay_from_escrow(collection_id, bidder, net_of_fee(&offer.amount, ledger_fee), PayoutKind::Refund, Some(purchase_id)).await {
+                Ok(refund_block) => {
+                    purchase.refund_block = Some(refund_block.clone());
+                    update_purchase_record(purchase_id, purchase);
+                    Err(format!("Error in transfering NFT {:?}, the offer has been refunded on block {}", e, refund_block))
+                },
+                Err(payout_id) => {
+                    Err(format!("Error in transfering NFT {:?}, a refund will be sent automatically with payout id: {}", e, payout_id))
+                },
+            }
+        },
+    }
+}
+
+///
+/// Refunds the offers expired, made on NFTs no longer existing or on collections cancelled
+///
+pub async fn refund_expired_offers() {
+    let _guard = match LockGuard::acquire(&EXPIRING) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let now = ic_cdk::api::time();
+    let collections = get_collections();
+    let nfts = get_nfts();
+    let mut expired = Vec::new();
+    for (key, mut offers) in get_offers() {
+        let closed = !nfts.contains_key(&key)
+            || collections.get(&key.collection_id).is_none_or(|x| x.status == CollectionStatus::Cancelled);
+        let (gone, left): (Vec<Offer>, Vec<Offer>) = offers.offers.into_iter().partition(|x| closed || x.expires_at <= now);
+        if !gone.is_empty() {
+            offers.offers = left;
+            insert_nft_offers(key, offers);
+            expired.extend(gone.into_iter().map(|x| (key.collection_id, x)));
+        }
+    }
+
+    for (collection_id, offer) in expired {
+        let ledger_fee = fee(ledger_of(&collection_id)).await.unwrap_or_default();
+        refund_offer(collection_id, &offer, ledger_fee).await;
+    }
+}
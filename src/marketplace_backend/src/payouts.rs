@@ -0,0 +1,377 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use candid::{Nat, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::Memo;
+
+use crate::common::guards::LockGuard;
+use crate::common::structures::{CollectionInfo, PayoutKind, PayoutStatus, PendingPayout, PlatformFee, PurchaseRecord};
+use crate::crowdfunding::add_raised;
+use crate::ledger::{escrow_subaccount, ledger_of, transfer};
+use crate::memory::{get_collections, get_payout, get_payouts, get_platform_fee, get_purchase, insert_payout_record, next_payment_nonce, update_payout_record, update_purchase_record};
+use crate::milestones::holds_in_escrow;
+use crate::tiers::add_sold;
+
+/// Interval between two runs of the payouts queue
+pub const PAYOUTS_INTERVAL: Duration = Duration::from_secs(60);
+/// Delay before the first retry, doubled at every failed attempt
+pub const BASE_BACKOFF_NANOS: u64 = 60 * 1_000_000_000;
+/// Maximum delay between two attempts
+pub const MAX_BACKOFF_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+/// Number of attempts after which a payout is marked as failed
+pub const MAX_ATTEMPTS: u32 = 10;
+/// Denominator of the amounts expressed in basis points
+pub const BPS_DENOMINATOR: u128 = 10_000;
+/// Age after which the ledger does not deduplicate a transfer anymore, with a margin on its window of 24 hours
+pub const DEDUP_WINDOW_NANOS: u64 = 23 * 60 * 60 * 1_000_000_000;
+
+thread_local! {
+    static PROCESSING: Cell<bool> = const { Cell::new(false) };
+}
+
+///
+/// Starts the timer that periodically retries the payments owed by the backend canister
+///
+pub fn start_payouts_timer() {
+    ic_cdk_timers::set_timer_interval(PAYOUTS_INTERVAL, || ic_cdk::spawn(process_payouts()));
+}
+
+///
+/// Delay to wait before the next attempt, growing exponentially with the number of attempts
+///
+/// ## Arguments
+/// * `attempts` - number of attempts already made
+///
+fn backoff(attempts: u32) -> u64 {
+    BASE_BACKOFF_NANOS
+        .saturating_mul(1u64 << attempts.saturating_sub(1).min(32))
+        .min(MAX_BACKOFF_NANOS)
+}
+
+///
+/// Amount left after paying the ledger fee, zero if the fee is higher than the amount
+///
+/// ## Arguments
+/// * `amount` - gross amount
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+pub fn net_of_fee(amount: &Nat, ledger_fee: u128) -> Nat {
+    let ledger_fee = Nat::from(ledger_fee);
+    if *amount > ledger_fee {
+        amount.clone() - ledger_fee
+    } else {
+        Nat::from(0u32)
+    }
+}
+
+///
+/// Pays an amount from the escrow of a collection, adding the payment to the queue if it fails.
+/// The creation time and the memo of the transfer are kept for the retries, so the ledger never executes it twice.
+///
+/// ## Arguments
+/// * `collection_id` - collection whose escrow pays
+/// * `to` - beneficiary of the payment
+/// * `amount` - amount of tokens to send
+/// * `kind` - kind of the payment
+/// * `purchase_id` - purchase the payment belongs to, if any
+///
+/// ## Returns
+/// * `Ok`: block index of the payment
+/// * `Error`: id of the payout that will retry the payment
+///
+pub async fn pay_from_escrow(collection_id: Principal, to: Principal, amount: Nat, kind: PayoutKind, purchase_id: Option<u64>) -> Result<Nat, u64> {
+    let mut payout = PendingPayout {
+        collection_id,
+        to,
+        amount,
+        kind,
+        purchase_id,
+        status: PayoutStatus::Pending,
+        attempts: 1,
+        next_attempt: 0,
+        last_error: String::new(),
+        block: None,
+        created_at_time: Some(ic_cdk::api::time()),
+        memo: Some(next_payment_nonce().to_be_bytes().to_vec()),
+    };
+    // a refund is saved on its purchase before the transfer, so that it is not counted as a purchase anymore
+    if let (PayoutKind::Refund, Some(id)) = (kind, purchase_id) {
+        if let Some(mut purchase) = get_purchase(id) {
+            purchase.refunded = true;
+            update_purchase_record(id, purchase);
+        }
+    }
+    let memo = payout.memo.clone().map(Memo::from);
+    match transfer(ledger_of(&collection_id), payout.amount.clone(), Some(escrow_subaccount(&collection_id)), Account::from(to), payout.created_at_time, memo).await {
+        Ok(block) => Ok(block),
+        Err(e) => {
+            // the payment is added to the queue with the same creation time and memo
+            payout.last_error = e;
+            payout.next_attempt = ic_cdk::api::time() + backoff(1);
+            Err(insert_payout_record(payout))
+        },
+    }
+}
+
+///
+/// Retries all the pending payouts whose backoff is expired,
+/// on success the block index is saved on the payout and on the purchase it belongs to.
+///
+pub async fn process_payouts() {
+    let _guard = match LockGuard::acquire(&PROCESSING) {
+        Some(x) => x,
+        None => return,
+    };
+
+    let now = ic_cdk::api::time();
+    let mut due = get_payouts()
+        .into_iter()
+        .filter(|x| x.1.status == PayoutStatus::Pending && x.1.next_attempt <= now)
+        .collect::<Vec<(u64, PendingPayout)>>();
+    due.sort_by_key(|x| x.0);
+
+    for (id, mut payout) in due {
+        // the payouts saved before the deduplication get their creation time and memo on the first retry
+        if payout.created_at_time.is_none() || payout.memo.is_none() {
+            payout.created_at_time = Some(ic_cdk::api::time());
+            payout.memo = Some(next_payment_nonce().to_be_bytes().to_vec());
+            update_payout_record(id, payout.clone());
+        }
+        let memo = payout.memo.clone().map(Memo::from);
+        match transfer(ledger_of(&payout.collection_id), payout.amount.clone(), Some(escrow_subaccount(&payout.collection_id)), Account::from(payout.to), payout.created_at_time, memo).await {
+            Ok(block) => {
+                payout.status = PayoutStatus::Completed;
+                payout.block = Some(block.clone());
+                if let Some(purchase_id) = payout.purchase_id {
+                    if let Some(mut purchase) = get_purchase(purchase_id) {
+                        set_purchase_block(&mut purchase, payout.kind, block);
+                        update_purchase_record(purchase_id, purchase);
+                    }
+                }
+            },
+            Err(e) => {
+                payout.attempts += 1;
+                payout.last_error = e;
+                if payout.attempts >= MAX_ATTEMPTS {
+                    payout.status = PayoutStatus::Failed;
+                } else {
+                    payout.next_attempt = ic_cdk::api::time() + backoff(payout.attempts);
+                }
+            },
+        }
+        update_payout_record(id, payout);
+    }
+}
+
+///
+/// Puts back in the queue a payout marked as failed after the maximum number of attempts.
+/// If the last attempt is older than the deduplication window of the ledger the transfer gets a new creation time,
+/// the ledger cannot detect it as a duplicate anymore so the memo should be looked up on the ledger before retrying.
+///
+/// ## Arguments
+/// * `payout_id` - id of the payout
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn retry_failed(payout_id: u64) -> Result<String, String> {
+    let mut payout = match get_payout(payout_id) {
+        Some(x) => x,
+        None => return Err("payout does not exists".to_string()),
+    };
+    if payout.status != PayoutStatus::Failed {
+        return Err("only the failed payouts can be retried".to_string());
+    }
+
+    let now = ic_cdk::api::time();
+    if payout.created_at_time.is_none_or(|x| now.saturating_sub(x) >= DEDUP_WINDOW_NANOS) {
+        payout.created_at_time = Some(now);
+    }
+    payout.status = PayoutStatus::Pending;
+    payout.attempts = 0;
+    payout.next_attempt = now;
+    update_payout_record(payout_id, payout);
+    Ok(format!("payout {} will be retried", payout_id))
+}
+
+///
+/// Saves the block index of a payment on the purchase it belongs to
+///
+fn set_purchase_block(purchase: &mut PurchaseRecord, kind: PayoutKind, block: Nat) {
+    match kind {
+        PayoutKind::Settlement => purchase.settlement_block = Some(block),
+        PayoutKind::Royalty => purchase.royalty_block = Some(block),
+        PayoutKind::PlatformFee => purchase.platform_fee_block = Some(block),
+        PayoutKind::Refund => purchase.refund_block = Some(block),
+    }
+}
+
+///
+/// Splits the price of a sale between the seller, the organizer of the collection and the platform fee account.
+/// Royalties are paid only on secondary sales, when the seller is not the organizer.
+/// Shares that do not cover the ledger fee are given to the seller.
+///
+/// ## Arguments
+/// * `price` - price paid by the buyer
+/// * `seller` - owner of the NFT sold
+/// * `collection` - info of the collection of the NFT
+/// * `platform_fee` - fee taken by the platform
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+/// ## Returns
+/// * list of (kind, beneficiary, gross amount) of the shares
+///
+pub fn split_proceeds(price: u128, seller: Principal, collection: &CollectionInfo, platform_fee: &PlatformFee, ledger_fee: u128) -> Vec<(PayoutKind, Principal, u128)> {
+    let mut shares = Vec::new();
+    let mut seller_share = price;
+
+    if seller != collection.owner {
+        let royalty = price * collection.royalty_bps as u128 / BPS_DENOMINATOR;
+        if royalty > ledger_fee {
+            seller_share -= royalty;
+            shares.push((PayoutKind::Royalty, collection.owner, royalty));
+        }
+    }
+    if let Some(fee_account) = platform_fee.fee_account {
+        let fee = price * platform_fee.fee_bps as u128 / BPS_DENOMINATOR;
+        if fee > ledger_fee && fee <= seller_share {
+            seller_share -= fee;
+            shares.push((PayoutKind::PlatformFee, fee_account, fee));
+        }
+    }
+    shares.insert(0, (PayoutKind::Settlement, seller, seller_share));
+    shares
+}
+
+///
+/// Pays from the escrow of the collection every share of a sale, 
+/// the block indexes are saved on the purchase and the failed payments are added to the payouts queue.
+///
+/// ## Arguments
+/// * `purchase_id` - id of the purchase
+/// * `purchase` - receipt of the purchase
+/// * `price` - price paid by the buyer
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+/// ## Returns
+/// * description of the payments made or scheduled
+///
+pub async fn settle_purchase(purchase_id: u64, mut purchase: PurchaseRecord, price: u128, ledger_fee: u128) -> String {
+    let collection = get_collections()
+        .get(&purchase.collection_id)
+        .expect("collection does not exists")
+        .clone();
+    let shares = split_proceeds(price, purchase.seller, &collection, &get_platform_fee(), ledger_fee);
+
+    let mut res = Vec::new();
+    for (kind, to, amount) in shares {
+        let net_amount = Nat::from(amount.saturating_sub(ledger_fee));
+        match pay_from_escrow(purchase.collection_id, to, net_amount, kind, Some(purchase_id)).await {
+            Ok(block) => {
+                res.push(format!("{:?} to {} on block {}", kind, to, block));
+                set_purchase_block(&mut purchase, kind, block);
+            },
+            Err(payout_id) => {
+                res.push(format!("{:?} to {} will be retried with payout id {}", kind, to, payout_id));
+            },
+        }
+    }
+    update_purchase_record(purchase_id, purchase);
+    res.join(", ")
+}
+
+///
+/// Completes a sale after the NFT has been transferred to the buyer: 
+/// the primary sales are added to the funds raised and to the NFTs sold of the collection and held in escrow if the collection requires it,
+/// the other sales are settled immediately.
+///
+/// ## Arguments
+/// * `purchase_id` - id of the purchase
+/// * `purchase` - receipt of the purchase
+/// * `price` - price paid by the buyer
+/// * `ledger_fee` - fee of a transaction on the ledger
+///
+/// ## Returns
+/// * description of the settlement
+///
+pub async fn settle_sale(purchase_id: u64, mut purchase: PurchaseRecord, price: u128, ledger_fee: u128) -> String {
+    let collection = get_collections()
+        .get(&purchase.collection_id)
+        .expect("collection does not exists")
+        .clone();
+    let primary_sale = purchase.seller == collection.owner;
+
+    if primary_sale {
+        add_raised(purchase.collection_id, price);
+        add_sold(purchase.collection_id, purchase.tkn_id);
+    }
+    if primary_sale && holds_in_escrow(&collection) {
+        purchase.escrowed = true;
+        update_purchase_record(purchase_id, purchase);
+        return "held in escrow".to_string();
+    }
+    settle_purchase(purchase_id, purchase, price, ledger_fee).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(owner: Principal, royalty_bps: u16) -> CollectionInfo {
+        CollectionInfo { owner, royalty_bps, ..Default::default() }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        assert_eq!(backoff(1), BASE_BACKOFF_NANOS);
+        assert_eq!(backoff(2), 2 * BASE_BACKOFF_NANOS);
+        assert_eq!(backoff(4), 8 * BASE_BACKOFF_NANOS);
+        assert_eq!(backoff(MAX_ATTEMPTS), MAX_BACKOFF_NANOS.min(BASE_BACKOFF_NANOS << (MAX_ATTEMPTS - 1)));
+        assert_eq!(backoff(u32::MAX), MAX_BACKOFF_NANOS);
+    }
+
+    #[test]
+    fn net_of_fee_never_underflows() {
+        assert_eq!(net_of_fee(&Nat::from(1_000u32), 10), Nat::from(990u32));
+        assert_eq!(net_of_fee(&Nat::from(10u32), 10), Nat::from(0u32));
+        assert_eq!(net_of_fee(&Nat::from(5u32), 10), Nat::from(0u32));
+    }
+
+    #[test]
+    fn primary_sale_pays_no_royalty() {
+        let owner = Principal::from_slice(&[1]);
+        let fee_account = Principal::from_slice(&[2]);
+        let shares = split_proceeds(10_000, owner, &collection(owner, 500), &PlatformFee { fee_account: Some(fee_account), fee_bps: 250 }, 10);
+
+        assert_eq!(shares, vec![
+            (PayoutKind::Settlement, owner, 9_750),
+            (PayoutKind::PlatformFee, fee_account, 250),
+        ]);
+    }
+
+    #[test]
+    fn secondary_sale_pays_royalty_and_fee() {
+        let owner = Principal::from_slice(&[1]);
+        let fee_account = Principal::from_slice(&[2]);
+        let seller = Principal::from_slice(&[3]);
+        let shares = split_proceeds(10_000, seller, &collection(owner, 500), &PlatformFee { fee_account: Some(fee_account), fee_bps: 250 }, 10);
+
+        assert_eq!(shares, vec![
+            (PayoutKind::Settlement, seller, 9_250),
+            (PayoutKind::Royalty, owner, 500),
+            (PayoutKind::PlatformFee, fee_account, 250),
+        ]);
+        assert_eq!(shares.iter().map(|x| x.2).sum::<u128>(), 10_000);
+    }
+
+    #[test]
+    fn shares_below_the_ledger_fee_go_to_the_seller() {
+        let owner = Principal::from_slice(&[1]);
+        let seller = Principal::from_slice(&[3]);
+        let shares = split_proceeds(100, seller, &collection(owner, 500), &PlatformFee { fee_account: Some(owner), fee_bps: 250 }, 10);
+
+        assert_eq!(shares, vec![(PayoutKind::Settlement, seller, 100)]);
+    }
+}
@@ -0,0 +1,132 @@
+use candid::Principal;
+
+use crate::cancellation::is_refunded;
+use crate::common::structures::{AllowlistKey, CollectionInfo, Role};
+use crate::memory::{get_allowlist, get_buyer_purchases, get_collection, insert_allowlist_entry, is_allowlisted, remove_allowlist_entry};
+use crate::roles::has_role;
+
+/// Maximum number of principals added or removed from an allowlist in a single call
+pub const MAX_ALLOWLIST_BATCH: usize = 500;
+
+///
+/// Number of NFTs bought by a principal from the organizer of a collection, the refunded purchases are not counted
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `organizer` - owner of the collection
+/// * `buyer` - principal buying the NFTs
+/// * `before` - if some, only the purchases saved before this one are counted
+///
+pub fn bought(collection_id: Principal, organizer: Principal, buyer: Principal, before: Option<u64>) -> u64 {
+    get_buyer_purchases(collection_id, buyer)
+        .into_iter()
+        .filter(|x| x.1.seller == organizer
+            && before.is_none_or(|id| x.0 < id)
+            && !is_refunded(&x.1))
+        .count() as u64
+}
+
+///
+/// Checks that a principal can buy an NFT from the organizer of a collection:
+/// during the presale only the principals in the allowlist can buy and nobody can buy more than `max_per_wallet` NFTs.
+/// After the payment the check is repeated counting only the purchases saved before, 
+/// so that concurrent purchases of the same principal cannot exceed the limit.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `buyer` - principal buying the NFT
+/// * `purchase_id` - id of the purchase of the NFT if already saved
+///
+/// ## Returns
+/// * `Ok`: the principal can buy the NFT
+/// * `Error`: String with the reason why the principal cannot buy the NFT
+///
+pub fn check_buyer(collection_id: Principal, collection: &CollectionInfo, buyer: Principal, purchase_id: Option<u64>) -> Result<(), String> {
+    if collection.presale_end.is_some_and(|end| end > ic_cdk::api::time())
+        && !is_allowlisted(&AllowlistKey { collection_id, principal: buyer }) {
+        return Err("the collection is in presale, only the principals in the allowlist can buy".to_string());
+    }
+    if let Some(cap) = collection.max_per_wallet {
+        if bought(collection_id, collection.owner, buyer, purchase_id) >= cap as u64 {
+            return Err(format!("a principal cannot buy more than {} NFTs of the collection", cap));
+        }
+    }
+    Ok(())
+}
+
+///
+/// Gets a collection checking that the caller is an owner or a manager
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+fn get_owned(collection_id: Principal, caller: Principal) -> Result<CollectionInfo, String> {
+    match get_collection(&collection_id) {
+        Some(x) if has_role(collection_id, &x, caller, Role::Manager) => Ok(x),
+        Some(_) => Err("only the owners and the managers can manage the allowlist of the collection".to_string()),
+        None => Err("collection does not exists".to_string()),
+    }
+}
+
+///
+/// Adds principals to the allowlist of a collection, only the organizer can do it
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `principals` - principals to add
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn add(collection_id: Principal, caller: Principal, principals: Vec<Principal>) -> Result<String, String> {
+    get_owned(collection_id, caller)?;
+    if principals.len() > MAX_ALLOWLIST_BATCH {
+        return Err(format!("cannot add more than {} principals at once", MAX_ALLOWLIST_BATCH));
+    }
+
+    let now = ic_cdk::api::time();
+    for principal in principals.iter() {
+        insert_allowlist_entry(AllowlistKey { collection_id, principal: *principal }, now);
+    }
+    Ok(format!("{} principals added to the allowlist", principals.len()))
+}
+
+///
+/// Removes principals from the allowlist of a collection, only the organizer can do it
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `principals` - principals to remove
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn remove(collection_id: Principal, caller: Principal, principals: Vec<Principal>) -> Result<String, String> {
+    get_owned(collection_id, caller)?;
+    if principals.len() > MAX_ALLOWLIST_BATCH {
+        return Err(format!("cannot remove more than {} principals at once", MAX_ALLOWLIST_BATCH));
+    }
+
+    for principal in principals.iter() {
+        remove_allowlist_entry(&AllowlistKey { collection_id, principal: *principal });
+    }
+    Ok(format!("{} principals removed from the allowlist", principals.len()))
+}
+
+///
+/// Gets the allowlist of a collection, only the organizer can see it
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+pub fn allowlist(collection_id: Principal, caller: Principal) -> Result<Vec<Principal>, String> {
+    get_owned(collection_id, caller)?;
+    Ok(get_allowlist(collection_id).into_iter().map(|x| x.0).collect())
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+
+use candid::Principal;
+
+use crate::collection::owner_of;
+use crate::common::structures::{CollectionChangeKind, CollectionInfo, CollectionStatus, Perk, PerkClaimKey, PerkKind, Privilege, PrivilegeKey, Role, TicketPerk};
+use crate::editing::record;
+use crate::memory::{get_collection, get_perk_claims, get_privilege, get_privileges, insert_perk_claims, insert_privilege, remove_privilege as remove_privilege_entry};
+use crate::roles::has_role;
+use crate::tiers::tier_of;
+
+/// Maximum number of perks given by a privilege code
+pub const MAX_PERKS: usize = 10;
+
+/// Maximum length in bytes of the name of a privilege or of a perk
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// Maximum length in bytes of the url of an exclusive content
+pub const MAX_URL_LENGTH: usize = 512;
+
+///
+/// Gets a collection checking that the caller can manage its privileges
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+///
+fn get_managed(collection_id: Principal, caller: Principal) -> Result<CollectionInfo, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if !has_role(collection_id, &collection, caller, Role::Manager) {
+        return Err("only the owners and the managers can manage the privileges".to_string());
+    }
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    Ok(collection)
+}
+
+///
+/// Checks the name, the perks and their limits of a privilege
+///
+/// ## Arguments
+/// * `privilege` - privilege to check
+///
+fn validate_privilege(privilege: &Privilege) -> Result<(), String> {
+    if privilege.name.is_empty() || privilege.name.len() > MAX_NAME_LENGTH {
+        return Err(format!("the name of the privilege must be between 1 and {} bytes", MAX_NAME_LENGTH));
+    }
+    if privilege.perks.len() > MAX_PERKS {
+        return Err(format!("a privilege can give at most {} perks", MAX_PERKS));
+    }
+    let now = ic_cdk::api::time();
+    let mut names = HashSet::new();
+    for perk in privilege.perks.iter() {
+        if perk.name.is_empty() || perk.name.len() > MAX_NAME_LENGTH {
+            return Err(format!("the name of the perks must be between 1 and {} bytes", MAX_NAME_LENGTH));
+        }
+        if !names.insert(perk.name.as_str()) {
+            return Err(format!("perk {} given twice", perk.name));
+        }
+        if perk.max_uses == Some(0) {
+            return Err(format!("perk {} can not be claimed", perk.name));
+        }
+        if perk.expires_at.is_some_and(|x| x <= now) {
+            return Err(format!("perk {} already expired", perk.name));
+        }
+        if let PerkKind::ExclusiveContent { ref url } = perk.kind {
+            if url.is_empty() || url.len() > MAX_URL_LENGTH {
+                return Err(format!("the url of the content must be between 1 and {} bytes", MAX_URL_LENGTH));
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Privilege code of a ticket, taken from its tier
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `tkn_id` - id of the ticket
+///
+fn code_of(collection_id: Principal, collection: &CollectionInfo, tkn_id: u64) -> Result<u8, String> {
+    match tier_of(collection_id, collection, tkn_id) {
+        Some(tier) => Ok(collection.nfts[tier].nft.token_privilege_code),
+        None => Err("nft does not exists".to_string()),
+    }
+}
+
+///
+/// Returns if a perk can still be claimed
+///
+/// ## Arguments
+/// * `perk` - perk to claim
+/// * `claimed` - number of times the perk has been claimed with the ticket
+/// * `now` - current time in nanoseconds
+///
+fn is_claimable(perk: &Perk, claimed: u32, now: u64) -> bool {
+    perk.expires_at.is_none_or(|x| now < x) && perk.max_uses.is_none_or(|x| claimed < x)
+}
+
+///
+/// Hides the url of an exclusive content, given only to the holders claiming the perk
+///
+/// ## Arguments
+/// * `perk` - perk returned by a public view
+///
+fn redact(perk: Perk) -> Perk {
+    match perk.kind {
+        PerkKind::ExclusiveContent { .. } => Perk { kind: PerkKind::ExclusiveContent { url: String::new() }, ..perk },
+        _ => perk,
+    }
+}
+
+///
+/// Registers the perks given by a privilege code on a collection, replacing the previous ones.
+/// The claims already made keep counting for the perks with the same name.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `code` - privilege code of the tickets
+/// * `privilege` - name and perks of the privilege
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn set_privilege(collection_id: Principal, caller: Principal, code: u8, privilege: Privilege) -> Result<String, String> {
+    get_managed(collection_id, caller)?;
+    validate_privilege(&privilege)?;
+
+    let name = privilege.name.clone();
+    insert_privilege(PrivilegeKey { collection_id, code }, privilege);
+    record(collection_id, caller, CollectionChangeKind::PrivilegeSet { code, name: name.clone() });
+    Ok(format!("privilege {} registered for code {}", name, code))
+}
+
+///
+/// Removes the perks given by a privilege code on a collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `code` - privilege code of the tickets
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn remove_privilege(collection_id: Principal, caller: Principal, code: u8) -> Result<String, String> {
+    get_managed(collection_id, caller)?;
+
+    if remove_privilege_entry(&PrivilegeKey { collection_id, code }).is_none() {
+        return Err(format!("no privilege registered for code {}", code));
+    }
+    record(collection_id, caller, CollectionChangeKind::PrivilegeRemoved { code });
+    Ok(format!("privilege of code {} removed", code))
+}
+
+///
+/// Privileges registered on a collection sorted by code, the urls of the exclusive contents are hidden
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+pub fn privileges(collection_id: Principal) -> Result<Vec<(u8, Privilege)>, String> {
+    if get_collection(&collection_id).is_none() {
+        return Err("collection does not exists".to_string());
+    }
+    Ok(get_privileges(collection_id)
+        .into_iter()
+        .map(|(code, privilege)| (code, Privilege { perks: privilege.perks.into_iter().map(redact).collect(), ..privilege }))
+        .collect())
+}
+
+///
+/// Perks a ticket entitles its holder to, with their claims.
+/// The urls of the exclusive contents are hidden, they are returned only when the perk is claimed.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the ticket
+///
+/// ## Returns
+/// * `Ok`: perks of the ticket, empty if no privilege is registered for its code
+/// * `Error`: String with some details about what went wrong
+///
+pub fn ticket_perks(collection_id: Principal, tkn_id: u64) -> Result<Vec<TicketPerk>, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    let code = code_of(collection_id, &collection, tkn_id)?;
+    let privilege = match get_privilege(&PrivilegeKey { collection_id, code }) {
+        Some(x) => x,
+        None => return Ok(Vec::new()),
+    };
+
+    let now = ic_cdk::api::time();
+    Ok(privilege.perks
+        .into_iter()
+        .map(|perk| {
+            let claimed = get_perk_claims(&PerkClaimKey { collection_id, tkn_id, perk: perk.name.clone() });
+            let claimable = is_claimable(&perk, claimed, now);
+            TicketPerk { perk: redact(perk), claimed, claimable }
+        })
+        .collect())
+}
+
+///
+/// Claims a perk of a ticket, callable by the holder of the ticket or by the door staff of the collection
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `tkn_id` - id of the ticket
+/// * `perk` - name of the perk
+///
+/// ## Returns
+/// * `Ok`: Successful message, the url for an exclusive content
+/// * `Error`: String with some details about what went wrong
+///
+pub async fn claim(collection_id: Principal, caller: Principal, tkn_id: u64, perk: String) -> Result<String, String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if collection.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    }
+    let code = code_of(collection_id, &collection, tkn_id)?;
+    if !has_role(collection_id, &collection, caller, Role::DoorStaff) && owner_of(collection_id, tkn_id).await? != caller {
+        return Err("only the holder of the ticket and the door staff can claim its perks".to_string());
+    }
+
+    // the privilege is read after the ownership check, it may have changed in the meantime
+    let privilege = match get_privilege(&PrivilegeKey { collection_id, code }) {
+        Some(x) => x,
+        None => return Err("the ticket gives no perks".to_string()),
+    };
+    let found = match privilege.perks.into_iter().find(|x| x.name == perk) {
+        Some(x) => x,
+        None => return Err(format!("the ticket does not give the perk {}", perk)),
+    };
+    let key = PerkClaimKey { collection_id, tkn_id, perk };
+    let claimed = get_perk_claims(&key);
+    if found.expires_at.is_some_and(|x| x <= ic_cdk::api::time()) {
+        return Err(format!("perk {} expired", found.name));
+    }
+    if found.max_uses.is_some_and(|x| claimed >= x) {
+        return Err(format!("perk {} already claimed {} times", found.name, claimed));
+    }
+
+    insert_perk_claims(key, claimed + 1);
+    match found.kind {
+        PerkKind::ExclusiveContent { url } => Ok(url),
+        _ => Ok(format!("perk {} claimed", found.name)),
+    }
+}
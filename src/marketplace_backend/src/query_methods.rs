@@ -1,11 +1,20 @@
 use std::collections::HashMap;
 
-use candid::Principal;
-use ic_ledger_types::MAINNET_LEDGER_CANISTER_ID;
+use candid::{Nat, Principal};
 use icrc_ledger_types::icrc1::account::Account;
 use crate::common::guards::caller_is_auth;
-use crate::common::structures::{CollectionFullInfo, NftMarketData, OwnersDoubleKey};
-use crate::memory::{get_nfts, get_collections};
+use crate::common::structures::{AttendanceReward, Auction, AuctionStatus, CheckInChallenge, CollectionChange, CollectionFilter, CollectionFullInfo, CollectionInfo, CollectionSort, CollectionStatus, CreationStatus, DutchPricing, Offer, NftMarketData, OwnersDoubleKey, PaymentToken, PayoutStatus, PendingPayout, PlatformFee, Privilege, PurchaseRecord, Role, TicketPerk, TierInfo};
+use crate::attendance::rewards;
+use crate::checkin::{challenge, redemption};
+use crate::creation::{get_draft, status};
+use crate::editing::changes;
+use crate::presale::{allowlist, check_buyer};
+use crate::privileges::{privileges, ticket_perks};
+use crate::roles::team;
+use crate::search::search;
+use crate::ledger::{balance_of, fee, ledger_of};
+use crate::memory::{get_auctions, get_nft_offers, get_offers, get_nfts, get_collections, get_payouts, get_platform_fee, get_purchases};
+use crate::tiers::{check_tier_on_sale, check_tier_window, mintable, tiers_info};
 
 ///
 /// Gets the list of canisters assigned to the caller
@@ -42,7 +51,7 @@ pub fn get_collection_ids(caller: Option<String>, offset: u32, limit: u32) -> Re
 }
 
 ///
-/// Returns if a collection is still available by checking if the expire date is passed.
+/// Returns if a collection is still available by checking if the expire date is passed and if it has not been cancelled.
 /// 
 /// ## Arguments
 /// * `canister_id` - Canister id of the collection
@@ -59,7 +68,222 @@ pub fn get_collection_viability(canister_id: Principal) -> Result<bool, String>
         Some(x) => x,
         None => return Err("collection does not exists".to_string())
     };
-    Ok(val.expire_date > ic_cdk::api::time())
+    Ok(val.expire_date > ic_cdk::api::time() && val.status == CollectionStatus::Active)
+}
+
+///
+/// Returns the availability of every tier of a collection: price, sale window, NFTs remaining and if it is sold out
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: tiers of the collection in the order they have been created
+/// * `Error`: if the collection does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_tiers(collection_id: String) -> Result<Vec<TierInfo>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    match get_collections().get(&collection_id) {
+        Some(x) => Ok(tiers_info(x)),
+        None => Err("collection does not exists".to_string())
+    }
+}
+
+///
+/// Returns the allowlist of the presale of a collection, only the organizer can see it
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: principals allowed to buy during the presale
+/// * `Error`: if the collection does not exist or the caller is not the organizer
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_allowlist(collection_id: String) -> Result<Vec<Principal>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    allowlist(collection_id, ic_cdk::caller())
+}
+
+///
+/// Returns the team of a collection with the role of each member, the organizer first
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: team members with their role
+/// * `Error`: if the collection does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_roles(collection_id: String) -> Result<Vec<(Principal, Role)>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    team(collection_id)
+}
+
+///
+/// Returns when a ticket has been checked in at the event door
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the ticket
+/// 
+/// ## Returns
+/// * `Ok`: timestamp of the check-in, None if the ticket has not been checked in yet
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::query(guard = "caller_is_auth", composite = true)]
+pub async fn get_ticket_redemption(collection_id: String, tkn_id: u64) -> Result<Option<u64>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    redemption(collection_id, tkn_id).await
+}
+
+///
+/// Issues a challenge to the caller, holder of a ticket, valid for 30 minutes.
+/// The holder signs it with his identity and shows it at the door, where it can be verified offline.
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the ticket
+/// 
+/// ## Returns
+/// * `Ok`: challenge to sign
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::query(guard = "caller_is_auth", composite = true)]
+pub async fn get_check_in_challenge(collection_id: String, tkn_id: u64) -> Result<CheckInChallenge, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    challenge(collection_id, ic_cdk::caller(), tkn_id).await
+}
+
+///
+/// Returns the privileges registered on a collection: the perks given by each privilege code
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: privilege codes with their name and perks, sorted by code, without the urls of the exclusive contents
+/// * `Error`: if the collection does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_privileges(collection_id: String) -> Result<Vec<(u8, Privilege)>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    privileges(collection_id)
+}
+
+///
+/// Returns the perks a ticket entitles its holder to, with the number of times each perk has been claimed
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the ticket
+/// 
+/// ## Returns
+/// * `Ok`: perks of the ticket and if they can still be claimed, without the urls of the exclusive contents
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_ticket_perks(collection_id: String, tkn_id: u64) -> Result<Vec<TicketPerk>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    ticket_perks(collection_id, tkn_id)
+}
+
+///
+/// Returns the tickets of an event rewarded with a proof of attendance
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister of the event
+/// 
+/// ## Returns
+/// * `Ok`: ids of the tickets rewarded with the principals and the ids of the tokens minted
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_attendance_rewards(collection_id: String) -> Result<Vec<(u64, AttendanceReward)>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    rewards(collection_id)
+}
+
+///
+/// Returns the audit trail of a collection: the changes made by the organizer after its creation
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: changes of the collection from the oldest one
+/// * `Error`: if the collection does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_changes(collection_id: String) -> Result<Vec<CollectionChange>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+    if !get_collections().contains_key(&collection_id) {
+        return Err("collection does not exists".to_string());
+    }
+    Ok(changes(collection_id))
+}
+
+///
+/// Returns the status of the creation of a collection: the last step completed and the error that stopped it, if any
+/// 
+/// ## Arguments
+/// * `job_id` - id of the creation
+/// 
+/// ## Returns
+/// * `Ok`: status of the creation
+/// * `Error`: if the creation does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_creation_status(job_id: u64) -> Result<CreationStatus, String> {
+
+    status(job_id).ok_or("creation does not exists".to_string())
+}
+
+///
+/// Returns the collection prepared in a draft to preview tiers, pricing and discount windows before publishing it.
+/// Only the owner of the draft can see it.
+/// 
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// 
+/// ## Returns
+/// * `Ok`: collection of the draft
+/// * `Error`: if the draft does not exist or it is not owned by the caller
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_collection_draft(draft_id: u64) -> Result<CollectionInfo, String> {
+
+    get_draft(draft_id, ic_cdk::caller())
+}
+
+///
+/// Returns the token used to pay the NFTs of a collection, the approvals of the buyers need to be made on his ledger.
+/// 
+/// ## Arguments
+/// * `canister_id` - Canister id of the collection
+/// 
+/// ## Returns
+/// * `Ok`: ledger and decimals of the payment token
+/// * `Error`: if the canister id does not exist
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_payment_token(canister_id: Principal) -> Result<PaymentToken, String> {
+
+    match get_collections().get(&canister_id) {
+        Some(x) => Ok(x.token),
+        None => Err("collection does not exists".to_string())
+    }
 }
 
 ///
@@ -93,7 +317,24 @@ pub fn get_collections_info_by_caller(caller: Option<String>, offset: u32, limit
             expire_date: x.1.expire_date, 
             discount_windows: x.1.clone().discount_windows, 
             available: get_collection_viability(*x.0).expect("Error in getting the records from the database"),
-            nfts: (*x.1.nfts).to_vec()
+            nfts: (*x.1.nfts).to_vec(),
+            royalty_bps: x.1.royalty_bps,
+            funding_goal: x.1.funding_goal.clone(),
+            settlement: x.1.settlement,
+            raised: x.1.raised.clone(),
+            funding_status: x.1.funding_status,
+            milestones: x.1.milestones.clone(),
+            released: x.1.released.clone(),
+            status: x.1.status,
+            dutch_pricing: x.1.dutch_pricing.clone(),
+            token: x.1.token,
+            presale_end: x.1.presale_end,
+            max_per_wallet: x.1.max_per_wallet,
+            name: x.1.name.clone(),
+            description: x.1.description.clone(),
+            tags: x.1.tags.clone(),
+            created_at: x.1.created_at,
+            attendance_collection: x.1.attendance_collection
         })
         .skip(offset as usize)
         .take(limit as usize)
@@ -123,7 +364,24 @@ pub fn get_all_collections(offset: u32, limit: u32) -> Result<Vec<CollectionFull
             expire_date: x.1.expire_date, 
             discount_windows: x.1.clone().discount_windows, 
             available: get_collection_viability(*x.0).expect("Error in getting the records from the database"),
-            nfts: (*x.1.nfts).to_vec()
+            nfts: (*x.1.nfts).to_vec(),
+            royalty_bps: x.1.royalty_bps,
+            funding_goal: x.1.funding_goal.clone(),
+            settlement: x.1.settlement,
+            raised: x.1.raised.clone(),
+            funding_status: x.1.funding_status,
+            milestones: x.1.milestones.clone(),
+            released: x.1.released.clone(),
+            status: x.1.status,
+            dutch_pricing: x.1.dutch_pricing.clone(),
+            token: x.1.token,
+            presale_end: x.1.presale_end,
+            max_per_wallet: x.1.max_per_wallet,
+            name: x.1.name.clone(),
+            description: x.1.description.clone(),
+            tags: x.1.tags.clone(),
+            created_at: x.1.created_at,
+            attendance_collection: x.1.attendance_collection
         })
         .skip(offset as usize)
         .take(limit as usize)
@@ -134,6 +392,65 @@ pub fn get_all_collections(offset: u32, limit: u32) -> Result<Vec<CollectionFull
     Ok(res)
 }
 
+///
+/// Full information about a collection including its availability
+/// 
+/// ## Arguments
+/// * `canister_id` - id of the collection canister
+/// * `collection` - info of the collection
+/// 
+fn full_info(canister_id: Principal, collection: CollectionInfo) -> CollectionFullInfo {
+
+    CollectionFullInfo { 
+        owner: collection.owner, 
+        canister_id, 
+        expire_date: collection.expire_date, 
+        available: collection.expire_date > ic_cdk::api::time() && collection.status == CollectionStatus::Active,
+        discount_windows: collection.discount_windows, 
+        nfts: collection.nfts,
+        royalty_bps: collection.royalty_bps,
+        funding_goal: collection.funding_goal,
+        settlement: collection.settlement,
+        raised: collection.raised,
+        funding_status: collection.funding_status,
+        milestones: collection.milestones,
+        released: collection.released,
+        status: collection.status,
+        dutch_pricing: collection.dutch_pricing,
+        token: collection.token,
+        presale_end: collection.presale_end,
+        max_per_wallet: collection.max_per_wallet,
+        name: collection.name,
+        description: collection.description,
+        tags: collection.tags,
+        created_at: collection.created_at,
+        attendance_collection: collection.attendance_collection
+    }
+}
+
+///
+/// Searches the collections with filters and a sort order, using the indexes of the collections 
+/// so that only the collections needed are read.
+/// 
+/// ## Arguments
+/// * `filter` - filters of the search: availability, organizer, range of the lowest price of the tiers, 
+///   category tags and text contained in the name or in the description
+/// * `sort` - order of the collections: expire date, newest, price from low to high or from high to low
+/// * `offset` - Offset of the first element to retrieve
+/// * `limit` - Number of elements to retrieve
+/// 
+/// ## Returns
+/// * collections found, including their availability
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn search_collections(filter: CollectionFilter, sort: CollectionSort, offset: u32, limit: u32) -> Vec<CollectionFullInfo> {
+
+    search(filter, sort, offset, limit)
+        .into_iter()
+        .map(|x| full_info(x.0, x.1))
+        .collect()
+}
+
 ///
 /// Returns all NFTs including their informations.
 /// 
@@ -152,7 +469,7 @@ pub fn get_all_nfts(offset: u32, limit: u32) -> Result<HashMap<OwnersDoubleKey,
         .iter()
         .skip(offset as usize)
         .take(limit as usize)
-        .map(|x| (*x.0, *x.1))
+        .map(|x| (*x.0, x.1.clone()))
         .collect::<HashMap<OwnersDoubleKey, NftMarketData>>();
     if res.is_empty() {
         return Err("no collections present".to_string())
@@ -160,6 +477,206 @@ pub fn get_all_nfts(offset: u32, limit: u32) -> Result<HashMap<OwnersDoubleKey,
     Ok(res)
 }
 
+///
+/// Returns the last auction of an NFT, including the highest bid
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// 
+/// ## Returns
+/// * `Ok`: auction of the NFT
+/// * `Error`: if the NFT has never been on auction
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_auction(collection_id: String, tkn_id: u64) -> Result<Auction, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("cannot convert from text to principal");
+    match get_auctions().get(&OwnersDoubleKey { collection_id, tkn_id }) {
+        Some(x) => Ok(x.clone()),
+        None => Err("NFT never on auction".to_string()),
+    }
+}
+
+///
+/// Returns the auctions accepting bids, sorted by end time
+/// 
+/// ## Arguments
+/// * `offset` - Offset of the first element to retrieve
+/// * `limit` - Number of elements to retrieve
+/// 
+/// ## Returns
+/// * `Ok`: list of NFTs on auction with their auction
+/// * `Error`: if no auction is active
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn list_active_auctions(offset: u32, limit: u32) -> Result<Vec<(OwnersDoubleKey, Auction)>, String> {
+
+    let mut res = get_auctions()
+        .into_iter()
+        .filter(|x| x.1.status == AuctionStatus::Active)
+        .collect::<Vec<(OwnersDoubleKey, Auction)>>();
+    res.sort_by_key(|x| x.1.end_time);
+
+    let res = res
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect::<Vec<(OwnersDoubleKey, Auction)>>();
+    if res.is_empty() {
+        return Err("no auctions present".to_string())
+    }
+    Ok(res)
+}
+
+///
+/// Returns the offers made on an NFT, sorted by amount from the highest
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// 
+/// ## Returns
+/// * `Ok`: offers made on the NFT
+/// * `Error`: if there are no offers on the NFT
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_offers_by_nft(collection_id: String, tkn_id: u64) -> Result<Vec<Offer>, String> {
+
+    let collection_id = Principal::from_text(collection_id).expect("cannot convert from text to principal");
+    let mut res = get_nft_offers(&OwnersDoubleKey { collection_id, tkn_id }).offers;
+    res.sort_by(|a, b| b.amount.cmp(&a.amount));
+    if res.is_empty() {
+        return Err("no offers present".to_string())
+    }
+    Ok(res)
+}
+
+///
+/// Returns the offers made by a bidder
+/// 
+/// ## Arguments
+/// * `bidder` - Optional of bidder principal.
+///     * if none, then it will return the offers of the caller of the function
+///     * if some, then it will return the offers of the principal passed as argument
+/// 
+/// ## Returns
+/// * `Ok`: offers of the bidder with the NFT they are made on
+/// * `Error`: if the bidder has no offers
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_offers_by_bidder(bidder: Option<String>) -> Result<Vec<(OwnersDoubleKey, Offer)>, String> {
+
+    let bidder = match &bidder {
+        Some(x) => Principal::from_text(x).expect("Not able to convert string to principal"),
+        None => ic_cdk::caller(),
+    };
+
+    let res = get_offers()
+        .into_iter()
+        .flat_map(|(key, x)| x.offers.into_iter().filter(|o| o.bidder == bidder).map(move |o| (key, o)))
+        .collect::<Vec<(OwnersDoubleKey, Offer)>>();
+    if res.is_empty() {
+        return Err("no offers present".to_string())
+    }
+    Ok(res)
+}
+
+///
+/// Returns the receipts of the purchases made or received by a principal, 
+/// including the block indexes of the ledger transactions to audit them.
+/// 
+/// ## Arguments
+/// * `caller` - Optional of caller principal.
+///     * if none, then it will return the purchases of the caller of the function
+///     * if some, then it will return the purchases of the principal passed as argument
+/// * `offset` - Offset of the first element to retrieve
+/// * `limit` - Number of elements to retrieve
+/// 
+/// ## Returns
+/// * `Ok`: purchases in which the principal is the buyer or the seller, sorted by purchase id
+/// * `Error`: if no purchase is found
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_purchases_by_caller(caller: Option<String>, offset: u32, limit: u32) -> Result<Vec<(u64, PurchaseRecord)>, String> {
+
+    let caller = match &caller {
+        Some(x) => Principal::from_text(x).expect("Not able to convert string to principal"),
+        None => ic_cdk::caller(),
+    };
+
+    let mut res = get_purchases()
+        .into_iter()
+        .filter(|x| x.1.buyer == caller || x.1.seller == caller)
+        .collect::<Vec<(u64, PurchaseRecord)>>();
+    res.sort_by_key(|x| x.0);
+
+    let res = res
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect::<Vec<(u64, PurchaseRecord)>>();
+    if res.is_empty() {
+        return Err("no purchases present".to_string())
+    }
+    Ok(res)
+}
+
+///
+/// Returns the payments owed to a principal that are still waiting to be retried by the backend.
+/// 
+/// ## Arguments
+/// * `caller` - Optional of caller principal.
+///     * if none, then it will return the payouts owed to the caller of the function
+///     * if some, then it will return the payouts owed to the principal passed as argument
+/// 
+/// ## Returns
+/// * `Ok`: list of pending payouts with their ids
+/// * `Error`: if no payout is pending
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_pending_payouts(caller: Option<String>) -> Result<Vec<(u64, PendingPayout)>, String> {
+
+    get_payouts_by_status(caller, PayoutStatus::Pending)
+}
+
+///
+/// Returns the payments owed to a principal for which the backend gave up after the maximum number of attempts.
+/// 
+/// ## Arguments
+/// * `caller` - Optional of caller principal.
+///     * if none, then it will return the payouts owed to the caller of the function
+///     * if some, then it will return the payouts owed to the principal passed as argument
+/// 
+/// ## Returns
+/// * `Ok`: list of failed payouts with their ids
+/// * `Error`: if no payout is failed
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_failed_payouts(caller: Option<String>) -> Result<Vec<(u64, PendingPayout)>, String> {
+
+    get_payouts_by_status(caller, PayoutStatus::Failed)
+}
+
+fn get_payouts_by_status(caller: Option<String>, status: PayoutStatus) -> Result<Vec<(u64, PendingPayout)>, String> {
+
+    let caller = match &caller {
+        Some(x) => Principal::from_text(x).expect("Not able to convert string to principal"),
+        None => ic_cdk::caller(),
+    };
+
+    let mut res = get_payouts()
+        .into_iter()
+        .filter(|x| x.1.to == caller && x.1.status == status)
+        .collect::<Vec<(u64, PendingPayout)>>();
+    res.sort_by_key(|x| x.0);
+
+    if res.is_empty() {
+        return Err("no payouts present".to_string())
+    }
+    Ok(res)
+}
+
 /// 
 /// Function that checks if the balance of an account is enough to purchase an NFT passed.
 /// 
@@ -180,79 +697,280 @@ pub async fn check_balance(owner: Option<String>, tkn_id: u64, collection_id: St
         None => ic_cdk::caller(),
     }; 
 
-    let price = match get_nfts()
+    let canister_id = Principal::from_text(collection_id.clone()).expect("unable to parse string to principal");
+    let ledger = ledger_of(&canister_id);
+
+    let price = get_nft_price(tkn_id, collection_id)?;
+
+    // the presale and the limit of NFTs per principal apply only to the NFTs sold by the organizer
+    if let Some(collection) = get_collections().get(&canister_id) {
+        if get_nfts().get(&OwnersDoubleKey { collection_id: canister_id, tkn_id }).is_some_and(|x| x.owner == collection.owner) {
+            check_buyer(canister_id, collection, owner, None)?;
+        }
+    }
+
+    let balance = balance_of(ledger, Account::from(owner)).await?;
+
+    let fee = fee(ledger).await?;
+
+    if (price + fee) <= balance {
+        return Ok(price)
+    }
+    Err("Low balance".to_string())
+
+}
+
+/// 
+/// Returns the price to pay for an NFT minted from a tier of a collection, applying the descending price or the discount windows of the collection.
+/// 
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tier` - index of the tier in the collection
+/// 
+/// ## Returns
+/// * `Ok`: price of the NFT
+/// * `Error`: collection not on sale, tier not on sale or sold out
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_tier_price(collection_id: String, tier: u32) -> Result<u128, String> {
+
+    let now = ic_cdk::api::time();
+    let binding = get_collections();
+    let collection_info = match binding.get(&Principal::from_text(collection_id).expect("unable to parse string to principal")) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+
+    if collection_info.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    } else if now > collection_info.expire_date {
+        return Err("collection Expired".to_string());
+    }
+    let tier = match collection_info.nfts.get(tier as usize) {
+        Some(x) => x,
+        None => return Err("tier does not exists".to_string()),
+    };
+    check_tier_window(tier)?;
+    if !mintable(tier) {
+        return Err(format!("tier {} sold out", tier.nft.token_name));
+    }
+
+    get_primary_price(&tier.nft.price, collection_info, now)
+}
+
+/// 
+/// Function that returns the price to pay for an NFT on sale, applying the discount windows of his collection.
+/// 
+/// ## Arguments
+/// * `tkn_id` - id of the token
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: Full price of the nft
+/// * `Error`: NFT not existing, not on sale or collection expired
+/// 
+pub(crate) fn get_nft_price(tkn_id: u64, collection_id: String) -> Result<u128, String> {
+
+    match get_nfts()
         .get(&OwnersDoubleKey { 
             collection_id: Principal::from_text(&collection_id).expect("cannot convert from text to principal"), 
             tkn_id 
         }) {
         
-        Some(&x) => {
+        Some(x) => {
             if !x.on_sale {
                 return Err("NFT not on sale".to_string());
             }
-            match x.price {
+            match &x.price {
                 Some(price) => {
-                    get_discount(price, collection_id, x.owner)
+                    get_discount(price, collection_id, tkn_id, x.owner)
                 },
                 None => Err("NFT price not present".to_string()),
             }
         },
         None => Err("Nft does not exists".to_string()),        
-    }?;
-
-    let balance = ic_cdk::call::<(Account,), (u128,)>
-        ( MAINNET_LEDGER_CANISTER_ID, "icrc1_balance_of", (Account::from(owner),) )
-        .await 
-        .map_err(|e| format!("failed to call ledger: {:?}", e))?.0;
-
-    let fee = ic_cdk::call::<(), (u128,)>
-        ( MAINNET_LEDGER_CANISTER_ID, "icrc1_fee", () )
-        .await 
-        .map_err(|e| format!("failed to call ledger: {:?}", e))?.0;
-
-    if (price + fee) <= balance {
-        return Ok(price)
     }
-    Err("Low balance".to_string())
-
 }
 
-
 /// 
 /// Function that checks if the collection assigned to the NFT is expired or if it is owned by another person and returnes the price either discounted or not 
-/// based on the discount windows of the collection.
+/// based on the descending price or on the discount windows of the collection.
 /// 
 /// ## Arguments
 /// * `price` - default price of the NFT
 /// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
 /// * `owner` - owner of the NFT
 /// 
 /// ## Returns
 /// * `Ok`: price either discounted or not, based on the ownage of the NFT and the discount windows
-/// * `Error`: collection expired or parsing errors
+/// * `Error`: collection expired, tier of the NFT not on sale or parsing errors
 /// 
-fn get_discount(price: u32, collection_id: String, owner: Principal) -> Result<u128, String> {
+fn get_discount(price: &Nat, collection_id: String, tkn_id: u64, owner: Principal) -> Result<u128, String> {
 
     let now = ic_cdk::api::time();
-    let price = price as u128;
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse collection id to pricipal");
     let binding = get_collections();
     let collection_info = binding
-        .get(&Principal::from_text(collection_id).expect("unable to parse collection id to pricipal"))
+        .get(&collection_id)
         .expect("collection does not exists");
 
-    if owner != collection_info.owner {
-        return Ok(price)
+    if collection_info.status == CollectionStatus::Cancelled {
+        return Err("collection cancelled".to_string());
+    } else if owner != collection_info.owner {
+        return to_amount(price.clone())
     } else if now > collection_info.expire_date {
         return Err("collection Expired".to_string());
     }
+    check_tier_on_sale(collection_id, collection_info, tkn_id)?;
+
+    get_primary_price(price, collection_info, now)
+}
+
+/// 
+/// Function that returns the price of a primary sale based on the descending price or on the discount windows of the collection.
+/// 
+/// ## Arguments
+/// * `price` - default price of the NFT
+/// * `collection_info` - info of the collection
+/// * `now` - time in nanoseconds at which the price is computed
+/// 
+/// ## Returns
+/// * `Ok`: price either discounted or not
+/// * `Error`: price too big
+/// 
+fn get_primary_price(price: &Nat, collection_info: &CollectionInfo, now: u64) -> Result<u128, String> {
+
+    if let Some(dutch_pricing) = &collection_info.dutch_pricing {
+        return to_amount(get_dutch_price(price, dutch_pricing, now));
+    }
 
     match collection_info.discount_windows
         .iter()
         .filter(|x| x.expire_date > now)
         .min_by_key(|x| x.expire_date) {
 
-        Some(x) => Ok(price - ((price * (x.discount_percentage as u128)) / 100)),
-        None => Ok(price),
+        // the windows saved before the percentage was checked are capped at 100
+        Some(x) => to_amount(price.clone() - price.clone() * Nat::from(x.discount_percentage.min(100)) / Nat::from(100u32)),
+        None => to_amount(price.clone()),
     }
 }
 
+/// 
+/// Function that converts a price to an amount that can be transferred on the ledger
+/// 
+/// ## Arguments
+/// * `price` - price in the smallest unit of the payment token
+/// 
+/// ## Returns
+/// * `Ok`: price as u128
+/// * `Error`: price too big
+/// 
+fn to_amount(price: Nat) -> Result<u128, String> {
+
+    u128::try_from(&price.0).map_err(|_| "NFT price too big".to_string())
+}
+
+/// 
+/// Function that computes the floor price of an NFT on descending price, reached at the end time.
+/// 
+/// ## Arguments
+/// * `price` - default price of the NFT
+/// * `dutch_pricing` - descending price configuration of the collection
+/// 
+pub fn get_dutch_floor(price: &Nat, dutch_pricing: &DutchPricing) -> Nat {
+
+    price.clone() * Nat::from(dutch_pricing.floor_percentage) / Nat::from(100u32)
+}
+
+/// 
+/// Function that computes the descending price of an NFT at a given time.
+/// 
+/// ## Arguments
+/// * `price` - default price of the NFT, applied until the start time
+/// * `dutch_pricing` - descending price configuration of the collection
+/// * `now` - time in nanoseconds at which the price is computed
+/// 
+/// ## Returns
+/// * price decreased linearly, or by steps, towards the floor price reached at the end time
+/// 
+fn get_dutch_price(price: &Nat, dutch_pricing: &DutchPricing, now: u64) -> Nat {
+
+    let floor = get_dutch_floor(price, dutch_pricing);
+    if now <= dutch_pricing.start_time {
+        return price.clone();
+    } else if now >= dutch_pricing.end_time {
+        return floor;
+    }
+
+    let duration = dutch_pricing.end_time - dutch_pricing.start_time;
+    let mut elapsed = now - dutch_pricing.start_time;
+    if dutch_pricing.step_interval > 0 {
+        elapsed -= elapsed % dutch_pricing.step_interval;
+    }
+    price.clone() - (price.clone() - floor) * Nat::from(elapsed) / Nat::from(duration)
+}
+
+///
+/// Returns the fee taken by the platform on every sale
+/// 
+#[ic_cdk::query(guard = "caller_is_auth")]
+pub fn get_platform_fee_account() -> PlatformFee {
+
+    get_platform_fee()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::structures::DiscountWindowArg;
+
+    fn with_windows(windows: &[(u64, u8)]) -> CollectionInfo {
+        let discount_windows = windows
+            .iter()
+            .map(|(expire_date, discount_percentage)| DiscountWindowArg { expire_date: *expire_date, discount_percentage: *discount_percentage })
+            .collect();
+        CollectionInfo { expire_date: 1000, discount_windows, ..Default::default() }
+    }
+
+    fn dutch(step_interval: u64) -> DutchPricing {
+        DutchPricing { start_time: 100, end_time: 200, floor_percentage: 40, step_interval }
+    }
+
+    #[test]
+    fn dutch_price_decreases_linearly_to_the_floor() {
+        let price = Nat::from(1_000u32);
+        assert_eq!(get_dutch_price(&price, &dutch(0), 0), Nat::from(1_000u32));
+        assert_eq!(get_dutch_price(&price, &dutch(0), 100), Nat::from(1_000u32));
+        assert_eq!(get_dutch_price(&price, &dutch(0), 150), Nat::from(700u32));
+        assert_eq!(get_dutch_price(&price, &dutch(0), 175), Nat::from(550u32));
+        assert_eq!(get_dutch_price(&price, &dutch(0), 200), Nat::from(400u32));
+        assert_eq!(get_dutch_price(&price, &dutch(0), u64::MAX), Nat::from(400u32));
+        assert_eq!(get_dutch_floor(&price, &dutch(0)), Nat::from(400u32));
+    }
+
+    #[test]
+    fn dutch_price_decreases_by_steps() {
+        let price = Nat::from(1_000u32);
+        assert_eq!(get_dutch_price(&price, &dutch(25), 124), Nat::from(1_000u32));
+        assert_eq!(get_dutch_price(&price, &dutch(25), 125), Nat::from(850u32));
+        assert_eq!(get_dutch_price(&price, &dutch(25), 174), Nat::from(700u32));
+        assert_eq!(get_dutch_price(&price, &dutch(25), 199), Nat::from(550u32));
+    }
+
+    #[test]
+    fn earliest_open_window_is_applied() {
+        let price = Nat::from(1_000u32);
+        let collection = with_windows(&[(300, 10), (200, 25)]);
+        assert_eq!(get_primary_price(&price, &collection, 100), Ok(750));
+        assert_eq!(get_primary_price(&price, &collection, 200), Ok(900));
+        assert_eq!(get_primary_price(&price, &collection, 300), Ok(1_000));
+    }
+
+    #[test]
+    fn discount_never_underflows_the_price() {
+        let price = Nat::from(1_000u32);
+        assert_eq!(get_primary_price(&price, &with_windows(&[(200, 100)]), 100), Ok(0));
+        assert_eq!(get_primary_price(&price, &with_windows(&[(200, 255)]), 100), Ok(0));
+    }
+}
@@ -0,0 +1,131 @@
+use candid::Principal;
+
+use crate::common::structures::{CollectionChangeKind, CollectionInfo, Role, RoleKey};
+use crate::editing::record;
+use crate::memory::{get_collection, get_role, get_roles, insert_role, remove_role};
+
+///
+/// Role of a principal on a collection, the organizer is always an owner
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `principal` - principal to check
+///
+pub fn role_of(collection_id: Principal, collection: &CollectionInfo, principal: Principal) -> Option<Role> {
+    if collection.owner == principal {
+        return Some(Role::Owner);
+    }
+    get_role(&RoleKey { collection_id, principal })
+}
+
+///
+/// Returns if a principal can act with a role on a collection, the owners can act with every role
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `principal` - principal to check
+/// * `role` - role needed
+///
+pub fn has_role(collection_id: Principal, collection: &CollectionInfo, principal: Principal, role: Role) -> bool {
+    match role_of(collection_id, collection, principal) {
+        Some(Role::Owner) => true,
+        Some(x) => x == role,
+        None => false,
+    }
+}
+
+///
+/// Gets a collection checking that the caller can manage its roles:
+/// the owners manage the other roles, only the organizer can grant or revoke the owner role
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `role` - role granted or revoked
+///
+fn check_manager_of(collection_id: Principal, caller: Principal, role: Role) -> Result<(), String> {
+    let collection = match get_collection(&collection_id) {
+        Some(x) => x,
+        None => return Err("collection does not exists".to_string()),
+    };
+    if role == Role::Owner && collection.owner != caller {
+        return Err("only the organizer can grant or revoke the owner role".to_string());
+    }
+    if !has_role(collection_id, &collection, caller, Role::Owner) {
+        return Err("only the owners can manage the roles of the collection".to_string());
+    }
+    Ok(())
+}
+
+///
+/// Gives a role on a collection to a principal, replacing the previous one.
+/// The change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `principal` - team member
+/// * `role` - role given
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn grant(collection_id: Principal, caller: Principal, principal: Principal, role: Role) -> Result<String, String> {
+    check_manager_of(collection_id, caller, role)?;
+    let key = RoleKey { collection_id, principal };
+    if let Some(previous) = get_role(&key) {
+        check_manager_of(collection_id, caller, previous)?;
+    }
+    if get_collection(&collection_id).is_some_and(|x| x.owner == principal) {
+        return Err("the organizer is already owner of the collection".to_string());
+    }
+
+    insert_role(key, role);
+    record(collection_id, caller, CollectionChangeKind::RoleGranted { principal, role });
+    Ok(format!("role {:?} given to {}", role, principal))
+}
+
+///
+/// Removes the role of a principal on a collection.
+/// The change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `caller` - principal calling the function
+/// * `principal` - team member
+///
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+///
+pub fn revoke(collection_id: Principal, caller: Principal, principal: Principal) -> Result<String, String> {
+    let key = RoleKey { collection_id, principal };
+    let role = match get_role(&key) {
+        Some(x) => x,
+        None => return Err("the principal has no role on the collection".to_string()),
+    };
+    check_manager_of(collection_id, caller, role)?;
+
+    remove_role(&key);
+    record(collection_id, caller, CollectionChangeKind::RoleRevoked { principal, role });
+    Ok(format!("role {:?} revoked to {}", role, principal))
+}
+
+///
+/// Team of a collection, the organizer first
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+///
+pub fn team(collection_id: Principal) -> Result<Vec<(Principal, Role)>, String> {
+    let organizer = match get_collection(&collection_id) {
+        Some(x) => x.owner,
+        None => return Err("collection does not exists".to_string()),
+    };
+    let mut res = vec![(organizer, Role::Owner)];
+    res.extend(get_roles(collection_id));
+    Ok(res)
+}
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+
+use candid::{Nat, Principal};
+
+use crate::common::structures::{CollectionFilter, CollectionInfo, CollectionSort, CollectionStatus};
+use crate::memory::{find_collections, get_collection, get_collections_by_owner, get_collections_by_tag};
+
+/// Maximum number of tags of a collection
+pub const MAX_TAGS: usize = 10;
+/// Maximum length of a tag
+pub const MAX_TAG_LENGTH: usize = 32;
+/// Maximum length of the description of a collection, saved on the marketplace for the search
+pub const MAX_DESCRIPTION_LENGTH: usize = 2048;
+
+///
+/// Checks the category tags of a collection and normalizes them lowercase without spaces around and duplicates
+///
+/// ## Arguments
+/// * `tags` - tags of the collection
+///
+/// ## Returns
+/// * `Ok`: normalized tags
+/// * `Error`: String with some details about what is wrong
+///
+pub fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut res: Vec<String> = Vec::new();
+    for tag in tags.into_iter().map(|x| x.trim().to_lowercase()) {
+        if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+            return Err(format!("tags cannot be empty or longer than {} bytes", MAX_TAG_LENGTH));
+        }
+        if !res.contains(&tag) {
+            res.push(tag);
+        }
+    }
+    if res.len() > MAX_TAGS {
+        return Err(format!("a collection cannot have more than {} tags", MAX_TAGS));
+    }
+    Ok(res)
+}
+
+///
+/// Returns if a collection matches the filters that are not resolved by the indexes
+///
+/// ## Arguments
+/// * `collection` - info of the collection
+/// * `filter` - filters of the search
+/// * `now` - time in nanoseconds of the search
+///
+fn matches(collection: &CollectionInfo, filter: &CollectionFilter, now: u64) -> bool {
+    if let Some(available) = filter.available {
+        if available != (collection.expire_date > now && collection.status == CollectionStatus::Active) {
+            return false;
+        }
+    }
+    let floor_price = collection.floor_price();
+    if filter.min_price.as_ref().is_some_and(|x| floor_price < *x) || filter.max_price.as_ref().is_some_and(|x| floor_price > *x) {
+        return false;
+    }
+    if let Some(text) = &filter.text {
+        let text = text.to_lowercase();
+        if !collection.name.to_lowercase().contains(&text)
+            && !collection.description.as_ref().is_some_and(|x| x.to_lowercase().contains(&text)) {
+            return false;
+        }
+    }
+    true
+}
+
+///
+/// Returns the range of the index of a sort that can contain the collections matching the filters
+///
+/// ## Arguments
+/// * `filter` - filters of the search
+/// * `sort` - order of the collections
+/// * `now` - time in nanoseconds of the search
+///
+/// ## Returns
+/// * lowest value of the index and, if some, the highest one
+///
+fn index_bounds(filter: &CollectionFilter, sort: CollectionSort, now: u64) -> (Nat, Option<Nat>) {
+    // prices are indexed as u128, like in the index of the collections
+    let price = |x: &Nat| u128::try_from(&x.0).unwrap_or(u128::MAX);
+    match sort {
+        CollectionSort::PriceLowToHigh => (
+            Nat::from(filter.min_price.as_ref().map_or(0, price)),
+            filter.max_price.as_ref().map(|x| Nat::from(price(x))),
+        ),
+        CollectionSort::PriceHighToLow => (
+            Nat::from(filter.max_price.as_ref().map_or(0, |x| u128::MAX - price(x))),
+            filter.min_price.as_ref().map(|x| Nat::from(u128::MAX - price(x))),
+        ),
+        CollectionSort::ExpireDate if filter.available == Some(true) => (Nat::from(now + 1), None),
+        _ => (Nat::from(0u32), None),
+    }
+}
+
+///
+/// Searches the collections: the collections are read in the order of the index of the sort, within the range
+/// of the price or of the expire date filtered, and the organizer and the tags are resolved with their indexes.
+/// The collections read are loaded only when the availability, the price or the text are filtered,
+/// otherwise only the page returned is loaded
+///
+/// ## Arguments
+/// * `filter` - filters of the search
+/// * `sort` - order of the collections
+/// * `offset` - Offset of the first collection to retrieve
+/// * `limit` - Number of collections to retrieve
+///
+/// ## Returns
+/// * collections found with their canister id
+///
+pub fn search(filter: CollectionFilter, sort: CollectionSort, offset: u32, limit: u32) -> Vec<(Principal, CollectionInfo)> {
+    let mut candidates: Option<HashSet<Principal>> = None;
+    if let Some(owner) = filter.owner {
+        candidates = Some(get_collections_by_owner(owner).into_iter().collect());
+    }
+    if !filter.tags.is_empty() {
+        let tagged = filter.tags
+            .iter()
+            .flat_map(|x| get_collections_by_tag(x.trim().to_lowercase()))
+            .collect::<HashSet<Principal>>();
+        candidates = Some(match candidates {
+            Some(x) => x.intersection(&tagged).cloned().collect(),
+            None => tagged,
+        });
+    }
+
+    let now = ic_cdk::api::time();
+    let (start, end) = index_bounds(&filter, sort, now);
+    let load = filter.available.is_some() || filter.min_price.is_some() || filter.max_price.is_some() || filter.text.is_some();
+    let found = find_collections(
+        sort,
+        start,
+        end,
+        |collection_id| {
+            if candidates.as_ref().is_some_and(|x| !x.contains(collection_id)) {
+                return None;
+            }
+            if !load {
+                return Some((*collection_id, None));
+            }
+            get_collection(collection_id)
+                .filter(|x| matches(x, &filter, now))
+                .map(|x| (*collection_id, Some(x)))
+        },
+        offset as usize,
+        limit as usize,
+    );
+    found
+        .into_iter()
+        .filter_map(|(collection_id, collection)| collection.or_else(|| get_collection(&collection_id)).map(|x| (collection_id, x)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_normalized() {
+        let tags = vec![" Music ".to_string(), "music".to_string(), "ROME".to_string()];
+        assert_eq!(normalize_tags(tags), Ok(vec!["music".to_string(), "rome".to_string()]));
+        assert_eq!(normalize_tags(Vec::new()), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_tags_are_rejected() {
+        assert!(normalize_tags(vec!["  ".to_string()]).is_err());
+        assert!(normalize_tags(vec!["x".repeat(MAX_TAG_LENGTH + 1)]).is_err());
+        assert!(normalize_tags(vec!["x".repeat(MAX_TAG_LENGTH)]).is_ok());
+
+        let many = (0..=MAX_TAGS).map(|x| format!("tag{}", x)).collect::<Vec<String>>();
+        assert!(normalize_tags(many).is_err());
+        // duplicates are counted once
+        let repeated = (0..=MAX_TAGS).map(|_| "tag".to_string()).collect::<Vec<String>>();
+        assert!(normalize_tags(repeated).is_ok());
+    }
+
+    #[test]
+    fn bounds_follow_the_filters() {
+        let filter = CollectionFilter { min_price: Some(Nat::from(10u32)), max_price: Some(Nat::from(20u32)), ..Default::default() };
+        assert_eq!(index_bounds(&filter, CollectionSort::PriceLowToHigh, 0), (Nat::from(10u32), Some(Nat::from(20u32))));
+        assert_eq!(
+            index_bounds(&filter, CollectionSort::PriceHighToLow, 0),
+            (Nat::from(u128::MAX - 20), Some(Nat::from(u128::MAX - 10)))
+        );
+        assert_eq!(index_bounds(&filter, CollectionSort::Newest, 0), (Nat::from(0u32), None));
+
+        let available = CollectionFilter { available: Some(true), ..Default::default() };
+        assert_eq!(index_bounds(&available, CollectionSort::ExpireDate, 5), (Nat::from(6u32), None));
+        let ended = CollectionFilter { available: Some(false), ..Default::default() };
+        assert_eq!(index_bounds(&ended, CollectionSort::ExpireDate, 5), (Nat::from(0u32), None));
+        assert_eq!(index_bounds(&CollectionFilter::default(), CollectionSort::PriceHighToLow, 0), (Nat::from(0u32), None));
+    }
+}
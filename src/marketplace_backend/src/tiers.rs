@@ -0,0 +1,227 @@
+use candid::Principal;
+
+use crate::common::structures::{CollectionInfo, CollectionNfts, NftMetadata, OwnersDoubleKey, TierInfo};
+use crate::memory::{get_collections, get_tier_token, get_tier_tokens, insert_collection_record};
+
+///
+/// Checks the sale windows of the tiers passed at the creation of a collection
+///
+/// ## Arguments
+/// * `nfts` - tiers of the collection
+/// * `expire_date` - expire date of the collection
+/// * `now` - current time
+///
+/// ## Returns
+/// * `Ok`: the sale windows are valid
+/// * `Error`: String with some details about what is wrong
+///
+pub fn validate_tiers(nfts: &[NftMetadata], expire_date: u64, now: u64) -> Result<(), String> {
+    for x in nfts.iter() {
+        if x.sale_end.is_some_and(|end| end > expire_date || end <= now) {
+            return Err(format!("sale end of tier {} cannot be in the past or after the expire date", x.token_name));
+        }
+        if let (Some(start), Some(end)) = (x.sale_start, x.sale_end) {
+            if start >= end {
+                return Err(format!("sale start of tier {} needs to be before his sale end", x.token_name));
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Index of the tier of a collection an NFT belongs to
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `tkn_id` - id of the token
+///
+pub fn tier_of(collection_id: Principal, collection: &CollectionInfo, tkn_id: u64) -> Option<usize> {
+    collection.nfts
+        .iter()
+        .position(|x| x.tkn_ids.contains(&tkn_id))
+        .or_else(|| get_tier_token(&OwnersDoubleKey { collection_id, tkn_id }).map(|x| x as usize))
+}
+
+///
+/// Ids of all the NFTs of a collection, sorted
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+///
+pub fn tkn_ids(collection_id: Principal, collection: &CollectionInfo) -> Vec<u64> {
+    let mut res = collection.nfts
+        .iter()
+        .flat_map(|x| x.tkn_ids.iter().copied())
+        .chain(get_tier_tokens(collection_id).into_iter().map(|x| x.0))
+        .collect::<Vec<u64>>();
+    res.sort();
+    res
+}
+
+///
+/// Returns if an NFT belongs to a soulbound tier, it can be sold only by the organizer
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `tkn_id` - id of the token
+///
+pub fn is_soulbound(collection_id: Principal, collection: &CollectionInfo, tkn_id: u64) -> bool {
+    tier_of(collection_id, collection, tkn_id).is_some_and(|x| collection.nfts[x].nft.soulbound.unwrap_or(false))
+}
+
+///
+/// Checks that the tier of an NFT can be sold by the organizer at the current time
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `collection` - info of the collection
+/// * `tkn_id` - id of the token
+///
+/// ## Returns
+/// * `Ok`: the tier is on sale
+/// * `Error`: String with some details about why the tier is not on sale
+///
+pub fn check_tier_on_sale(collection_id: Principal, collection: &CollectionInfo, tkn_id: u64) -> Result<(), String> {
+    match tier_of(collection_id, collection, tkn_id) {
+        Some(index) => check_tier_window(&collection.nfts[index]),
+        None => Ok(()),
+    }
+}
+
+///
+/// Checks that the current time is inside the sale window of a tier
+///
+/// ## Arguments
+/// * `tier` - tier of the collection
+///
+/// ## Returns
+/// * `Ok`: the tier is on sale
+/// * `Error`: String with some details about why the tier is not on sale
+///
+pub fn check_tier_window(tier: &CollectionNfts) -> Result<(), String> {
+    let now = ic_cdk::api::time();
+    if tier.nft.sale_start.is_some_and(|start| now < start) {
+        return Err(format!("sale of tier {} not started yet", tier.nft.token_name));
+    }
+    if tier.nft.sale_end.is_some_and(|end| now > end) {
+        return Err(format!("sale of tier {} ended", tier.nft.token_name));
+    }
+    Ok(())
+}
+
+///
+/// Number of NFTs of a tier not sold yet
+///
+/// ## Arguments
+/// * `tier` - tier of the collection
+///
+pub fn remaining(tier: &CollectionNfts) -> u64 {
+    tier.nft.quantity.saturating_sub(tier.sold)
+}
+
+///
+/// Returns if a tier has NFTs that can still be minted, the NFTs being minted are counted as already minted
+///
+/// ## Arguments
+/// * `tier` - tier of the collection
+///
+pub fn mintable(tier: &CollectionNfts) -> bool {
+    (tier.tkn_ids.len() as u64).saturating_add(tier.reserved) < tier.nft.quantity
+}
+
+///
+/// Counts a primary sale of an NFT on his tier
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `tkn_id` - id of the token sold
+///
+pub fn add_sold(collection_id: Principal, tkn_id: u64) {
+    if let Some(mut collection) = get_collections().get(&collection_id).cloned() {
+        if let Some(index) = tier_of(collection_id, &collection, tkn_id) {
+            collection.nfts[index].sold += 1;
+            insert_collection_record(collection_id, collection);
+        }
+    }
+}
+
+///
+/// Availability of every tier of a collection
+///
+/// ## Arguments
+/// * `collection` - info of the collection
+///
+pub fn tiers_info(collection: &CollectionInfo) -> Vec<TierInfo> {
+    collection.nfts
+        .iter()
+        .map(|x| TierInfo {
+            token_name: x.nft.token_name.clone(),
+            token_privilege_code: x.nft.token_privilege_code,
+            price: x.nft.price.clone(),
+            sale_start: x.nft.sale_start,
+            sale_end: x.nft.sale_end,
+            supply: x.nft.quantity,
+            remaining: remaining(x),
+            sold_out: remaining(x) == 0,
+            soulbound: x.nft.soulbound.unwrap_or(false),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use candid::Nat;
+
+    use super::*;
+
+    const NOW: u64 = 1_000;
+
+    fn tier(quantity: u64, sale_start: Option<u64>, sale_end: Option<u64>) -> NftMetadata {
+        NftMetadata {
+            token_name: "tier".to_string(),
+            token_privilege_code: 0,
+            token_description: String::new(),
+            quantity,
+            token_logo: String::new(),
+            price: Nat::from(100u32),
+            sale_start,
+            sale_end,
+            soulbound: None,
+        }
+    }
+
+    #[test]
+    fn sale_windows_inside_the_collection_are_valid() {
+        let tiers = vec![tier(10, None, None), tier(10, Some(NOW), Some(2_000)), tier(10, None, Some(5_000))];
+        assert!(validate_tiers(&tiers, 5_000, NOW).is_ok());
+    }
+
+    #[test]
+    fn invalid_sale_windows_are_rejected() {
+        assert!(validate_tiers(&[tier(10, None, Some(NOW))], 5_000, NOW).is_err());
+        assert!(validate_tiers(&[tier(10, None, Some(5_001))], 5_000, NOW).is_err());
+        assert!(validate_tiers(&[tier(10, Some(2_000), Some(2_000))], 5_000, NOW).is_err());
+    }
+
+    #[test]
+    fn remaining_counts_the_nfts_not_sold() {
+        let sold = |sold| CollectionNfts { nft: tier(10, None, None), tkn_ids: Vec::new(), sold, reserved: 0 };
+        assert_eq!(remaining(&sold(3)), 7);
+        assert_eq!(remaining(&sold(10)), 0);
+        assert_eq!(remaining(&sold(12)), 0);
+    }
+
+    #[test]
+    fn mintable_counts_the_old_and_the_reserved_nfts() {
+        let tier = |tkn_ids: Vec<u64>, reserved| CollectionNfts { nft: tier(3, None, None), tkn_ids, sold: 0, reserved };
+        assert!(mintable(&tier(Vec::new(), 2)));
+        assert!(mintable(&tier(vec![1], 1)));
+        assert!(!mintable(&tier(vec![1], 2)));
+        assert!(!mintable(&tier(Vec::new(), 3)));
+        assert!(!mintable(&tier(vec![1, 2, 3], 0)));
+    }
+}
@@ -1,17 +1,43 @@
-use std::str::FromStr;
 use candid::{Nat, Principal};
 use ic_ledger_types::MAINNET_LEDGER_CANISTER_ID;
 use icrc_ledger_types::icrc1::account::Account;
-use icrc_ledger_types::icrc1::transfer::{BlockIndex, TransferArg, TransferError as TransferErrorICRC};
 
-use crate::common::structures::{CollectionInfo, CollectionNfts, Errors, IcrcTransferArg, MintArg, OwnersDoubleKey, TransferArgs, TransferError};
-use crate::common::{guards::caller_is_auth, structures::Arg};
-use crate::factory::mint_collection_canister;
-use crate::memory::{get_nfts, insert_nft_record, insert_collection_record};
+use crate::common::structures::{AttendanceArg, CollectionInfo, CollectionNfts, CollectionStatus, DiscountWindowArg, Errors, FundingStatus, Milestone, NftMarketData, OwnersDoubleKey, PaymentToken, PayoutKind, Privilege, PurchaseRecord, Role, SettlementMode, SignedCheckIn, TransferArgs, TransferError};
+use crate::common::guards::{caller_is_auth, caller_is_controller};
+use crate::common::structures::{Arg, PlatformFee};
+use crate::attendance::reward;
+use crate::auctions::{bid, on_auction, start as start_auction_nft};
+use crate::cancellation::cancel;
+use crate::checkin::{check_in, snapshot, sync};
+use crate::collection::{owner_of, redeemed_at, transfer_token};
+use crate::crowdfunding::{goal_reached, withdraw_raised};
+use crate::creation::{create, create_draft, publish, resume, rollback, update_draft};
+use crate::editing::{add_discount_window as add_window, extend_expire_date as extend_expiry, remove_discount_window as remove_window, update_metadata, MAX_DISCOUNT_WINDOWS, MAX_LOGO_SIZE};
+use crate::factory::encode_init_arg;
+use crate::ledger::{decimals, escrow_account, fee, ledger_of, transfer_from};
+use crate::memory::{get_collections, get_nfts, insert_nft_record, insert_purchase_record, set_platform_fee, update_purchase_record};
+use crate::milestones::{approve_release, release_remaining, request_release, validate_milestones};
+use crate::minting::buy;
+use crate::offers::{accept as accept_nft_offer, cancel as cancel_nft_offer, make as make_nft_offer};
+use crate::payouts::{pay_from_escrow, retry_failed, settle_sale, BPS_DENOMINATOR};
+use crate::presale::{add as add_allowlist, check_buyer, remove as remove_allowlist};
+use crate::privileges::{claim, remove_privilege, set_privilege};
+use crate::roles::{grant, revoke};
+use crate::search::{normalize_tags, MAX_DESCRIPTION_LENGTH};
+use crate::query_methods::{get_dutch_floor, get_nft_price};
+use crate::tiers::{is_soulbound, validate_tiers};
+
+/// Maximum royalties on secondary sales an organizer can set, in basis points
+pub const MAX_ROYALTY_BPS: u16 = 5_000;
 
 ///
 /// Creates a collection of nft using the ICRC-7 standard and saves in database the principal of the owner of the colletion and the id of the canister collection.
-/// Then creates NFTs to the values passed as arguments of different types (Ex: premium, standard, VIP).
+/// Then registers the tiers of NFTs passed as arguments (Ex: premium, standard, VIP), 
+/// the NFTs are not minted here but to the buyers when they pay with `buy_nft`.
+/// The creation is saved step by step, if a step fails the error contains the id of the creation 
+/// that can be resumed with `resume_collection_creation` or rolled back with `rollback_collection_creation`.
+/// Until `presale_end` only the principals added with `add_to_allowlist` can buy, 
+/// `max_per_wallet` limits the NFTs a principal can buy from the organizer.
 ///
 /// ## Arguments
 /// * `arg`: 
@@ -20,7 +46,16 @@ use crate::memory::{get_nfts, insert_nft_record, insert_collection_record};
 ///         pub canister_arg: CanisterArg,
 ///         pub nfts: Vec<NftMetadata>,
 ///         pub expire_date: u64,
-///         pub discount_windows: Vec<DiscountWindowArg>
+///         pub discount_windows: Vec<DiscountWindowArg>,
+///         pub royalty_bps: u16,
+///         pub funding_goal: Option<Nat>,
+///         pub settlement: SettlementMode,
+///         pub milestones: Vec<MilestoneArg>,
+///         pub dutch_pricing: Option<DutchPricing>,
+///         pub ledger: Option<Principal>,
+///         pub presale_end: Option<u64>,
+///         pub max_per_wallet: Option<u32>,
+///         pub tags: Vec<String>
 ///     }
 /// 
 /// ```
@@ -31,6 +66,31 @@ use crate::memory::{get_nfts, insert_nft_record, insert_collection_record};
 #[ic_cdk::update(guard = "caller_is_auth")]
 pub async fn create_collection_nfts(arg: Arg) -> Result<String, Errors> {
 
+    let (init_arg, collection) = prepare_collection(arg, CollectionStatus::Active).await?;
+
+    match create(ic_cdk::caller(), init_arg, collection).await {
+        Ok(canister_id) => Ok(canister_id.to_string()),
+        Err(message) => Err(Errors::GenericError { 
+            message, 
+            error_code: 400
+        }),
+    }
+}
+
+///
+/// Checks the arg passed to create a collection and prepares the init arg of the collection canister 
+/// and the record of the collection saved when it is registered
+///
+/// ## Arguments
+/// * `arg` - arg passed to create the collection
+/// * `status` - status of the collection, `Draft` if the collection is not published yet
+/// 
+/// ## Returns
+/// * `Ok`: candid encoded init arg and record of the collection
+/// * `Error`: Error of type Errors
+/// 
+async fn prepare_collection(arg: Arg, status: CollectionStatus) -> Result<(Vec<u8>, CollectionInfo), Errors> {
+
     if arg.expire_date <= ic_cdk::api::time() {
         return Err(Errors::GenericError { 
             message: "Error: Expiration date cannot be in the past".to_string(), 
@@ -38,6 +98,13 @@ pub async fn create_collection_nfts(arg: Arg) -> Result<String, Errors> {
         });
     }
 
+    if arg.discount_windows.len() > MAX_DISCOUNT_WINDOWS {
+        return Err(Errors::GenericError { 
+            message: format!("a collection cannot have more than {} discount windows", MAX_DISCOUNT_WINDOWS), 
+            error_code: 400
+        });
+    }
+
     for x in arg.discount_windows.iter() {
         if x.expire_date >= arg.expire_date || x.expire_date <= ic_cdk::api::time() {
             return Err(Errors::GenericError { 
@@ -45,6 +112,12 @@ pub async fn create_collection_nfts(arg: Arg) -> Result<String, Errors> {
                 error_code: 400
             });
         }
+        if x.discount_percentage > 100 {
+            return Err(Errors::GenericError { 
+                message: "Error: discount percentage cannot be higher than 100".to_string(), 
+                error_code: 400
+            });
+        }
     }
 
     if arg.nfts.iter().map(|x| x.quantity).sum::<u64>() as u128 != arg.canister_arg.icrc7_supply_cap {
@@ -54,103 +127,284 @@ pub async fn create_collection_nfts(arg: Arg) -> Result<String, Errors> {
         });
     }
 
-    let canister_id = match mint_collection_canister(arg.canister_arg).await {
-        Ok(x) => Principal::from_str(&x).expect("unable to tranform string to Principal"),
+    if arg.nfts.iter().any(|x| u128::try_from(&x.price.0).is_err()) {
+        return Err(Errors::GenericError { 
+            message: "price of the NFTs too big".to_string(), 
+            error_code: 400
+        });
+    }
+
+    if arg.royalty_bps > MAX_ROYALTY_BPS {
+        return Err(Errors::GenericError { 
+            message: format!("royalties cannot be higher than {} basis points", MAX_ROYALTY_BPS), 
+            error_code: 400
+        });
+    }
+
+    if arg.settlement == SettlementMode::AllOrNothing && arg.funding_goal.as_ref().is_none_or(|x| *x == 0u32) {
+        return Err(Errors::GenericError { 
+            message: "all-or-nothing collections need a funding goal greater than 0".to_string(), 
+            error_code: 400
+        });
+    }
+
+    if let Some(x) = &arg.dutch_pricing {
+        if !arg.discount_windows.is_empty() {
+            return Err(Errors::GenericError { 
+                message: "descending price cannot be used together with discount windows".to_string(), 
+                error_code: 400
+            });
+        }
+        if x.start_time >= x.end_time || x.end_time > arg.expire_date || x.floor_percentage == 0 || x.floor_percentage > 100 || x.step_interval >= x.end_time - x.start_time {
+            return Err(Errors::GenericError { 
+                message: "descending price needs start time before end time, end time before the expire date, a floor percentage between 1 and 100 and a step interval shorter than the decay".to_string(), 
+                error_code: 400
+            });
+        }
+    }
+
+    if arg.presale_end.is_some_and(|x| x <= ic_cdk::api::time() || x >= arg.expire_date) {
+        return Err(Errors::GenericError { 
+            message: "the presale cannot end in the past or after the expire date".to_string(), 
+            error_code: 400
+        });
+    }
+
+    if arg.max_per_wallet == Some(0) {
+        return Err(Errors::GenericError { 
+            message: "the maximum number of NFTs per principal must be greater than 0".to_string(), 
+            error_code: 400
+        });
+    }
+
+    if let Err(message) = validate_tiers(&arg.nfts, arg.expire_date, ic_cdk::api::time()) {
+        return Err(Errors::GenericError { 
+            message, 
+            error_code: 400
+        });
+    }
+
+    if let Err(message) = validate_milestones(&arg.milestones, ic_cdk::api::time()) {
+        return Err(Errors::GenericError { 
+            message, 
+            error_code: 400
+        });
+    }
+
+    if arg.canister_arg.icrc7_description.as_ref().is_some_and(|x| x.len() > MAX_DESCRIPTION_LENGTH) {
+        return Err(Errors::GenericError { 
+            message: format!("description cannot be longer than {} bytes", MAX_DESCRIPTION_LENGTH), 
+            error_code: 400
+        });
+    }
+
+    if arg.canister_arg.icrc7_logo.as_ref().is_some_and(|x| x.len() > MAX_LOGO_SIZE) {
+        return Err(Errors::GenericError { 
+            message: format!("logo cannot be bigger than {} bytes", MAX_LOGO_SIZE), 
+            error_code: 400
+        });
+    }
+
+    let tags = match normalize_tags(arg.tags) {
+        Ok(x) => x,
         Err(message) => return Err(Errors::GenericError { 
             message, 
             error_code: 400
         }),
     };
 
-    let mut tkn_id = 1;
+    // the ledger is queried before creating the canister so that an invalid ledger is rejected
+    let ledger = arg.ledger.unwrap_or(MAINNET_LEDGER_CANISTER_ID);
+    let (token, ledger_fee) = match (decimals(ledger).await, fee(ledger).await) {
+        (Ok(decimals), Ok(ledger_fee)) => (PaymentToken { ledger, decimals }, ledger_fee),
+        (Err(e), _) | (_, Err(e)) => return Err(Errors::GenericError { 
+            message: format!("payment token ledger not valid: {}", e), 
+            error_code: 400
+        }),
+    };
+
+    // the buyers at the floor price pay the fee of the transfer to the organizer
+    if arg.dutch_pricing.as_ref().is_some_and(|x| arg.nfts.iter().any(|nft| get_dutch_floor(&nft.price, x) <= ledger_fee)) {
+        return Err(Errors::GenericError { 
+            message: format!("the floor price of every tier must be greater than the fee of the ledger: {}", ledger_fee), 
+            error_code: 400
+        });
+    }
+
     let caller = ic_cdk::caller();
+    let name = arg.canister_arg.icrc7_name.clone();
+    let description = arg.canister_arg.icrc7_description.clone();
+    let init_arg = encode_init_arg(arg.canister_arg, caller, arg.royalty_bps);
 
-    let mut nfts: Vec<CollectionNfts> = Vec::new();
-
-    for x in arg.nfts.iter() {
-        let mut mint_arg = MintArg {
-            to: Account {
-                owner: caller,
-                subaccount: None,
-            },
-            memo: None,
-            token_id: tkn_id,
-            from_subaccount: None,
-            token_description: Some(x.token_description.clone()),
-            token_logo: Some(x.token_logo.clone()),
-            token_name: Some(x.token_name.clone()),
-            token_privilege_code: Some(x.token_privilege_code),
-        };
-        let mut tkn_ids:Vec<u64> = Vec::new();
-        for _ in 0..x.quantity {
-
-            tkn_ids.push(tkn_id as u64);
-
-            let (mint_result,): (Result<u128, Errors>,) = ic_cdk::call(canister_id, "icrc7_mint", (&mint_arg, caller,))
-            .await
-            .expect("Error in minting NFT");
-
-            if mint_result.is_err() {
-                return Err(mint_result.expect_err("error message not loaded"));
-            }
-            insert_nft_record(canister_id, tkn_id as u64, caller, Some(x.price), true);
-            tkn_id += 1;
-            mint_arg.token_id = tkn_id;
-        }
-        nfts.push(CollectionNfts {nft: x.clone(), tkn_ids});
+    // the NFTs are minted to the buyers at purchase time, the tiers are saved as templates
+    let nfts = arg.nfts
+        .into_iter()
+        .map(|x| CollectionNfts { nft: x, tkn_ids: Vec::new(), sold: 0, reserved: 0 })
+        .collect::<Vec<CollectionNfts>>();
+
+    let collection = CollectionInfo { 
+        owner: caller, 
+        expire_date: arg.expire_date, 
+        discount_windows: arg.discount_windows, 
+        nfts, 
+        royalty_bps: arg.royalty_bps,
+        funding_goal: arg.funding_goal,
+        settlement: arg.settlement,
+        raised: Nat::from(0u32),
+        funding_status: FundingStatus::Open,
+        milestones: arg.milestones.into_iter().map(Milestone::from).collect(),
+        released: Nat::from(0u32),
+        status,
+        dutch_pricing: arg.dutch_pricing,
+        token,
+        next_tkn_id: 1,
+        presale_end: arg.presale_end,
+        max_per_wallet: arg.max_per_wallet,
+        name,
+        description,
+        tags,
+        created_at: 0,
+        attendance_collection: None
+    };
+
+    Ok((init_arg, collection))
+}
+
+///
+/// Saves a draft of a collection, nothing is created, minted or charged until the draft is published with `publish_collection`.
+/// The arg is checked like in `create_collection_nfts`.
+///
+/// ## Arguments
+/// * `arg` - arg passed to create the collection
+/// 
+/// ## Returns
+/// * `Ok`: id of the draft
+/// * `Error`: Error of type Errors
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn create_collection_draft(arg: Arg) -> Result<u64, Errors> {
+
+    let (init_arg, collection) = prepare_collection(arg, CollectionStatus::Draft).await?;
+
+    Ok(create_draft(ic_cdk::caller(), init_arg, collection))
+}
+
+///
+/// Replaces a draft of a collection with a new arg, only the owner of the draft can call it before publishing it
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// * `arg` - arg passed to create the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: Error of type Errors
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn update_collection_draft(draft_id: u64, arg: Arg) -> Result<String, Errors> {
+
+    let (init_arg, collection) = prepare_collection(arg, CollectionStatus::Draft).await?;
+
+    match update_draft(draft_id, ic_cdk::caller(), init_arg, collection) {
+        Ok(()) => Ok(format!("draft {} updated", draft_id)),
+        Err(message) => Err(Errors::GenericError { 
+            message, 
+            error_code: 400
+        }),
+    }
+}
+
+///
+/// Publishes a draft of a collection: the collection canister is created and the collection registered on the marketplace.
+/// Only the owner of the draft can call it, if a step fails the creation can be resumed or rolled back like in `create_collection_nfts`.
+///
+/// ## Arguments
+/// * `draft_id` - id of the draft
+/// 
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: Error of type Errors
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn publish_collection(draft_id: u64) -> Result<String, Errors> {
+
+    match publish(draft_id, ic_cdk::caller()).await {
+        Ok(canister_id) => Ok(canister_id.to_string()),
+        Err(message) => Err(Errors::GenericError { 
+            message, 
+            error_code: 400
+        }),
     }
-    insert_collection_record(canister_id, CollectionInfo { owner: caller, expire_date: arg.expire_date, discount_windows: arg.discount_windows, nfts});
+}
+
+///
+/// Resumes the creation of a collection stopped by an error, from the last step completed.
+/// Only the owner of the collection can call it.
+///
+/// ## Arguments
+/// * `job_id` - id of the creation, returned in the error of `create_collection_nfts`
+/// 
+/// ## Returns
+/// * `Ok`: canister id of the collection
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn resume_collection_creation(job_id: u64) -> Result<String, String> {
 
-    Ok(canister_id.to_string())
+    resume(job_id, ic_cdk::caller()).await.map(|x| x.to_string())
 }
 
 ///
-/// Transfer amount of tokens from an account to another,
-/// before calling this function it is needed to approve the tokens to transfer + the transaction fee to this backend canister
-/// so that this canister can have the allowance to transfer tokens in behalf of the caller
+/// Rolls back the creation of a collection not completed, the collection canister is deleted if it has been created.
+/// A draft rolled back is discarded. Only the owner of the collection can call it.
 ///
 /// ## Arguments
-/// * `args`: 
-/// ```
-///     type TransferArgs = record { 
-///       amount : nat; 
-///       tkn_id : nat;
-///       collection_id : text;
-///     };
-/// ```
-/// * caller: account to which the nft will be transferred
+/// * `job_id` - id of the creation, returned in the error of `create_collection_nfts`
 /// 
 /// ## Returns
-/// * `Ok`: Transaction id
+/// * `Ok`: Successful message
 /// * `Error`: String with some details about what went wrong
 /// 
-async fn transfer(amount: Nat, to: Principal) -> Result<BlockIndex, String> {
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn rollback_collection_creation(job_id: u64) -> Result<String, String> {
 
-    ic_cdk::println!(
-        "Transferring {} tokens to account {}",
-        amount,
-        to,
-    );
+    rollback(job_id, ic_cdk::caller()).await
+}
 
-    let transfer_args: TransferArg = TransferArg {
-        memo: None,
-        amount,
-        from_subaccount: None,
-        fee: None,
-        to: Account::from(to),
-        created_at_time: None,
-    };
+///
+/// Purchase of an NFT of a tier of a collection, the NFT is minted to the caller after the payment.
+/// The price to pay is computed by the backend applying the descending price or the discount windows of the collection.
+/// Before calling this function it is needed to approve, using ICRC-2, the price of the NFT + the transaction fee to this backend canister.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tier` - index of the tier in the collection
+/// * `amount` - maximum amount the caller is willing to pay
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn buy_nft(collection_id: String, tier: u32, amount: Nat) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
 
-    ic_cdk::call::<(TransferArg,), (Result<BlockIndex, TransferErrorICRC>,)>
-        ( MAINNET_LEDGER_CANISTER_ID, "icrc1_transfer", (transfer_args,),)
-            .await 
-            .map_err(|e| format!("failed to call ledger: {:?}", e))?
-            .0
-            .map_err(|e| format!("ledger transfer error {:?}", e))
+    buy(collection_id, tier, ic_cdk::caller(), amount).await
 }
 
 ///
-/// Transfer NFT from an account to another,
+/// Purchase of an NFT on sale, the price to pay is computed by the backend applying the discount windows of the collection.
+/// Before calling this function it is needed to approve, using ICRC-2, the price of the NFT + the transaction fee to this backend canister.
+/// 
+/// The purchase happens in the following steps:
+/// 1) the NFT is locked so that no one else can buy it while the purchase is in progress
+/// 2) the price is moved from the caller to the escrow subaccount of the collection with `icrc2_transfer_from`
+/// 3) the NFT is transferred from the owner to the caller with `icrc7_transfer`
+/// 4) the escrowed price (minus the ledger fee) is settled to the owner of the NFT, 
+///    or refunded to the caller if the NFT could not be transferred.
+///    The primary sales of all-or-nothing collections and of collections released by milestones stay in escrow.
+/// 
+/// Every step is recorded on a purchase receipt containing the block indexes of the ledger transactions.
 ///
 /// ## Arguments
 /// * `args`:
@@ -159,6 +413,7 @@ async fn transfer(amount: Nat, to: Principal) -> Result<BlockIndex, String> {
 ///       tkn_id : nat;
 ///       collection_id : text;
 ///     };
+///   where `amount` is the maximum amount the caller is willing to pay
 /// 
 /// ## Returns
 /// * `Ok`: Successful message
@@ -167,55 +422,810 @@ async fn transfer(amount: Nat, to: Principal) -> Result<BlockIndex, String> {
 #[ic_cdk::update(guard = "caller_is_auth")]
 pub async fn transfer_nft(args: TransferArgs) -> Result<String, String> {
     let collection_id = Principal::from_text(args.clone().collection_id).expect("unable to parse string to principal");
+    let tkn_id = args.tkn_id as u64;
 
-    let binding = get_nfts();
-    let owner_nft = binding
-        .get(
-            &OwnersDoubleKey {
-                collection_id: Principal::from_text(args.clone().collection_id).expect("unable to convert collection id to principal"), 
-                tkn_id: args.tkn_id as u64
-            });
+    let nft = match get_nfts().get(&OwnersDoubleKey { collection_id, tkn_id }) {
+        Some(x) => x.clone(),
+        None => return Err("nft does not exists".to_string()),
+    };
 
-    if owner_nft.is_none() {
-        return Err("nft does not exists".to_string());
+    let price = get_nft_price(tkn_id, args.clone().collection_id)?;
+    if price > args.amount {
+        return Err(format!("the price of the NFT is {}, higher than the amount passed", price));
     }
 
-    let owner_nft = owner_nft.unwrap().owner;
+    let owner_nft = nft.owner;
     let caller = ic_cdk::caller();
+    if owner_nft == caller {
+        return Err("the caller already owns the NFT".to_string());
+    }
 
-    let transfer_nft: Result<u128, TransferError> = match ic_cdk::call::<(Vec<IcrcTransferArg>, Option<Principal> ), (Vec<Option<Result<u128, TransferError>>>,)>(
-        collection_id, 
-        "icrc7_transfer", 
-        ([IcrcTransferArg {
-            from_subaccount: None, 
-            to: Account::from(caller), 
-            token_id: args.tkn_id, 
-            memo: None, 
-            created_at_time: None
-        }].to_vec(), Some(owner_nft), ), )
-    .await
-    .map_err(|e| format!("failed to call collection: {:?}", e))?
-    .0.first() {
-        Some(Some(trasfer_el)) => {
-            trasfer_el.clone()
+    // the presale and the limit of NFTs per principal apply only to the NFTs sold by the organizer
+    let primary = match get_collections().get(&collection_id) {
+        Some(x) if x.owner == owner_nft => {
+            check_buyer(collection_id, x, caller, None)?;
+            true
         },
-        _ => Err(TransferError::GenericError { error_code: 400, message: "error in transfering NFT".to_string() })
+        _ => false,
+    };
+
+    // lock the NFT during the purchase
+    insert_nft_record(collection_id, tkn_id, owner_nft, nft.price.clone(), false);
+
+    let ledger = ledger_of(&collection_id);
+    let payment_block = match transfer_from(ledger, Nat::from(price), Account::from(caller), escrow_account(&collection_id)).await {
+        Ok(block) => block,
+        Err(e) => {
+            insert_nft_record(collection_id, tkn_id, owner_nft, nft.price, nft.on_sale);
+            return Err(format!("Error in transferring the tokens from the caller to the escrow: {}", e));
+        }
+    };
+
+    let mut purchase = PurchaseRecord {
+        collection_id,
+        tkn_id,
+        buyer: caller,
+        seller: owner_nft,
+        amount: Nat::from(price),
+        timestamp: ic_cdk::api::time(),
+        payment_block: payment_block.clone(),
+        settlement_block: None,
+        refund_block: None,
+        royalty_block: None,
+        platform_fee_block: None,
+        escrowed: false,
+        refunded: false,
+    };
+    let purchase_id = insert_purchase_record(purchase.clone());
+
+    // the collection could have been cancelled or the limit of the caller reached by concurrent purchases while the payment was in progress
+    let allowed = match get_collections().get(&collection_id) {
+        Some(x) if x.status == CollectionStatus::Cancelled => Err("collection cancelled".to_string()),
+        Some(x) if primary => check_buyer(collection_id, x, caller, Some(purchase_id)),
+        _ => Ok(()),
+    };
+
+    let transfer_nft: Result<u128, TransferError> = match allowed {
+        Err(message) => Err(TransferError::GenericError { error_code: 400, message }),
+        Ok(()) => transfer_token(collection_id, tkn_id, owner_nft, caller).await,
     };
 
+    let ledger_fee = fee(ledger).await.unwrap_or_default();
+
     match transfer_nft {
         Ok(_) => {
-            insert_nft_record(collection_id, args.tkn_id as u64, caller, None, false);
-            if let Some(e) = transfer(args.amount, owner_nft).await.err() {
-                return Err(format!("Error in transfering the tokens from backend to owner of nft, : {}", e))
-            }
-            Ok(format!("NFT with token id: {}, transferred from {} to {} correctly", args.tkn_id, owner_nft, caller))
+            insert_nft_record(collection_id, tkn_id, caller, None, false);
+            let settlement = settle_sale(purchase_id, purchase, price, ledger_fee).await;
+            Ok(format!(
+                "NFT with token id: {}, transferred from {} to {} correctly, payment block: {}, settlement: {}", 
+                args.tkn_id, owner_nft, caller, payment_block, settlement
+            ))
         },
         Err(e) => {
-            if let Some(transf_e) = transfer(args.amount, caller).await.err() {
-                return Err(format!("Error in connecting to the ledger, please be patient, a refund will arrive: {}, Transfer Error: {:?}", transf_e, e))
+            let net_amount = Nat::from(price.saturating_sub(ledger_fee));
+            // the NFT is not restored if it has been burned by a cancellation
+            if get_nfts().contains_key(&OwnersDoubleKey { collection_id, tkn_id }) {
+                insert_nft_record(collection_id, tkn_id, owner_nft, nft.price, nft.on_sale);
+            }
+            match pay_from_escrow(collection_id, caller, net_amount, PayoutKind::Refund, Some(purchase_id)).await {
+                Ok(refund_block) => {
+                    purchase.refund_block = Some(refund_block.clone());
+                    update_purchase_record(purchase_id, purchase);
+                    Err(format!("Error in transfering NFT {:?}, the payment has been refunded on block {}", e, refund_block))
+                },
+                Err(payout_id) => {
+                    Err(format!("Error in transfering NFT {:?}, a refund will be sent automatically with payout id: {}", e, payout_id))
+                },
             }
-            ic_cdk::println!("{:?} aaaaaaaaaaa", e);
-            Err(format!("Error in transfering NFT {:?}, a refund will be sent automatically", e))
         },                
     }
-}
\ No newline at end of file
+}
+
+///
+/// Checks the price of a listing, it needs to be greater than 0 and to fit in an amount of the ledger
+///
+/// ## Arguments
+/// * `price` - price in the smallest unit of the payment token
+/// 
+/// ## Returns
+/// * `Ok`: the price is valid
+/// * `Error`: String with some details about what is wrong
+/// 
+fn validate_price(price: &Nat) -> Result<(), String> {
+    if *price == 0u32 {
+        return Err("price must be greater than 0".to_string());
+    }
+    if u128::try_from(&price.0).is_err() {
+        return Err("price too big".to_string());
+    }
+    Ok(())
+}
+
+///
+/// Checks that the caller is the owner of an NFT registered on the marketplace and that he can sell it, 
+/// the ownership is verified on the collection canister with `icrc7_owner_of`.
+/// The soulbound NFTs can be sold only by the organizer and the tickets checked in can not be resold.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// 
+/// ## Returns
+/// * `Ok`: marketplace data of the NFT
+/// * `Error`: String with some details about what went wrong
+/// 
+async fn check_nft_ownership(collection_id: Principal, tkn_id: u64) -> Result<NftMarketData, String> {
+
+    let nft = match get_nfts().get(&OwnersDoubleKey { collection_id, tkn_id }) {
+        Some(x) => x.clone(),
+        None => return Err("nft does not exists".to_string()),
+    };
+
+    let caller = ic_cdk::caller();
+    if owner_of(collection_id, tkn_id).await? != caller {
+        return Err("the caller is not the owner of the NFT".to_string());
+    }
+    if get_collections().get(&collection_id).is_some_and(|x| x.owner != caller && is_soulbound(collection_id, x, tkn_id)) {
+        return Err("the NFT is soulbound, it can not be resold".to_string());
+    }
+    if redeemed_at(collection_id, tkn_id).await?.is_some() {
+        return Err("the ticket has been checked in, it can not be resold".to_string());
+    }
+    Ok(nft)
+}
+
+///
+/// Checks that the NFTs of a collection can be put on sale
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: info of the collection
+/// * `Error`: collection not existing, cancelled, expired or with a funding goal not reached yet
+/// 
+fn check_collection_on_sale(collection_id: Principal) -> Result<CollectionInfo, String> {
+
+    match get_collections().get(&collection_id) {
+        Some(x) if x.status == CollectionStatus::Cancelled => Err("collection cancelled".to_string()),
+        Some(x) if x.expire_date <= ic_cdk::api::time() => Err("collection Expired".to_string()),
+        Some(x) if x.settlement == SettlementMode::AllOrNothing && !goal_reached(x) => 
+            Err("NFTs of an all-or-nothing collection cannot be resold before the funding goal is reached".to_string()),
+        Some(x) => Ok(x.clone()),
+        None => Err("collection does not exists".to_string()),
+    }
+}
+
+///
+/// Puts on sale an NFT owned by the caller at the price passed
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `price` - price of the NFT
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn list_nft(collection_id: String, tkn_id: u64, price: Nat) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    validate_price(&price)?;
+
+    check_collection_on_sale(collection_id)?;
+
+    let nft = check_nft_ownership(collection_id, tkn_id).await?;
+    if nft.on_sale {
+        return Err("NFT already on sale".to_string());
+    }
+    if on_auction(&OwnersDoubleKey { collection_id, tkn_id }) {
+        return Err("NFT on auction".to_string());
+    }
+
+    let caller = ic_cdk::caller();
+    insert_nft_record(collection_id, tkn_id, caller, Some(price.clone()), true);
+    Ok(format!("NFT with token id: {} listed at price {}", tkn_id, price))
+}
+
+///
+/// Changes the price of an NFT on sale owned by the caller
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `price` - new price of the NFT
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn update_listing_price(collection_id: String, tkn_id: u64, price: Nat) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    validate_price(&price)?;
+
+    let nft = check_nft_ownership(collection_id, tkn_id).await?;
+    if !nft.on_sale {
+        return Err("NFT not on sale".to_string());
+    }
+
+    let caller = ic_cdk::caller();
+    insert_nft_record(collection_id, tkn_id, caller, Some(price.clone()), true);
+    Ok(format!("NFT with token id: {} repriced at {}", tkn_id, price))
+}
+
+///
+/// Removes from sale an NFT owned by the caller
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn delist_nft(collection_id: String, tkn_id: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    let nft = check_nft_ownership(collection_id, tkn_id).await?;
+    if !nft.on_sale {
+        return Err("NFT not on sale".to_string());
+    }
+
+    let caller = ic_cdk::caller();
+    insert_nft_record(collection_id, tkn_id, caller, None, false);
+    Ok(format!("NFT with token id: {} removed from sale", tkn_id))
+}
+
+///
+/// Withdraws the funds raised by an all-or-nothing collection, 
+/// possible only for the owners and the finance team after the expire date if the funding goal has been reached.
+/// The funds are always paid to the organizer.
+/// When the goal is not reached the buyers are refunded automatically and their NFTs burned.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: description of the payments made or scheduled
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn withdraw_funds(collection_id: String) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    withdraw_raised(collection_id, ic_cdk::caller()).await
+}
+
+///
+/// Cancels the event of a collection, only the owners can call it.
+/// The sales are stopped, every buyer is refunded the price paid and all the NFTs of the collection are burned.
+/// The payments already received by the organizer are taken back from the caller with ICRC-2, 
+/// so before calling this function the caller needs to approve their sum + the transaction fee to this backend canister.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: description of the refunds
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn cancel_collection(collection_id: String) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    cancel(collection_id, ic_cdk::caller()).await
+}
+
+///
+/// Extends the expire date of a collection, only the owners and the managers can call it before the collection expires.
+/// The expire date can only be postponed, every change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `expire_date` - new expire date of the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn extend_expire_date(collection_id: String, expire_date: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    extend_expiry(collection_id, ic_cdk::caller(), expire_date)
+}
+
+///
+/// Adds a discount window to a collection, only the owners and the managers can call it.
+/// The window cannot be in the past or after the expire date and it cannot be added to a collection with a descending price.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `window` - discount window to add
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn add_discount_window(collection_id: String, window: DiscountWindowArg) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    add_window(collection_id, ic_cdk::caller(), window)
+}
+
+///
+/// Removes a discount window not expired yet from a collection, only the owners and the managers can call it
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `expire_date` - expire date of the discount window to remove
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn remove_discount_window(collection_id: String, expire_date: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    remove_window(collection_id, ic_cdk::caller(), expire_date)
+}
+
+///
+/// Updates the description and the logo of a collection on its canister, only the owners and the managers can call it.
+/// The fields passed as none are not changed.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `description` - new description of the collection
+/// * `logo` - new logo of the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn update_collection_metadata(collection_id: String, description: Option<String>, logo: Option<String>) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    update_metadata(collection_id, ic_cdk::caller(), description, logo).await
+}
+
+///
+/// Adds principals to the allowlist of a collection, only the owners and the managers can call it.
+/// Until the end of the presale only the principals in the allowlist can buy the NFTs of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `principals` - principals to add
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn add_to_allowlist(collection_id: String, principals: Vec<Principal>) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    add_allowlist(collection_id, ic_cdk::caller(), principals)
+}
+
+///
+/// Removes principals from the allowlist of a collection, only the owners and the managers can call it
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `principals` - principals to remove
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn remove_from_allowlist(collection_id: String, principals: Vec<Principal>) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    remove_allowlist(collection_id, ic_cdk::caller(), principals)
+}
+
+///
+/// Gives a role on a collection to a team member, replacing his previous role.
+/// The owners manage the roles of the team, only the organizer can give or revoke the owner role.
+/// Every change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `principal` - team member
+/// * `role` - role given: Owner, Manager, DoorStaff or Finance
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn grant_collection_role(collection_id: String, principal: Principal, role: Role) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    grant(collection_id, ic_cdk::caller(), principal, role)
+}
+
+///
+/// Revokes the role of a team member of a collection, the change is saved in the audit trail of the collection
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `principal` - team member
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn revoke_collection_role(collection_id: String, principal: Principal) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    revoke(collection_id, ic_cdk::caller(), principal)
+}
+
+///
+/// Checks in a ticket at the event door, the token is marked as redeemed on the collection canister and can not be checked in again.
+/// Callable only by the owners and the door staff of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the ticket
+/// * `holder` - principal presenting the ticket, must own it
+/// 
+/// ## Returns
+/// * `Ok`: id of the redeem transaction on the collection
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn check_in_ticket(collection_id: String, tkn_id: u64, holder: Principal) -> Result<u128, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    check_in(collection_id, ic_cdk::caller(), tkn_id, holder).await
+}
+
+///
+/// Takes a certified snapshot of the owners of the tickets still to be checked in.
+/// The scanners download it with `icrc7_owners_snapshot` on the collection canister to check in tickets offline.
+/// Callable only by the owners and the door staff of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: timestamp of the snapshot
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn snapshot_ticket_owners(collection_id: String) -> Result<u64, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    snapshot(collection_id, ic_cdk::caller()).await
+}
+
+///
+/// Redeems the tickets checked in offline, each challenge signed by the holder is verified before redeeming the ticket.
+/// Callable only by the owners and the door staff of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `check_ins` - challenges signed by the holders and scanned at the door
+/// 
+/// ## Returns
+/// * `Ok`: result of each check-in, with the id of the redeem transaction on the collection
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn sync_check_ins(collection_id: String, check_ins: Vec<SignedCheckIn>) -> Result<Vec<Result<u128, String>>, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    sync(collection_id, ic_cdk::caller(), check_ins).await
+}
+
+///
+/// Registers the perks given by a privilege code to the tickets of a collection, replacing the previous ones.
+/// Callable only by the owners and the managers of the collection, the change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `code` - privilege code of the tiers
+/// * `privilege` - name and perks of the privilege, each perk with its usage limit and expiry
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn set_collection_privilege(collection_id: String, code: u8, privilege: Privilege) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    set_privilege(collection_id, ic_cdk::caller(), code, privilege)
+}
+
+///
+/// Removes the perks given by a privilege code to the tickets of a collection.
+/// Callable only by the owners and the managers of the collection, the change is saved in the audit trail of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `code` - privilege code of the tiers
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn remove_collection_privilege(collection_id: String, code: u8) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    remove_privilege(collection_id, ic_cdk::caller(), code)
+}
+
+///
+/// Claims a perk of a ticket, each perk is claimed separately within its usage limit and before its expiry.
+/// Callable by the holder of the ticket or by the door staff of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the ticket
+/// * `perk` - name of the perk
+/// 
+/// ## Returns
+/// * `Ok`: Successful message, the url of the content for an exclusive content
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn claim_perk(collection_id: String, tkn_id: u64, perk: String) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    claim(collection_id, ic_cdk::caller(), tkn_id, perk).await
+}
+
+///
+/// Rewards the attendees of an ended event with a proof-of-attendance collection, minting a soulbound token to the holder of every ticket checked in.
+/// The first call creates the collection, the following ones resume an interrupted minting and reward the tickets checked in later.
+/// Callable only by the owners of the collection.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister of the event
+/// * `arg` - name, symbol, description and logo of the proof-of-attendance collection, ignored after the first call
+/// 
+/// ## Returns
+/// * `Ok`: id of the proof-of-attendance collection canister
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn mint_attendance_collection(collection_id: String, arg: AttendanceArg) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    reward(collection_id, ic_cdk::caller(), arg).await.map(|x| x.to_text())
+}
+
+///
+/// Requests the release of the funds of a milestone, only the owners and the finance team can call it before the deadline of the milestone
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `milestone_id` - index of the milestone in the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub fn request_milestone_release(collection_id: String, milestone_id: u32) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    request_release(collection_id, milestone_id, ic_cdk::caller())
+}
+
+///
+/// Approves the release of a requested milestone, callable by an admin or by a backer holding NFTs of the collection.
+/// The funds are paid to the organizer when an admin approves or when the backers approving hold more than half of the NFTs sold.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `milestone_id` - index of the milestone in the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message or description of the payments made or scheduled
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn approve_milestone_release(collection_id: String, milestone_id: u32) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    approve_release(collection_id, milestone_id, ic_cdk::caller()).await
+}
+
+///
+/// Releases to the organizer the funds left in escrow after every milestone has been released and the collection is expired,
+/// only the owners and the finance team can call it
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// 
+/// ## Returns
+/// * `Ok`: description of the payments made or scheduled
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn release_remaining_funds(collection_id: String) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    release_remaining(collection_id, ic_cdk::caller()).await
+}
+
+///
+/// Puts on auction an NFT owned by the caller, at the end time the NFT is transferred to the highest bidder
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `start_price` - minimum amount of the first bid
+/// * `min_increment` - minimum difference between a bid and the previous one
+/// * `end_time` - time in nanoseconds when the auction ends, it cannot be after the expire date of the collection
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn start_auction(collection_id: String, tkn_id: u64, start_price: Nat, min_increment: Nat, end_time: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    let collection = check_collection_on_sale(collection_id)?;
+    let nft = check_nft_ownership(collection_id, tkn_id).await?;
+    if nft.on_sale {
+        return Err("NFT on sale, it needs to be delisted first".to_string());
+    }
+    let ledger_fee = fee(collection.token.ledger).await?;
+
+    start_auction_nft(OwnersDoubleKey { collection_id, tkn_id }, ic_cdk::caller(), start_price, min_increment, end_time, collection.expire_date, ledger_fee)
+}
+
+///
+/// Places a bid on an NFT on auction.
+/// Before calling this function it is needed to approve, using ICRC-2, the amount of the bid + the transaction fee to this backend canister.
+/// The amount is held in escrow and refunded when a higher bid is placed.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `amount` - amount of the bid
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn place_bid(collection_id: String, tkn_id: u64, amount: Nat) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    bid(OwnersDoubleKey { collection_id, tkn_id }, ic_cdk::caller(), amount).await
+}
+
+///
+/// Makes an offer on an NFT, also if it is not on sale.
+/// Before calling this function it is needed to approve, using ICRC-2, the amount offered + the transaction fee to this backend canister.
+/// The amount is held in escrow until the offer is accepted, cancelled or expired.
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `amount` - amount offered
+/// * `expires_at` - time in nanoseconds after which the offer is refunded
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn make_offer(collection_id: String, tkn_id: u64, amount: Nat, expires_at: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    check_collection_on_sale(collection_id)?;
+    let caller = ic_cdk::caller();
+    match get_nfts().get(&OwnersDoubleKey { collection_id, tkn_id }) {
+        Some(x) if x.owner == caller => return Err("the caller already owns the NFT".to_string()),
+        Some(x) if get_collections().get(&collection_id).is_some_and(|c| c.owner != x.owner && is_soulbound(collection_id, c, tkn_id)) => {
+            return Err("the NFT is soulbound, it can not be resold".to_string())
+        },
+        Some(_) => (),
+        None => return Err("nft does not exists".to_string()),
+    }
+
+    make_nft_offer(OwnersDoubleKey { collection_id, tkn_id }, caller, amount, expires_at).await
+}
+
+///
+/// Cancels the offer of the caller on an NFT and refunds it
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// 
+/// ## Returns
+/// * `Ok`: description of the refund
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn cancel_offer(collection_id: String, tkn_id: u64) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    cancel_nft_offer(OwnersDoubleKey { collection_id, tkn_id }, ic_cdk::caller()).await
+}
+
+///
+/// Accepts an offer on an NFT owned by the caller, the NFT is transferred to the bidder 
+/// and the amount offered is settled like a purchase
+///
+/// ## Arguments
+/// * `collection_id` - id of the collection canister
+/// * `tkn_id` - id of the token
+/// * `bidder` - principal that made the offer
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_auth")]
+pub async fn accept_offer(collection_id: String, tkn_id: u64, bidder: Principal) -> Result<String, String> {
+    let collection_id = Principal::from_text(collection_id).expect("unable to parse string to principal");
+
+    check_collection_on_sale(collection_id)?;
+    let key = OwnersDoubleKey { collection_id, tkn_id };
+    let nft = check_nft_ownership(collection_id, tkn_id).await?;
+    if on_auction(&key) {
+        return Err("NFT on auction".to_string());
+    }
+
+    accept_nft_offer(key, nft, bidder).await
+}
+
+///
+/// Sets the fee taken by the platform on every sale, only a controller of the backend can call it
+///
+/// ## Arguments
+/// * `fee_account` - account receiving the fee, if none no fee is taken
+/// * `fee_bps` - fee in basis points of the price
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_controller")]
+pub fn set_platform_fee_account(fee_account: Option<Principal>, fee_bps: u16) -> Result<String, String> {
+
+    if fee_bps as u128 > BPS_DENOMINATOR {
+        return Err("fee cannot be higher than 100%".to_string());
+    }
+    set_platform_fee(PlatformFee { fee_account, fee_bps });
+    Ok("platform fee updated".to_string())
+}
+
+///
+/// Puts back in the queue a payout failed after the maximum number of attempts, only a controller of the backend can call it
+///
+/// ## Arguments
+/// * `payout_id` - id of the payout
+/// 
+/// ## Returns
+/// * `Ok`: Successful message
+/// * `Error`: String with some details about what went wrong
+/// 
+#[ic_cdk::update(guard = "caller_is_controller")]
+pub fn retry_payout(payout_id: u64) -> Result<String, String> {
+
+    retry_failed(payout_id)
+}
@@ -1,7 +1,7 @@
 import { Principal } from "@dfinity/principal";
 import { icp_ledger_canister, createActor as createLedgerActor } from "../../../declarations/icp_ledger_canister"
 import { marketplace_backend, createActor as createBackendActor } from "../../../declarations/marketplace_backend";
-import { CanisterArg, DiscountWindowArg, Errors, NftMetadata } from "../../../declarations/marketplace_backend/marketplace_backend.did";
+import { CanisterArg, CollectionFilter, CollectionSort, DiscountWindowArg, DutchPricing, Errors, MilestoneArg, NftMetadata, SettlementMode } from "../../../declarations/marketplace_backend/marketplace_backend.did";
 import { HttpAgent, Identity, Agent } from "@dfinity/agent";
 import { isSafari } from "react-device-detect";
 import { AuthClient } from "@dfinity/auth-client";
@@ -67,13 +67,14 @@ export class Connection {
    * 
    * 1) Firstly it checks if the collectionId and nftId are not empty strings.
    * 2) Checks the balance of the caller, returning the right price of the NFT (fee included).
-   * 3) Calls ICRC-2 approve method to approve the backend canister to use exactly the nft price amount of tokens (ICP) as their behalf (on this method the caller pays a fee).
+   * 3) Calls ICRC-2 approve method, on the ledger of the payment token of the collection, to approve the backend canister to use exactly the nft price amount of tokens + the fee as their behalf (on this method the caller pays a fee).
    * 4) If everything went well transfer_nft is called, where it happends exactly:
-   *    1) The caller sends exactly the nftPrice to the owner of the NFT (a fee is paid in this operation).
+   *    1) The backend moves the nftPrice from the caller to the escrow of the collection (a fee is paid in this operation).
    *    2) The NFT is transfered from the owner of it to the caller of the function.
+   *    3) The escrowed nftPrice is settled to the owner of the NFT, or refunded to the caller if the NFT transfer fails.
    * 
    * @param actorBackend backend actor used to call api
-   * @param actorLedger mainnet ledger actor used to call the ICRC-1 and ICRC-2 methods
+   * @param actorLedger ledger actor of the payment token of the collection used to call the ICRC-1 and ICRC-2 methods
    * @returns 
    */
   async purchaseNft() {
@@ -94,21 +95,35 @@ export class Connection {
         return
     }
   
+    let token = await this.actorBackend.get_payment_token(Principal.fromText(collectionId))
+
+    if ("Err" in token) {
+        console.log(token.Err)
+        return
+    }
+
+    // Every collection is paid with his own token, so the approval is made on his ledger
+    const actorLedger = createLedgerActor(token.Ok.ledger.toText(), {
+      agent: this.agent
+    })
+
     // Approves the backend canister to transfer token (ex. ICP) on caller behalf
-    let transfer = await this.actorLedger.icrc1_transfer({
+    let approve = await actorLedger.icrc2_approve({
       from_subaccount: [],
-      to: {
+      spender: {
         owner: Principal.fromText(process.env.CANISTER_ID_MARKETPLACE_BACKEND as string),
         subaccount: []
       },
-      amount: nftPrice.Ok + await icp_ledger_canister.icrc1_fee(),
+      amount: nftPrice.Ok + await actorLedger.icrc1_fee(),
+      expected_allowance: [],
+      expires_at: [],
       fee: [],
       memo: [],
       created_at_time: [],
     })
   
-    if ("Err" in transfer) {
-        console.log(transfer.Err)
+    if ("Err" in approve) {
+        console.log(approve.Err)
         return
     }
   
@@ -121,6 +136,62 @@ export class Connection {
     console.log(transferRes)
   }
 
+  /**
+   * Method that buys an NFT of a tier of a collection, the NFT is minted to the caller after the payment.
+   * 
+   * 1) Gets the price of the tier, discounted if the collection has discount windows or a descending price.
+   * 2) Calls ICRC-2 approve method, on the ledger of the payment token of the collection, to approve the backend canister to use the price + the fee.
+   * 3) Calls buy_nft, where the price is moved to the escrow of the collection and the NFT is minted to the caller, 
+   *    or the payment is refunded if the NFT cannot be minted.
+   * 
+   * @param collectionId canister id of the collection
+   * @param tier index of the tier in the collection
+   * @returns result of the purchase
+   */
+  async purchaseFromTier(collectionId: string, tier: number) {
+    let tierPrice = await this.actorBackend.get_tier_price(collectionId, tier)
+
+    if ("Err" in tierPrice) {
+        console.log(tierPrice.Err)
+        return
+    }
+
+    let token = await this.actorBackend.get_payment_token(Principal.fromText(collectionId))
+
+    if ("Err" in token) {
+        console.log(token.Err)
+        return
+    }
+
+    const actorLedger = createLedgerActor(token.Ok.ledger.toText(), {
+      agent: this.agent
+    })
+
+    let approve = await actorLedger.icrc2_approve({
+      from_subaccount: [],
+      spender: {
+        owner: Principal.fromText(process.env.CANISTER_ID_MARKETPLACE_BACKEND as string),
+        subaccount: []
+      },
+      amount: tierPrice.Ok + await actorLedger.icrc1_fee(),
+      expected_allowance: [],
+      expires_at: [],
+      fee: [],
+      memo: [],
+      created_at_time: [],
+    })
+
+    if ("Err" in approve) {
+        console.log(approve.Err)
+        return
+    }
+
+    let buyRes = await this.actorBackend.buy_nft(collectionId, tier, tierPrice.Ok)
+
+    console.log(buyRes)
+    return buyRes
+  }
+
   /**
    * Method that gets from backend all the collections assigned to the caller.
    * 
@@ -145,14 +216,32 @@ export class Connection {
    * @param nfts Vector of NftMetadata the total sum of the nfts quantity needs to be equal to the supply_cap in CanisterArg
    * @param expire_date expire date in nanosecods of the collection
    * @param discount_windows array of windows of discount, giving in nanoseconds the expire date of the discount and the discount percentage (10 = 10%)
+   * @param royalty_bps royalties paid to the organizer on secondary sales in basis points (100 = 1%)
+   * @param funding_goal amount to raise with the primary sales, required by the AllOrNothing settlement
+   * @param settlement Immediate to be paid at every sale, AllOrNothing to keep the payments in escrow until the expire date and refund the buyers if the goal is not reached
+   * @param milestones tranches in which the escrowed funds are released to the organizer, each one approved by an admin or by the backers
+   * @param dutch_pricing descending price from the NFT price to a floor percentage of it, alternative to the discount windows
+   * @param ledger ICRC-1 ledger of the token used to pay the NFTs, ICP if not passed
+   * @param presale_end end of the presale, until then only the allowlisted principals can buy
+   * @param max_per_wallet maximum number of NFTs a principal can buy from the organizer
+   * @param tags category tags used by the search of the collections
    * @returns success message or an error of type Errors
    */
-  async createCanister(arg: CanisterArg, nfts: NftMetadata[], expire_date: bigint, discount_windows: DiscountWindowArg[]): Promise<string | Errors> {
+  async createCanister(arg: CanisterArg, nfts: NftMetadata[], expire_date: bigint, discount_windows: DiscountWindowArg[], royalty_bps: number = 0, funding_goal?: bigint, settlement: SettlementMode = { Immediate: null }, milestones: MilestoneArg[] = [], dutch_pricing?: DutchPricing, ledger?: Principal, presale_end?: bigint, max_per_wallet?: number, tags: string[] = []): Promise<string | Errors> {
     const res = await this.actorBackend.create_collection_nfts({
       canister_arg: arg,
       nfts,
       expire_date,
-      discount_windows
+      discount_windows,
+      royalty_bps,
+      funding_goal: funding_goal === undefined ? [] : [funding_goal],
+      settlement,
+      milestones,
+      dutch_pricing: dutch_pricing === undefined ? [] : [dutch_pricing],
+      ledger: ledger === undefined ? [] : [ledger],
+      presale_end: presale_end === undefined ? [] : [presale_end],
+      max_per_wallet: max_per_wallet === undefined ? [] : [max_per_wallet],
+      tags
     });
 
     if ("Ok" in res) {
@@ -196,5 +285,134 @@ export class Connection {
     return res.Err
   }
 
+  /**
+   * Searches the collections
+   * 
+   * @param filter filters on availability, organizer, price range, category tags and text in the name or description
+   * @param sort order of the collections: ExpireDate, Newest, PriceLowToHigh or PriceHighToLow
+   * @param offset Offset of the first element to retrieve
+   * @param limit Number of elements to retrieve
+   * @returns Array of collections found
+   */
+  async searchCollections(filter: CollectionFilter, sort: CollectionSort, offset: number, limit: number) {
+    const res = await this.actorBackend.search_collections(filter, sort, offset, limit)
+
+    return res.map(x => {
+      return {...x, canister_id: x.canister_id.toText(), owner: x.owner.toText()}
+    })
+  }
+
+  /**
+   * Gets the tiers of a collection with their availability
+   * 
+   * @param collectionId canister id of the collection
+   * @returns Array of tiers giving name, price, sale window, supply, NFTs remaining and a boolean saying if the tier is sold out
+   */
+  async getCollectionTiers(collectionId: string) {
+    const res = await this.actorBackend.get_collection_tiers(collectionId)
+
+    if ("Ok" in res) {
+      return res.Ok
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets the changes made by the organizer on a collection after its creation
+   * 
+   * @param collectionId canister id of the collection
+   * @returns Array of changes, from the oldest one, giving the change made, who made it and when
+   */
+  async getCollectionChanges(collectionId: string) {
+    const res = await this.actorBackend.get_collection_changes(collectionId)
+
+    if ("Ok" in res) {
+      return res.Ok.map(x => {
+        return {...x, collection_id: x.collection_id.toText(), caller: x.caller.toText()}
+      })
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets when a ticket has been checked in at the event door
+   * 
+   * @param collectionId canister id of the collection
+   * @param tknId id of the ticket
+   * @returns Timestamp of the check-in in nanoseconds, undefined if the ticket has not been checked in yet
+   */
+  async getTicketRedemption(collectionId: string, tknId: bigint) {
+    const res = await this.actorBackend.get_ticket_redemption(collectionId, tknId)
+
+    if ("Ok" in res) {
+      return res.Ok[0]
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets a challenge to sign with the identity of the holder of a ticket, to check it in offline at the door
+   * 
+   * @param collectionId canister id of the collection
+   * @param tknId id of the ticket owned by the caller
+   * @returns Challenge bound to the collection, the ticket and the holder, valid for 30 minutes
+   */
+  async getCheckInChallenge(collectionId: string, tknId: bigint) {
+    const res = await this.actorBackend.get_check_in_challenge(collectionId, tknId)
+
+    if ("Ok" in res) {
+      return res.Ok
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets the perks given by each privilege code of a collection
+   * 
+   * @param collectionId canister id of the collection
+   * @returns Array of privilege codes with the name of the privilege and its perks
+   */
+  async getCollectionPrivileges(collectionId: string) {
+    const res = await this.actorBackend.get_collection_privileges(collectionId)
+
+    if ("Ok" in res) {
+      return res.Ok.map(([code, privilege]) => {
+        return {...privilege, code}
+      })
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets the perks a ticket entitles its holder to
+   * 
+   * @param collectionId canister id of the collection
+   * @param tknId id of the ticket
+   * @returns Array of perks giving the perk, how many times it has been claimed and if it can still be claimed
+   */
+  async getTicketPerks(collectionId: string, tknId: bigint) {
+    const res = await this.actorBackend.get_ticket_perks(collectionId, tknId)
+
+    if ("Ok" in res) {
+      return res.Ok
+    }
+    return res.Err
+  }
+
+  /**
+   * Gets the tickets of an event rewarded with a proof of attendance
+   * 
+   * @param collectionId id of the collection of the event
+   * @returns Array of ticket ids with the principal their proof of attendance was minted to
+   */
+  async getAttendanceRewards(collectionId: string) {
+    const res = await this.actorBackend.get_attendance_rewards(collectionId)
+
+    if ("Ok" in res) {
+      return res.Ok
+    }
+    return res.Err
+  }
+
 }
 
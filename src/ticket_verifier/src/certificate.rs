@@ -0,0 +1,135 @@
+use candid::Principal;
+use ic_canister_sig_creation::IC_ROOT_PK_DER;
+use ic_cbor::CertificateToCbor;
+use ic_certificate_verification::VerifyCertificate;
+use ic_certification::Certificate;
+
+/// Public key of the root subnet of the IC mainnet, DER encoded
+pub const IC_ROOT_KEY: [u8; 133] = *IC_ROOT_PK_DER;
+
+///
+/// Verifies a certificate issued by the IC for a canister, following its delegation if any.
+/// The time of the certificate cannot be further than `max_age` from `now`.
+///
+/// ## Arguments
+/// * `certificate` - CBOR encoded certificate
+/// * `canister_id` - canister that issued the certificate
+/// * `root_key` - DER encoded public key of the root subnet, `IC_ROOT_KEY` on mainnet
+/// * `now` - current time in nanoseconds
+/// * `max_age` - maximum age of the certificate in nanoseconds
+///
+/// ## Returns
+/// * `Ok`: decoded certificate
+/// * `Error`: String with some details about what went wrong
+///
+pub fn verify_certificate(certificate: &[u8], canister_id: Principal, root_key: &[u8], now: u64, max_age: u64) -> Result<Certificate, String> {
+    let certificate = Certificate::from_cbor(certificate)
+        .map_err(|e| format!("invalid certificate: {}", e))?;
+    certificate.verify(canister_id.as_slice(), root_key, &(now as u128), &(max_age as u128))
+        .map_err(|e| format!("invalid certificate: {}", e))?;
+    Ok(certificate)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ic_certification::hash_tree::{fork, label, leaf};
+    use ic_certification::{Delegation, HashTree};
+    use ic_verify_bls_signature::PrivateKey;
+
+    /// Secret key of the root subnet used by the tests
+    const ROOT_SECRET: u8 = 42;
+    /// Secret key of the subnet delegated by the root subnet in the tests
+    const SUBNET_SECRET: u8 = 7;
+
+    /// Domain separator prepended to the root hash of the state tree signed by the subnets
+    const STATE_ROOT_DOMAIN: &[u8] = b"\x0dic-state-root";
+
+    fn secret_key(secret: u8) -> PrivateKey {
+        let mut bytes = [0; 32];
+        bytes[31] = secret;
+        PrivateKey::deserialize(&bytes).unwrap()
+    }
+
+    pub(crate) fn public_key(secret: u8) -> Vec<u8> {
+        [&IC_ROOT_KEY[..37], secret_key(secret).public_key().serialize().as_slice()].concat()
+    }
+
+    pub(crate) fn root_key() -> Vec<u8> {
+        public_key(ROOT_SECRET)
+    }
+
+    pub(crate) fn leb128(mut value: u64) -> Vec<u8> {
+        let mut res = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                res.push(byte);
+                return res;
+            }
+            res.push(byte | 0x80);
+        }
+    }
+
+    fn sign(secret: u8, tree: HashTree, delegation: Option<Delegation>) -> Vec<u8> {
+        let mut message = STATE_ROOT_DOMAIN.to_vec();
+        message.extend_from_slice(&tree.digest());
+        let signature = secret_key(secret).sign(&message).serialize().to_vec();
+        // the certificates of the IC are tagged as self described CBOR
+        [[0xd9, 0xd9, 0xf7].as_slice(), &serde_cbor::to_vec(&Certificate { tree, signature, delegation }).unwrap()].concat()
+    }
+
+    /// Certificate of the certified data of a canister, signed by the root subnet
+    pub(crate) fn certify(canister_id: Principal, certified_data: &[u8], time: u64) -> Vec<u8> {
+        sign(ROOT_SECRET, state_tree(canister_id, certified_data, time), None)
+    }
+
+    fn state_tree(canister_id: Principal, certified_data: &[u8], time: u64) -> HashTree {
+        fork(
+            label("canister", label(canister_id.as_slice(), label("certified_data", leaf(certified_data)))),
+            label("time", leaf(leb128(time))),
+        )
+    }
+
+    fn delegated(canister_id: Principal, ranges: Vec<(Principal, Principal)>) -> Vec<u8> {
+        let subnet_id = Principal::from_slice(&[1, 2, 3]);
+        let subnet = label(subnet_id.as_slice(), fork(
+            label("canister_ranges", leaf(serde_cbor::to_vec(&ranges).unwrap())),
+            label("public_key", leaf(public_key(SUBNET_SECRET))),
+        ));
+        let delegation = Delegation {
+            subnet_id: subnet_id.as_slice().to_vec(),
+            certificate: sign(ROOT_SECRET, label("subnet", subnet), None),
+        };
+        sign(SUBNET_SECRET, state_tree(canister_id, b"data", 1), Some(delegation))
+    }
+
+    #[test]
+    fn certificates_signed_by_the_root_are_valid() {
+        let canister_id = Principal::from_slice(&[9; 10]);
+        assert!(verify_certificate(&certify(canister_id, b"data", 300), canister_id, &root_key(), 300, 0).is_ok());
+        assert!(verify_certificate(&certify(canister_id, b"data", 300), canister_id, &public_key(ROOT_SECRET + 1), 300, 0).is_err());
+        assert!(verify_certificate(&certify(canister_id, b"data", 300), canister_id, &IC_ROOT_KEY, 300, 0).is_err());
+    }
+
+    #[test]
+    fn certificates_need_to_be_fresh() {
+        let canister_id = Principal::from_slice(&[9; 10]);
+        let certificate = certify(canister_id, b"data", 300);
+        assert!(verify_certificate(&certificate, canister_id, &root_key(), 400, 100).is_ok());
+        assert!(verify_certificate(&certificate, canister_id, &root_key(), 200, 100).is_ok());
+        assert!(verify_certificate(&certificate, canister_id, &root_key(), 401, 100).is_err());
+        assert!(verify_certificate(&certificate, canister_id, &root_key(), 199, 100).is_err());
+    }
+
+    #[test]
+    fn delegations_need_to_cover_the_canister() {
+        let canister_id = Principal::from_slice(&[9; 10]);
+        let inside = vec![(Principal::from_slice(&[8; 10]), Principal::from_slice(&[10; 10]))];
+        assert!(verify_certificate(&delegated(canister_id, inside), canister_id, &root_key(), 1, 0).is_ok());
+
+        let outside = vec![(Principal::from_slice(&[10; 10]), Principal::from_slice(&[11; 10]))];
+        assert!(verify_certificate(&delegated(canister_id, outside), canister_id, &root_key(), 1, 0).is_err());
+    }
+}
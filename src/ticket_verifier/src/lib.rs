@@ -0,0 +1,10 @@
+// The functions of this crate do not call the IC, so the scanners at the event door
+// can verify the tickets while offline and sync the check-ins later with `sync_check_ins`
+
+pub mod certificate;
+pub mod structures;
+pub mod verification;
+
+pub use certificate::IC_ROOT_KEY;
+pub use structures::*;
+pub use verification::*;
@@ -0,0 +1,61 @@
+use candid::{CandidType, Principal};
+use icrc_ledger_types::icrc1::account::Account;
+use serde::{Deserialize, Serialize};
+
+///
+/// Challenge issued to the holder of a ticket to check it in offline, the holder signs it with his identity.
+/// The mac authenticates the challenge as issued by the backend, it is checked when the check-in is synced.
+/// 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CheckInChallenge {
+    pub collection_id: Principal,
+    pub tkn_id: u64,
+    pub holder: Principal,
+    pub nonce: u64,
+    pub expires_at: u64,
+    pub mac: Vec<u8>,
+}
+
+///
+/// Delegation from a key of the holder to another key, as issued by Internet Identity to the session keys of the dapps
+/// 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SignedDelegation {
+    pub pubkey: Vec<u8>,
+    pub expiration: u64,
+    pub targets: Option<Vec<Principal>>,
+    pub signature: Vec<u8>,
+}
+
+///
+/// Challenge signed by the holder of a ticket and scanned at the event door.
+/// The public key is the DER encoded key of the holder identity: ed25519, ECDSA secp256k1 or P-256, or a canister signature key
+/// as the ones of Internet Identity. The delegations lead from that key to the one that signed the challenge.
+/// 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct SignedCheckIn {
+    pub challenge: CheckInChallenge,
+    pub public_key: Vec<u8>,
+    pub delegations: Vec<SignedDelegation>,
+    pub signature: Vec<u8>,
+    pub scanned_at: u64,
+}
+
+///
+/// Owners of the tickets still to be checked in, sorted by token id, as returned by `icrc7_owners_snapshot`
+/// 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct OwnersSnapshot {
+    pub taken_at: u64,
+    pub owners: Vec<(u128, Account)>,
+}
+
+///
+/// Snapshot of the owners with the certificate of the collection canister and the witness of the snapshot hash
+/// 
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
+pub struct CertifiedOwnersSnapshot {
+    pub snapshot: OwnersSnapshot,
+    pub certificate: Option<Vec<u8>>,
+    pub hash_tree: Vec<u8>,
+}
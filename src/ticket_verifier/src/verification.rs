@@ -0,0 +1,490 @@
+use candid::Principal;
+use ed25519_dalek::{Signature as Ed25519Signature, VerifyingKey as Ed25519Key};
+use ic_canister_sig_creation::{delegation_signature_msg, extract_raw_canister_sig_pk_from_der, extract_raw_root_pk_from_der, DELEGATION_SIG_DOMAIN};
+use ic_certification::{HashTree, LookupResult};
+use ic_signature_verification::verify_canister_sig;
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{Signature as EcdsaSignature, VerifyingKey as EcdsaKey};
+use p256::ecdsa::{Signature as P256Signature, VerifyingKey as P256Key};
+use sha2::{Digest, Sha256};
+
+use crate::certificate::verify_certificate;
+use crate::structures::{CertifiedOwnersSnapshot, CheckInChallenge, OwnersSnapshot, SignedCheckIn, SignedDelegation};
+
+/// Validity of a check-in challenge, 30 minutes in nanoseconds
+pub const CHALLENGE_TTL: u64 = 30 * 60 * 1_000_000_000;
+
+/// Maximum age of a snapshot of the owners accepted by the scanners, 12 hours in nanoseconds
+pub const MAX_SNAPSHOT_AGE: u64 = 12 * 60 * 60 * 1_000_000_000;
+
+/// Maximum number of delegations between the key of the holder and the key that signed the challenge, as accepted by the IC
+pub const MAX_DELEGATIONS: usize = 20;
+
+/// Domain separator prepended to the challenges signed by the holders
+const CHALLENGE_DOMAIN: &[u8] = b"\x0fticket-check-in";
+
+/// DER prefix of the ed25519 public keys
+const ED25519_DER_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// DER prefix of the uncompressed secp256k1 public keys
+const SECP256K1_DER_PREFIX: [u8; 23] = [
+    0x30, 0x56, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a, 0x03, 0x42, 0x00,
+];
+
+/// DER prefix of the uncompressed P-256 public keys
+const P256_DER_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07,
+    0x03, 0x42, 0x00,
+];
+
+///
+/// Bytes of a challenge signed by the holder of the ticket
+///
+/// ## Arguments
+/// * `challenge` - challenge issued by the backend
+///
+pub fn challenge_message(challenge: &CheckInChallenge) -> Vec<u8> {
+    let collection_id = challenge.collection_id.as_slice();
+    let holder = challenge.holder.as_slice();
+
+    let mut message = CHALLENGE_DOMAIN.to_vec();
+    message.push(collection_id.len() as u8);
+    message.extend_from_slice(collection_id);
+    message.extend_from_slice(&challenge.tkn_id.to_be_bytes());
+    message.push(holder.len() as u8);
+    message.extend_from_slice(holder);
+    message.extend_from_slice(&challenge.nonce.to_be_bytes());
+    message.extend_from_slice(&challenge.expires_at.to_be_bytes());
+    message
+}
+
+///
+/// Bytes of a delegation signed by the key delegating to its public key
+///
+/// ## Arguments
+/// * `delegation` - delegation to another key
+///
+pub fn delegation_message(delegation: &SignedDelegation) -> Vec<u8> {
+    let targets = delegation.targets.as_ref()
+        .map(|x| x.iter().map(|target| target.as_slice().to_vec()).collect::<Vec<_>>());
+
+    let mut message = vec![DELEGATION_SIG_DOMAIN.len() as u8];
+    message.extend_from_slice(DELEGATION_SIG_DOMAIN);
+    message.extend_from_slice(&delegation_signature_msg(&delegation.pubkey, delegation.expiration, targets.as_ref()));
+    message
+}
+
+///
+/// Verifies a signature made with an ed25519, ECDSA secp256k1 or P-256 key, or a canister signature
+///
+/// ## Arguments
+/// * `public_key` - DER encoded public key
+/// * `message` - message signed
+/// * `signature` - signature of the message, 64 bytes or a CBOR encoded canister signature
+/// * `root_key` - DER encoded public key of the root subnet, `IC_ROOT_KEY` on mainnet
+///
+/// ## Returns
+/// * `Ok`: valid signature
+/// * `Error`: String with some details about what went wrong
+///
+pub fn verify_signature(public_key: &[u8], message: &[u8], signature: &[u8], root_key: &[u8]) -> Result<(), String> {
+    if let Some(key) = public_key.strip_prefix(&ED25519_DER_PREFIX) {
+        let key: [u8; 32] = key.try_into().map_err(|_| "invalid ed25519 public key".to_string())?;
+        let key = Ed25519Key::from_bytes(&key).map_err(|_| "invalid ed25519 public key".to_string())?;
+        let signature = Ed25519Signature::from_slice(signature).map_err(|_| "invalid ed25519 signature".to_string())?;
+        return key.verify_strict(message, &signature).map_err(|_| "wrong signature".to_string());
+    }
+    if let Some(key) = public_key.strip_prefix(&SECP256K1_DER_PREFIX) {
+        let key = EcdsaKey::from_sec1_bytes(key).map_err(|_| "invalid secp256k1 public key".to_string())?;
+        let signature = EcdsaSignature::from_slice(signature).map_err(|_| "invalid secp256k1 signature".to_string())?;
+        let signature = signature.normalize_s().unwrap_or(signature);
+        return key.verify(message, &signature).map_err(|_| "wrong signature".to_string());
+    }
+    if let Some(key) = public_key.strip_prefix(&P256_DER_PREFIX) {
+        let key = P256Key::from_sec1_bytes(key).map_err(|_| "invalid P-256 public key".to_string())?;
+        let signature = P256Signature::from_slice(signature).map_err(|_| "invalid P-256 signature".to_string())?;
+        let signature = signature.normalize_s().unwrap_or(signature);
+        return key.verify(message, &signature).map_err(|_| "wrong signature".to_string());
+    }
+    if extract_raw_canister_sig_pk_from_der(public_key).is_ok() {
+        let root_key = extract_raw_root_pk_from_der(root_key)?;
+        return verify_canister_sig(message, signature, public_key, &root_key);
+    }
+    Err("unsupported public key, only ed25519, secp256k1, P-256 and canister signature keys are supported".to_string())
+}
+
+///
+/// Verifies a challenge signed by the holder of a ticket, directly or with a key delegated by the holder.
+/// The challenge cannot be valid for longer than `CHALLENGE_TTL` and it needs to be scanned while valid and not after `now`.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `check_in` - challenge signed by the holder and scanned at the door
+/// * `root_key` - DER encoded public key of the root subnet, `IC_ROOT_KEY` on mainnet
+/// * `now` - current time in nanoseconds
+///
+/// ## Returns
+/// * `Ok`: the challenge has been signed by the holder before its expiration
+/// * `Error`: String with some details about what went wrong
+///
+pub fn verify_check_in(collection_id: Principal, check_in: &SignedCheckIn, root_key: &[u8], now: u64) -> Result<(), String> {
+    let challenge = &check_in.challenge;
+    if challenge.collection_id != collection_id {
+        return Err("challenge issued for another collection".to_string());
+    }
+    if challenge.expires_at < challenge.nonce || challenge.expires_at - challenge.nonce > CHALLENGE_TTL {
+        return Err("challenge valid for longer than allowed".to_string());
+    }
+    if check_in.scanned_at < challenge.nonce || check_in.scanned_at > now {
+        return Err("challenge scanned before being issued or in the future".to_string());
+    }
+    if check_in.scanned_at > challenge.expires_at {
+        return Err("challenge expired".to_string());
+    }
+    if Principal::self_authenticating(&check_in.public_key) != challenge.holder {
+        return Err("public key does not belong to the holder".to_string());
+    }
+    if check_in.delegations.len() > MAX_DELEGATIONS {
+        return Err(format!("at most {} delegations are accepted", MAX_DELEGATIONS));
+    }
+
+    let mut key = &check_in.public_key;
+    for delegation in check_in.delegations.iter() {
+        if delegation.expiration < check_in.scanned_at {
+            return Err("delegation expired".to_string());
+        }
+        if delegation.targets.as_ref().is_some_and(|x| !x.contains(&collection_id)) {
+            return Err("delegation not valid for the collection".to_string());
+        }
+        verify_signature(key, &delegation_message(delegation), &delegation.signature, root_key)?;
+        key = &delegation.pubkey;
+    }
+    verify_signature(key, &challenge_message(challenge), &check_in.signature, root_key)
+}
+
+///
+/// Hash of a snapshot of the owners, computed as the collection canister does when certifying it
+///
+/// ## Arguments
+/// * `snapshot` - owners of the tickets
+///
+pub fn hash_owners_snapshot(snapshot: &OwnersSnapshot) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(snapshot.taken_at.to_be_bytes());
+    for (token_id, owner) in snapshot.owners.iter() {
+        let principal = owner.owner.as_slice();
+        hasher.update(token_id.to_be_bytes());
+        hasher.update([principal.len() as u8]);
+        hasher.update(principal);
+        hasher.update(owner.subaccount.unwrap_or([0; 32]));
+    }
+    hasher.finalize().into()
+}
+
+///
+/// Verifies that a snapshot of the owners is the one certified by the collection canister
+/// with a certificate signed by the IC, and that neither the snapshot nor the certificate are older than `max_age`
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `certified` - snapshot with its certificate and witness
+/// * `root_key` - DER encoded public key of the root subnet, `IC_ROOT_KEY` on mainnet
+/// * `now` - current time of the scanner in nanoseconds
+/// * `max_age` - maximum age of the snapshot in nanoseconds, `MAX_SNAPSHOT_AGE` by default
+///
+/// ## Returns
+/// * `Ok`: the snapshot is certified
+/// * `Error`: String with some details about what went wrong
+///
+pub fn verify_snapshot(collection_id: Principal, certified: &CertifiedOwnersSnapshot, root_key: &[u8], now: u64, max_age: u64) -> Result<(), String> {
+    let certificate = match certified.certificate {
+        Some(ref x) => verify_certificate(x, collection_id, root_key, now, max_age)?,
+        None => return Err("snapshot not certified".to_string()),
+    };
+    let witness: HashTree = serde_cbor::from_slice(&certified.hash_tree)
+        .map_err(|e| format!("invalid hash tree: {}", e))?;
+    match witness.lookup_path([b"owners_snapshot".as_slice()]) {
+        LookupResult::Found(hash) if hash == hash_owners_snapshot(&certified.snapshot).as_slice() => {},
+        _ => return Err("snapshot does not match the certified hash".to_string()),
+    }
+
+    let path = [b"canister".as_slice(), collection_id.as_slice(), b"certified_data".as_slice()];
+    match certificate.tree.lookup_path(path) {
+        LookupResult::Found(data) if data == witness.digest().as_slice() => {},
+        _ => return Err("certified data of the collection does not match the snapshot".to_string()),
+    }
+    // the time of the snapshot is certified with its hash
+    if now.saturating_sub(certified.snapshot.taken_at) > max_age {
+        return Err("snapshot too old, download a new one".to_string());
+    }
+    Ok(())
+}
+
+///
+/// Verifies a ticket at the event door without calling the IC.
+/// The scanner keeps the tickets checked in since the snapshot to reject double check-ins.
+///
+/// ## Arguments
+/// * `collection_id` - collection canister id
+/// * `check_in` - challenge signed by the holder and scanned at the door
+/// * `certified` - snapshot of the owners downloaded before the event
+/// * `root_key` - DER encoded public key of the root subnet, `IC_ROOT_KEY` on mainnet
+/// * `now` - current time of the scanner in nanoseconds
+///
+/// ## Returns
+/// * `Ok`: the holder owns the ticket and it has not been checked in before the snapshot
+/// * `Error`: String with some details about what went wrong
+///
+pub fn verify_offline(collection_id: Principal, check_in: &SignedCheckIn, certified: &CertifiedOwnersSnapshot, root_key: &[u8], now: u64) -> Result<(), String> {
+    verify_snapshot(collection_id, certified, root_key, now, MAX_SNAPSHOT_AGE)?;
+    verify_check_in(collection_id, check_in, root_key, now)?;
+
+    let tkn_id = check_in.challenge.tkn_id as u128;
+    match certified.snapshot.owners.binary_search_by_key(&tkn_id, |(id, _)| *id) {
+        Ok(index) if certified.snapshot.owners[index].1.owner == check_in.challenge.holder => Ok(()),
+        Ok(_) => Err("ticket not owned by the holder".to_string()),
+        Err(_) => Err("ticket does not exists or already checked in".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::certificate::tests::{certify, root_key};
+    use ed25519_dalek::SigningKey as Ed25519SigningKey;
+    use ed25519_dalek::Signer as _;
+    use ic_canister_sig_creation::{hash_bytes, CanisterSigPublicKey};
+    use ic_certification::hash_tree::{label, leaf};
+    use icrc_ledger_types::icrc1::account::Account;
+    use k256::ecdsa::SigningKey as EcdsaSigningKey;
+    use p256::ecdsa::SigningKey as P256SigningKey;
+    use serde::Serialize;
+
+    const NOW: u64 = 1_000_000_000_000;
+
+    fn collection_id() -> Principal {
+        Principal::from_slice(&[9; 10])
+    }
+
+    fn ed25519_key() -> (Ed25519SigningKey, Vec<u8>) {
+        let key = Ed25519SigningKey::from_bytes(&[7; 32]);
+        let der = [ED25519_DER_PREFIX.as_slice(), key.verifying_key().as_bytes()].concat();
+        (key, der)
+    }
+
+    fn check_in(nonce: u64, expires_at: u64, scanned_at: u64) -> SignedCheckIn {
+        let (key, public_key) = ed25519_key();
+        let challenge = CheckInChallenge {
+            collection_id: collection_id(),
+            tkn_id: 3,
+            holder: Principal::self_authenticating(&public_key),
+            nonce,
+            expires_at,
+            mac: Vec::new(),
+        };
+        let signature = key.sign(&challenge_message(&challenge)).to_bytes().to_vec();
+        SignedCheckIn { challenge, public_key, delegations: Vec::new(), signature, scanned_at }
+    }
+
+    /// Delegation to the session key used to sign the challenges, as the ones issued by Internet Identity
+    fn delegate(sign: impl Fn(&[u8]) -> Vec<u8>, expiration: u64, targets: Option<Vec<Principal>>) -> SignedDelegation {
+        let mut delegation = SignedDelegation { pubkey: ed25519_key().1, expiration, targets, signature: Vec::new() };
+        delegation.signature = sign(&delegation_message(&delegation));
+        delegation
+    }
+
+    /// Canister signature of a message by the seed of a user of the canister `signer`
+    fn canister_sign(signer: Principal, seed: &[u8], message: &[u8]) -> Vec<u8> {
+        #[derive(Serialize)]
+        struct CanisterSig {
+            certificate: serde_cbor::Value,
+            tree: HashTree,
+        }
+
+        let tree = label("sig", label(hash_bytes(seed).as_slice(), label(hash_bytes(message).as_slice(), leaf(b""))));
+        let signature = CanisterSig {
+            certificate: serde_cbor::Value::Bytes(certify(signer, &tree.digest(), NOW)),
+            tree,
+        };
+        [[0xd9, 0xd9, 0xf7].as_slice(), &serde_cbor::to_vec(&signature).unwrap()].concat()
+    }
+
+    /// Check-in of a holder identified by a canister signature key, signed with a session key delegated by the holder
+    fn delegated_check_in(expiration: u64, targets: Option<Vec<Principal>>) -> SignedCheckIn {
+        let signer = Principal::from_slice(&[4; 10]);
+        let public_key = CanisterSigPublicKey::new(signer, b"user".to_vec()).to_der();
+        let challenge = CheckInChallenge {
+            collection_id: collection_id(),
+            tkn_id: 3,
+            holder: Principal::self_authenticating(&public_key),
+            nonce: NOW,
+            expires_at: NOW + 10,
+            mac: Vec::new(),
+        };
+        let delegation = delegate(|x| canister_sign(signer, b"user", x), expiration, targets);
+        let signature = ed25519_key().0.sign(&challenge_message(&challenge)).to_bytes().to_vec();
+        SignedCheckIn { challenge, public_key, delegations: vec![delegation], signature, scanned_at: NOW + 1 }
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{:02x}", x)).collect()
+    }
+
+    fn snapshot() -> OwnersSnapshot {
+        OwnersSnapshot {
+            taken_at: 5,
+            owners: vec![
+                (1, Account { owner: Principal::from_slice(&[1, 2, 3]), subaccount: None }),
+                (3, Account { owner: Principal::self_authenticating(ed25519_key().1), subaccount: Some([7; 32]) }),
+            ],
+        }
+    }
+
+    fn certify_snapshot(snapshot: OwnersSnapshot, taken_at: u64) -> CertifiedOwnersSnapshot {
+        let snapshot = OwnersSnapshot { taken_at, ..snapshot };
+        let witness: HashTree = label("owners_snapshot", leaf(hash_owners_snapshot(&snapshot).to_vec()));
+        CertifiedOwnersSnapshot {
+            certificate: Some(certify(collection_id(), &witness.digest(), taken_at)),
+            hash_tree: serde_cbor::to_vec(&witness).unwrap(),
+            snapshot,
+        }
+    }
+
+    #[test]
+    fn challenge_message_binds_the_fields() {
+        let challenge = check_in(1, 2, 1).challenge;
+        let message = challenge_message(&challenge);
+        assert!(message.starts_with(CHALLENGE_DOMAIN));
+        assert_eq!(message.len(), CHALLENGE_DOMAIN.len() + 1 + 10 + 8 + 1 + 29 + 8 + 8);
+
+        let other = CheckInChallenge { tkn_id: 4, ..challenge.clone() };
+        assert_ne!(challenge_message(&other), message);
+        // the mac of the backend is not signed by the holder
+        let with_mac = CheckInChallenge { mac: vec![1; 32], ..challenge };
+        assert_eq!(challenge_message(&with_mac), message);
+    }
+
+    #[test]
+    fn ed25519_and_ecdsa_signatures_are_verified() {
+        let (key, der) = ed25519_key();
+        let signature = key.sign(b"message").to_bytes();
+        assert!(verify_signature(&der, b"message", &signature, &root_key()).is_ok());
+        assert!(verify_signature(&der, b"other", &signature, &root_key()).is_err());
+
+        let key = EcdsaSigningKey::from_bytes(&[7; 32].into()).unwrap();
+        let der = [SECP256K1_DER_PREFIX.as_slice(), key.verifying_key().to_encoded_point(false).as_bytes()].concat();
+        let signature: EcdsaSignature = key.sign(b"message");
+        assert!(verify_signature(&der, b"message", &signature.to_bytes(), &root_key()).is_ok());
+        assert!(verify_signature(&der, b"other", &signature.to_bytes(), &root_key()).is_err());
+
+        let key = P256SigningKey::from_bytes(&[7; 32].into()).unwrap();
+        let der = [P256_DER_PREFIX.as_slice(), key.verifying_key().to_encoded_point(false).as_bytes()].concat();
+        let signature: P256Signature = key.sign(b"message");
+        assert!(verify_signature(&der, b"message", &signature.to_bytes(), &root_key()).is_ok());
+        assert!(verify_signature(&der, b"other", &signature.to_bytes(), &root_key()).is_err());
+
+        assert!(verify_signature(&[0; 44], b"message", &signature.to_bytes(), &root_key()).is_err());
+    }
+
+    #[test]
+    fn canister_signatures_are_verified() {
+        let signer = Principal::from_slice(&[4; 10]);
+        let der = CanisterSigPublicKey::new(signer, b"user".to_vec()).to_der();
+        let signature = canister_sign(signer, b"user", b"message");
+        assert!(verify_signature(&der, b"message", &signature, &root_key()).is_ok());
+        assert!(verify_signature(&der, b"other", &signature, &root_key()).is_err());
+        assert!(verify_signature(&der, b"message", &signature, &crate::IC_ROOT_KEY).is_err());
+
+        // signed for another user of the same canister
+        let other = CanisterSigPublicKey::new(signer, b"other".to_vec()).to_der();
+        assert!(verify_signature(&other, b"message", &signature, &root_key()).is_err());
+    }
+
+    #[test]
+    fn check_ins_signed_with_delegated_keys_are_verified() {
+        assert!(verify_check_in(collection_id(), &delegated_check_in(NOW + 1, None), &root_key(), NOW + 1).is_ok());
+        assert!(verify_check_in(collection_id(), &delegated_check_in(NOW + 1, Some(vec![collection_id()])), &root_key(), NOW + 1).is_ok());
+
+        assert!(verify_check_in(collection_id(), &delegated_check_in(NOW, None), &root_key(), NOW + 1).is_err());
+        assert!(verify_check_in(collection_id(), &delegated_check_in(NOW + 1, Some(vec![Principal::anonymous()])), &root_key(), NOW + 1).is_err());
+
+        // the challenge needs to be signed by the last delegated key
+        let mut unsigned = delegated_check_in(NOW + 1, None);
+        unsigned.signature = Ed25519SigningKey::from_bytes(&[8; 32]).sign(&challenge_message(&unsigned.challenge)).to_bytes().to_vec();
+        assert!(verify_check_in(collection_id(), &unsigned, &root_key(), NOW + 1).is_err());
+        let mut undelegated = delegated_check_in(NOW + 1, None);
+        undelegated.delegations.clear();
+        assert!(verify_check_in(collection_id(), &undelegated, &root_key(), NOW + 1).is_err());
+
+        // chain of delegations between ed25519 keys
+        let mut chained = check_in(NOW, NOW + 10, NOW + 1);
+        chained.delegations = vec![delegate(|x| ed25519_key().0.sign(x).to_bytes().to_vec(), NOW + 1, None); MAX_DELEGATIONS];
+        assert!(verify_check_in(collection_id(), &chained, &root_key(), NOW + 1).is_ok());
+        chained.delegations.push(chained.delegations[0].clone());
+        assert!(verify_check_in(collection_id(), &chained, &root_key(), NOW + 1).is_err());
+    }
+
+    #[test]
+    fn check_ins_are_bound_to_the_validity_of_the_challenge() {
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW + CHALLENGE_TTL, NOW + 1), &root_key(), NOW + 1).is_ok());
+
+        // longer than the validity of the challenges issued by the backend
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW + CHALLENGE_TTL + 1, NOW + 1), &root_key(), NOW + 1).is_err());
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW - 1, NOW), &root_key(), NOW).is_err());
+        // scanned before being issued, after the expiration or in the future
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW + 10, NOW - 1), &root_key(), NOW).is_err());
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW + 10, NOW + 11), &root_key(), NOW + 11).is_err());
+        assert!(verify_check_in(collection_id(), &check_in(NOW, NOW + 10, NOW + 5), &root_key(), NOW + 4).is_err());
+
+        assert!(verify_check_in(Principal::anonymous(), &check_in(NOW, NOW + 10, NOW), &root_key(), NOW).is_err());
+        let mut forged = check_in(NOW, NOW + 10, NOW);
+        forged.challenge.holder = Principal::anonymous();
+        assert!(verify_check_in(collection_id(), &forged, &root_key(), NOW).is_err());
+        let mut tampered = check_in(NOW, NOW + 10, NOW);
+        tampered.challenge.tkn_id = 4;
+        assert!(verify_check_in(collection_id(), &tampered, &root_key(), NOW).is_err());
+    }
+
+    #[test]
+    fn snapshot_hash_matches_the_collection_canister() {
+        // the same snapshot is hashed by `utils::hash_owners_snapshot` of the icrc7 canister in its tests
+        let snapshot = OwnersSnapshot {
+            taken_at: 5,
+            owners: vec![
+                (1, Account { owner: Principal::from_slice(&[1, 2, 3]), subaccount: None }),
+                (2, Account { owner: Principal::anonymous(), subaccount: Some([7; 32]) }),
+            ],
+        };
+        assert_eq!(hex(&hash_owners_snapshot(&snapshot)), "298dcd31fbdf96e8818824f2e6ce55fc7258314c586f9689e0f842cb6f84c81d");
+    }
+
+    #[test]
+    fn certified_snapshots_are_verified() {
+        let certified = certify_snapshot(snapshot(), NOW);
+        assert!(verify_snapshot(collection_id(), &certified, &root_key(), NOW, MAX_SNAPSHOT_AGE).is_ok());
+        assert!(verify_snapshot(collection_id(), &certified, &root_key(), NOW + MAX_SNAPSHOT_AGE + 1, MAX_SNAPSHOT_AGE).is_err());
+        assert!(verify_snapshot(collection_id(), &certified, &crate::IC_ROOT_KEY, NOW, MAX_SNAPSHOT_AGE).is_err());
+        assert!(verify_snapshot(Principal::anonymous(), &certified, &root_key(), NOW, MAX_SNAPSHOT_AGE).is_err());
+
+        let mut tampered = certified.clone();
+        tampered.snapshot.owners.pop();
+        assert!(verify_snapshot(collection_id(), &tampered, &root_key(), NOW, MAX_SNAPSHOT_AGE).is_err());
+        let uncertified = CertifiedOwnersSnapshot { certificate: None, ..certified };
+        assert!(verify_snapshot(collection_id(), &uncertified, &root_key(), NOW, MAX_SNAPSHOT_AGE).is_err());
+    }
+
+    #[test]
+    fn tickets_are_verified_offline() {
+        let certified = certify_snapshot(snapshot(), NOW);
+        assert!(verify_offline(collection_id(), &check_in(NOW, NOW + 10, NOW + 1), &certified, &root_key(), NOW + 1).is_ok());
+
+        // the ticket 1 is owned by another principal
+        let mut other = check_in(NOW, NOW + 10, NOW + 1);
+        other.challenge.tkn_id = 1;
+        other.signature = ed25519_key().0.sign(&challenge_message(&other.challenge)).to_bytes().to_vec();
+        assert!(verify_offline(collection_id(), &other, &certified, &root_key(), NOW + 1).is_err());
+        // the ticket 3 has been checked in before the snapshot
+        let checked_in = certify_snapshot(OwnersSnapshot { owners: snapshot().owners[..1].to_vec(), ..snapshot() }, NOW);
+        assert!(verify_offline(collection_id(), &check_in(NOW, NOW + 10, NOW + 1), &checked_in, &root_key(), NOW + 1).is_err());
+    }
+}